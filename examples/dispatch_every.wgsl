@@ -0,0 +1,27 @@
+// Only dispatch an expensive entrypoint every 30th frame, while the rest of the pipeline runs every frame
+
+#workgroup_count update_palette 1 1 1
+#dispatch_every update_palette 30
+
+#storage palette array<float4, 8>
+
+@compute @workgroup_size(8)
+fn update_palette(@builtin(global_invocation_id) id: uint3) {
+    let t = float(time.frame / 30u);
+    palette[id.x] = float4(.5 + .5 * cos(t + float(id.x) + float3(0.,2.,4.)), 1.);
+}
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Stripes coloured by the palette, which changes twice a second at 60fps
+    let col = palette[id.x * 8u / screen_size.x].rgb;
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(col, 1.));
+}
@@ -0,0 +1,24 @@
+// The number of workgroups for an indirect dispatch is read from `dispatch_indirect[i]` on the GPU,
+// where `i` is the order in which the `#dispatch_indirect` directives appear
+
+#dispatch_indirect draw_band
+
+@compute @workgroup_size(1)
+fn prepare() {
+    // Only cover a band of the screen whose height varies over time
+    let screen_size = uint2(textureDimensions(screen));
+    let height = uint(float(screen_size.y) * (.5 + .5 * sin(time.elapsed)));
+    dispatch_indirect[0] = DispatchIndirect((screen_size.x + 15u) / 16u, (height + 15u) / 16u, 1u);
+}
+
+@compute @workgroup_size(16, 16)
+fn draw_band(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(float(id.x) / float(screen_size.x), .2, .5, 1.));
+}
@@ -0,0 +1,32 @@
+// Dispatch an entrypoint over a 3D domain of 64x64x64 invocations instead of the screen, e.g. for volumetric simulations
+
+#dispatch_size fill_volume 64 64 64
+
+#storage volume array<atomic<u32>, 262144>
+
+@compute @workgroup_size(4, 4, 4)
+fn fill_volume(@builtin(global_invocation_id) id: uint3) {
+    let p = float3(id) / 64. - .5;
+    let density = select(0u, 1u, length(p) < .25 + .1 * sin(time.elapsed));
+    atomicStore(&volume[id.x + 64u * (id.y + 64u * id.z)], density);
+}
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Project the volume along the z axis
+    let xy = id.xy * 64u / screen_size;
+    var sum = 0u;
+    for (var z = 0u; z < 64u; z++) {
+        sum += atomicLoad(&volume[xy.x + 64u * (xy.y + 64u * z)]);
+    }
+    let col = float3(float(sum) / 32.);
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(col, 1.));
+}
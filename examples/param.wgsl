@@ -0,0 +1,21 @@
+// Declare custom uniforms in the shader, so the host can generate sliders for them
+
+#param speed 1.0 range(0, 10) step(0.1)
+#param rings 8 range(1, 32) step(1)
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Concentric rings moving outwards
+    let uv = (float2(id.xy) - .5 * float2(screen_size)) / float(screen_size.y);
+    let d = length(uv) * custom.rings - time.elapsed * custom.speed;
+    let col = float3(.5 + .5 * cos(6.2831853 * d));
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(col, 1.));
+}
@@ -0,0 +1,30 @@
+// `screen_mips` holds a mipmapped copy of the screen, useful for cheap bloom and blur effects.
+// It is generated after the last entry point using `screen`, so that entry point (and any before
+// it) samples the previous frame, while entry points after it sample the current one.
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Pixel coordinates (centre of pixel, origin at bottom left)
+    let fragCoord = float2(float(id.x) + .5, float(screen_size.y - id.y) - .5);
+
+    // Normalised pixel coordinates (from 0 to 1)
+    let uv = fragCoord / float2(screen_size);
+
+    // A small bright disc following the mouse
+    let d = length(float2(id.xy) - float2(mouse.pos));
+    let disc = float3(step(d, 20.));
+
+    // Blur the previous frame by sampling a coarser mip level
+    let lod = 3. + 2. * sin(time.elapsed);
+    let prev = textureSampleLevel(screen_mips, bilinear, float2(id.xy) / float2(screen_size), lod).rgb;
+    let col = max(disc, .98 * prev);
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(col, 1.));
+}
@@ -0,0 +1,28 @@
+// Calling `signalFinished()` tells the host that rendering has converged, so batch renders can stop early
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Pixel coordinates (centre of pixel, origin at bottom left)
+    let fragCoord = float2(float(id.x) + .5, float(screen_size.y - id.y) - .5);
+
+    // Normalised pixel coordinates (from 0 to 1)
+    let uv = fragCoord / float2(screen_size);
+
+    // Fade in over the first 256 frames
+    let t = min(float(time.frame) / 256., 1.);
+    let col = t * pow(.5 + .5 * cos(uv.xyx + float3(0.,2.,4.)), float3(2.2));
+
+    // Done once fully faded in
+    if (time.frame >= 256u && all(id.xy == uint2(0u))) {
+        signalFinished();
+    }
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(col, 1.));
+}
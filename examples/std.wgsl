@@ -0,0 +1,42 @@
+// The bundled standard library: noise, signed distance functions, colour conversions and quaternions
+
+#include "std/noise"
+#include "std/sdf"
+#include "std/colour"
+#include "std/quaternion"
+
+fn map(p: float3) -> float {
+    let q = qRotate(qAxisAngle(float3(1., 1., 0.), time.elapsed), p);
+    let d = opSmoothUnion(sdTorus(q, float2(.6, .2)), sdSphere(p, .5), .2);
+    return d + .05 * fbm3(4. * p, 4);
+}
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Pixel coordinates (centre of pixel, origin at bottom left)
+    let fragCoord = float2(float(id.x) + .5, float(screen_size.y - id.y) - .5);
+
+    // Normalised pixel coordinates (from -1 to 1 vertically)
+    let uv = (2. * fragCoord - float2(screen_size)) / float(screen_size.y);
+
+    // Sphere tracing
+    let ro = float3(0., 0., -3.);
+    let rd = normalize(float3(uv, 1.5));
+    var t = 0.;
+    for (var i = 0; i < 64; i++) {
+        let d = map(ro + t * rd);
+        if (d < 1e-3 || t > 10.) { break; }
+        t += d;
+    }
+    let hue = fract(.1 * t + .05 * time.elapsed);
+    let col = select(float3(0.), srgbToLinear(hsvToRgb(float3(hue, .6, 1. / (1. + t * t * .1)))), t < 10.);
+
+    // Output to screen (linear colour space)
+    textureStore(screen, int2(id.xy), float4(col, 1.));
+}
@@ -0,0 +1,40 @@
+// Storage buffers can hold any type; runtime-sized arrays can be given an element count to size the buffer
+
+#define NUM_PARTICLES 4096
+
+struct Particle {
+    pos: float2,
+    vel: float2,
+}
+
+#storage particles array<Particle> NUM_PARTICLES
+#storage counter atomic<u32>
+
+#dispatch_size move_particles NUM_PARTICLES 1 1
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    // Viewport resolution (in pixels)
+    let screen_size = uint2(textureDimensions(screen));
+
+    // Prevent overdraw for workgroups on the edge of the viewport
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+
+    // Clear the screen before the particles are drawn by the next entrypoint
+    textureStore(screen, int2(id.xy), float4(0., 0., 0., 1.));
+}
+
+@compute @workgroup_size(256)
+fn move_particles(@builtin(global_invocation_id) id: uint3) {
+    let screen_size = float2(textureDimensions(screen));
+    var p = particles[id.x];
+    if (time.frame == 0u) {
+        let h = float2(float(id.x) * .7548776662, float(id.x) * .5698402910);
+        p.pos = fract(h) * screen_size;
+        p.vel = 2. * cos(6.28318530718 * fract(h.yx) + float2(0., 1.5707963268));
+    }
+    p.pos = (p.pos + p.vel + screen_size) % screen_size;
+    particles[id.x] = p;
+    textureStore(screen, int2(p.pos), float4(1.));
+    atomicAdd(&counter, 1u);
+}
@@ -0,0 +1,34 @@
+// Double-buffered storage: each frame reads last frame's cells from `cells_read` and writes
+// `cells_write`, so no invocation sees a neighbour that has already been updated
+
+#storage_double cells array<u32> SCREEN_WIDTH*SCREEN_HEIGHT
+
+fn cell(p: int2, size: int2) -> u32 {
+    let q = (p + size) % size;
+    return cells_read[q.y * size.x + q.x];
+}
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    let size = int2(textureDimensions(screen));
+    let p = int2(id.xy);
+    if (p.x >= size.x || p.y >= size.y) { return; }
+
+    var alive = cell(p, size);
+    if (time.frame == 0u) {
+        // random initial state
+        alive = u32(fract(sin(dot(float2(p), float2(12.9898, 78.233))) * 43758.5453) > .7);
+    } else {
+        var neighbours = 0u;
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                if (x != 0 || y != 0) {
+                    neighbours += cell(p + int2(x, y), size);
+                }
+            }
+        }
+        alive = u32(neighbours == 3u || (alive == 1u && neighbours == 2u));
+    }
+    cells_write[p.y * size.x + p.x] = alive;
+    textureStore(screen, id.xy, float4(float3(float(alive)), 1.));
+}
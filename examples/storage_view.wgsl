@@ -0,0 +1,27 @@
+// A storage buffer can be viewed with another type, e.g. to accumulate with atomics in one
+// entry point and read plainly in another. Browsers reject an entry point using both.
+
+#storage density array<atomic<u32>> SCREEN_WIDTH*SCREEN_HEIGHT
+#storage_view density_values density array<u32>
+
+#define NUM_POINTS 65536
+#dispatch_size splat NUM_POINTS 1 1
+
+@compute @workgroup_size(256)
+fn splat(@builtin(global_invocation_id) id: uint3) {
+    let screen_size = textureDimensions(screen);
+    let t = float(id.x) / float(NUM_POINTS) * 6.28318530718;
+    let r = .4 * float(min(screen_size.x, screen_size.y)) * cos(3. * t + time.elapsed);
+    let pos = uint2(float2(screen_size) / 2. + r * float2(cos(t), sin(t)));
+    atomicAdd(&density[pos.y * screen_size.x + pos.x], 1u);
+}
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    let screen_size = textureDimensions(screen);
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+    let i = id.y * screen_size.x + id.x;
+    let value = 1. - exp(-.1 * float(density_values[i]));
+    density_values[i] = 0u;
+    textureStore(screen, id.xy, float4(float3(value), 1.));
+}
@@ -13,29 +13,260 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 #[cfg(all(feature = "winit", not(target_arch = "wasm32")))]
 mod winit {
-    use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, HttpCacheOptions};
+    use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, ValueEnum};
+    use notify::Watcher;
     use serde::{Deserialize, Serialize};
     use std::error::Error;
-    use wgputoy::context::init_wgpu;
-    use wgputoy::WgpuToyRenderer;
+    use std::path::{Path, PathBuf};
+    use wgputoy::context::{
+        init_wgpu, init_wgpu_headless, list_adapters, AdapterOptions, DeviceProfile, WgpuContext,
+    };
+    use wgputoy::{SourceMap, WgpuToyRenderer};
     use winit::{
-        event::{ElementState, Event, WindowEvent},
+        event::{ElementState, Event, KeyEvent, WindowEvent},
         event_loop::ControlFlow,
+        keyboard::{KeyCode, PhysicalKey},
+        window::Fullscreen,
     };
 
-    #[cfg(not(wasm_platform))]
     use std::time;
-    #[cfg(wasm_platform)]
-    use web_time as time;
 
     const POLL_SLEEP_TIME: time::Duration = time::Duration::from_millis(100);
+    /// How often the window title's statistics are updated
+    const TITLE_INTERVAL: time::Duration = time::Duration::from_millis(500);
+    /// The time a frame advances by when single-stepping
+    const STEP_TIME: f32 = 1. / 60.;
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     enum Mode {
         Poll,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    /// Run a compute shader in a window.
+    #[derive(Parser, Debug)]
+    #[command(version, about)]
+    struct Args {
+        /// Read defaults from this TOML file, rather than `wgputoy.toml` if it exists
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+        /// The shader to run, in WGSL, or Shadertoy GLSL if it ends in `.glsl`, or SPIR-V if it
+        /// ends in `.spv`. Uniforms and textures are read from `{shader}.json` if it exists
+        #[arg(long, default_value = "examples/default.wgsl")]
+        shader: String,
+        /// Download a shader from compute.toys and run it, given its ID or the URL of its page
+        #[arg(long, value_name = "ID_OR_URL", conflicts_with = "shader")]
+        remote: Option<String>,
+        /// Play each shader in a directory in turn, in order of their names, looping forever
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["shader", "remote"])]
+        playlist: Option<PathBuf>,
+        /// How long each shader of `--playlist` plays for, e.g. `30s` or `2m`
+        #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = parse_duration)]
+        cycle: time::Duration,
+        /// Where `--remote` fetches shaders from, as `{api}/{id}`
+        #[arg(
+            long,
+            value_name = "URL",
+            default_value = "https://compute.toys/api/shader"
+        )]
+        remote_api: String,
+        /// Width of the window, in physical pixels
+        #[arg(long, default_value_t = 1280)]
+        width: u32,
+        /// Height of the window, in physical pixels
+        #[arg(long, default_value_t = 720)]
+        height: u32,
+        /// Wait for vertical blank before presenting each frame
+        #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+        vsync: bool,
+        /// The graphics API to render with, rather than the platform's preferred one
+        #[arg(long, value_enum)]
+        backend: Option<Backend>,
+        /// The GPU to render with, by its index in `--list-adapters` or part of its name
+        #[arg(long, value_name = "INDEX_OR_NAME")]
+        adapter: Option<String>,
+        /// The kind of GPU to prefer where there are several [default: high]
+        #[arg(long, value_enum)]
+        power_preference: Option<PowerPreference>,
+        /// The features and limits to request of the GPU, to emulate a weaker device and check
+        /// that a shader works on one
+        #[arg(long, value_enum, default_value_t = Profile::Full)]
+        profile: Profile,
+        /// List the GPUs available with `--backend`, then exit
+        #[arg(long)]
+        list_adapters: bool,
+        /// Fill the current monitor with a borderless window
+        #[arg(long)]
+        fullscreen: bool,
+        /// Render at most this many frames per second, rather than pausing 100ms after each frame
+        #[arg(long, value_name = "FPS")]
+        fps_cap: Option<f32>,
+        /// Save a screenshot after rendering N frames, or once the shader calls
+        /// `signalFinished()`, then exit
+        #[arg(long, value_name = "N")]
+        screenshot_after: Option<u32>,
+        /// Where to save the screenshot taken by `--screenshot-after`
+        #[arg(long, value_name = "PATH", default_value = "screenshot.png")]
+        screenshot_path: String,
+        /// Render frames offscreen at a fixed timestep and write them to `--out`, rather than
+        /// opening a window
+        #[arg(long, requires = "out")]
+        headless: bool,
+        /// The number of frames to render with `--headless`, stopping early if the shader calls
+        /// `signalFinished()`
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        frames: u32,
+        /// The frame rate of the animation rendered with `--headless`, which sets its timestep
+        #[arg(long, value_name = "FPS", default_value_t = 60.)]
+        frame_rate: f32,
+        /// The directory to write the frames rendered with `--headless` to, as
+        /// `frame_00000.png` onwards
+        #[arg(long, value_name = "DIR")]
+        out: Option<PathBuf>,
+        /// Render FRAMES frames offscreen as fast as possible, at the timestep of `--frame-rate`,
+        /// then print the time each took and the GPU time of each entry point as JSON. Stops early
+        /// if the shader calls `signalFinished()`
+        #[arg(
+            long,
+            value_name = "FRAMES",
+            num_args = 0..=1,
+            default_missing_value = "1000",
+            conflicts_with = "headless"
+        )]
+        bench: Option<u32>,
+        /// Capture audio from the default input device, or the first whose name contains DEVICE,
+        /// into `--audio-channel` for audio-reactive shaders
+        #[arg(long, value_name = "DEVICE", num_args = 0..=1, default_missing_value = "")]
+        audio_input: Option<String>,
+        /// The channel `--audio-input` writes the spectrum and waveform to
+        #[arg(long, value_name = "INDEX", default_value_t = 0)]
+        audio_channel: usize,
+    }
+
+    /// Defaults for the command line options, and settings only found here.
+    #[derive(Deserialize, Debug, Default)]
+    #[serde(default, deny_unknown_fields)]
+    struct Config {
+        width: Option<u32>,
+        height: Option<u32>,
+        vsync: Option<bool>,
+        backend: Option<Backend>,
+        adapter: Option<String>,
+        power_preference: Option<PowerPreference>,
+        /// The directory textures fetched from URLs are cached in
+        texture_cache: Option<PathBuf>,
+        keys: Keys,
+    }
+
+    #[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+    #[serde(rename_all = "lowercase")]
+    enum Backend {
+        Vulkan,
+        Metal,
+        Dx12,
+        /// OpenGL, or OpenGL ES
+        Gl,
+    }
+
+    #[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+    #[serde(rename_all = "lowercase")]
+    enum PowerPreference {
+        /// The most powerful GPU, e.g. a discrete one
+        High,
+        /// The GPU using the least power, e.g. an integrated one
+        Low,
+        /// Whichever GPU the system chooses
+        None,
+    }
+
+    #[derive(ValueEnum, Debug, Clone, Copy)]
+    enum Profile {
+        /// Every feature the GPU supports, with the limits every WebGPU implementation supports
+        Full,
+        /// No optional features, e.g. `f16` or filtering float32 textures
+        Baseline,
+        /// No optional features, and the lower limits of devices only supporting GLES 3.1 or
+        /// D3D11, e.g. 2048x2048 textures
+        Downlevel,
+    }
+
+    fn adapter_options(args: &Args) -> AdapterOptions {
+        AdapterOptions {
+            backends: args.backend.map(|backend| match backend {
+                Backend::Vulkan => wgpu::Backends::VULKAN,
+                Backend::Metal => wgpu::Backends::METAL,
+                Backend::Dx12 => wgpu::Backends::DX12,
+                Backend::Gl => wgpu::Backends::GL,
+            }),
+            adapter: args.adapter.clone(),
+            power_preference: args.power_preference.map(|preference| match preference {
+                PowerPreference::High => wgpu::PowerPreference::HighPerformance,
+                PowerPreference::Low => wgpu::PowerPreference::LowPower,
+                PowerPreference::None => wgpu::PowerPreference::None,
+            }),
+        }
+    }
+
+    /// The keys of the player's built-in actions, named as in `winit::keyboard::KeyCode`,
+    /// e.g. `"KeyP"` or `"F5"`.
+    #[derive(Deserialize, Debug, Clone, Copy)]
+    #[serde(default, deny_unknown_fields)]
+    struct Keys {
+        pause: KeyCode,
+        step: KeyCode,
+        reset: KeyCode,
+        screenshot: KeyCode,
+        /// Show or hide the panel, with the `egui` feature
+        panel: KeyCode,
+    }
+
+    impl Default for Keys {
+        fn default() -> Self {
+            Keys {
+                pause: KeyCode::Space,
+                step: KeyCode::Period,
+                reset: KeyCode::KeyR,
+                screenshot: KeyCode::F12,
+                panel: KeyCode::F1,
+            }
+        }
+    }
+
+    /// Parse the command line, taking options that aren't given from the config file.
+    fn parse_args() -> Result<(Args, Config), Box<dyn Error>> {
+        let matches = Args::command().get_matches();
+        let mut args = Args::from_arg_matches(&matches)?;
+        let config = match &args.config {
+            Some(path) => Some(path.as_path()),
+            None => Some(Path::new("wgputoy.toml")).filter(|path| path.exists()),
+        };
+        let Some(path) = config else {
+            return Ok((args, Config::default()));
+        };
+        let config: Config = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|toml| toml::from_str(&toml).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let is_default = |id| matches.value_source(id) == Some(ValueSource::DefaultValue);
+        if is_default("width") {
+            args.width = config.width.unwrap_or(args.width);
+        }
+        if is_default("height") {
+            args.height = config.height.unwrap_or(args.height);
+        }
+        if is_default("vsync") {
+            args.vsync = config.vsync.unwrap_or(args.vsync);
+        }
+        args.backend = args.backend.or(config.backend);
+        args.adapter = args.adapter.take().or(config.adapter.clone());
+        args.power_preference = args.power_preference.or(config.power_preference);
+        #[cfg(feature = "fetch")]
+        if let Some(dir) = &config.texture_cache {
+            wgputoy::set_cache_dir(dir.clone());
+        }
+        Ok((args, config))
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Default)]
     #[serde(rename_all = "camelCase")]
     struct ShaderMeta {
         uniforms: Vec<Uniform>,
@@ -55,68 +286,562 @@ mod winit {
         img: String,
     }
 
-    async fn init() -> Result<WgpuToyRenderer, Box<dyn Error>> {
-        let wgpu = init_wgpu(1280, 720, "").await?;
+    /// A shader as stored by compute.toys, whose body is its source along with the metadata
+    /// otherwise read from `{shader}.json`.
+    #[cfg(feature = "fetch")]
+    #[derive(Deserialize, Debug)]
+    struct RemoteShader {
+        name: Option<String>,
+        body: serde_json::Value,
+    }
+
+    #[cfg(feature = "fetch")]
+    #[derive(Deserialize, Debug)]
+    struct RemoteBody {
+        code: String,
+        #[serde(flatten)]
+        meta: ShaderMeta,
+    }
+
+    /// Download a shader from compute.toys into a temporary directory, along with its metadata,
+    /// returning the path of the shader. Its textures are fetched by `init` as usual.
+    #[cfg(feature = "fetch")]
+    async fn fetch_remote(id_or_url: &str, api: &str) -> Result<String, Box<dyn Error>> {
+        let id = match id_or_url.split_once("/view/") {
+            Some((_, rest)) => rest.split(['/', '?', '#']).next().unwrap_or_default(),
+            None => id_or_url,
+        };
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("{id_or_url} is not a shader ID or page").into());
+        }
+        let url = format!("{}/{id}", api.trim_end_matches('/'));
+        let json = reqwest::get(&url).await?.error_for_status()?.text().await?;
+        let shader: RemoteShader = serde_json::from_str(&json)?;
+        // the site stores the body, and the code within it, as JSON encoded strings
+        let body: RemoteBody = match shader.body {
+            serde_json::Value::String(body) => serde_json::from_str(&body)?,
+            body => serde_json::from_value(body)?,
+        };
+        let code = serde_json::from_str(&body.code).unwrap_or(body.code);
+        let dir = std::env::temp_dir().join("wgputoy");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{id}.wgsl"));
+        std::fs::write(&path, code)?;
+        std::fs::write(
+            dir.join(format!("{id}.wgsl.json")),
+            serde_json::to_string_pretty(&body.meta)?,
+        )?;
+        let path = path.to_string_lossy().into_owned();
+        println!(
+            "Downloaded {} to {path}",
+            shader.name.as_deref().unwrap_or(id)
+        );
+        Ok(path)
+    }
+
+    #[cfg(not(feature = "fetch"))]
+    async fn fetch_remote(_id_or_url: &str, _api: &str) -> Result<String, Box<dyn Error>> {
+        Err("--remote requires the fetch feature".into())
+    }
+
+    /// Parse a number of seconds, optionally followed by `s`, or a number of minutes followed by `m`.
+    fn parse_duration(duration: &str) -> Result<time::Duration, String> {
+        let (number, unit) = match duration.strip_suffix('m') {
+            Some(minutes) => (minutes, 60.),
+            None => (duration.strip_suffix('s').unwrap_or(duration), 1.),
+        };
+        match number.parse::<f32>() {
+            Ok(n) if n.is_finite() && n > 0. => Ok(time::Duration::from_secs_f32(n * unit)),
+            _ => Err(format!("{duration} is not a duration such as 30s or 2m")),
+        }
+    }
+
+    /// The shaders in a directory which `load_shader` can run, in order of their names.
+    fn playlist(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut shaders = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("wgsl" | "glsl" | "spv")
+            ) {
+                shaders.push(path.to_string_lossy().into_owned());
+            }
+        }
+        shaders.sort();
+        if shaders.is_empty() {
+            return Err(format!("{} contains no shaders", dir.display()).into());
+        }
+        Ok(shaders)
+    }
+
+    /// The shader's name with the frame rate and, where the device supports timestamp
+    /// queries, the GPU time of each entry point.
+    fn window_title(wgputoy: &WgpuToyRenderer, shader: &str) -> String {
+        let name = Path::new(shader)
+            .file_name()
+            .map_or(shader.into(), |name| name.to_string_lossy());
+        if wgputoy.is_paused() {
+            return format!("{name} - paused");
+        }
+        let mut title = format!("{name} - {:.0} fps", wgputoy.get_frame_stats().fps);
+        let timings = wgputoy.get_pass_timings();
+        for (name, ms) in timings.names().iter().zip(timings.ms()) {
+            title += &format!(" - {name} {ms:.2} ms");
+        }
+        title
+    }
+
+    /// The `KeyboardEvent.keyCode` of a key, which the website passes to `set_keydown`.
+    fn key_code(code: KeyCode) -> Option<usize> {
+        use KeyCode::*;
+        const LETTERS: [KeyCode; 26] = [
+            KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN,
+            KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+        ];
+        const DIGITS: [KeyCode; 10] = [
+            Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+        ];
+        const NUMPAD: [KeyCode; 10] = [
+            Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8,
+            Numpad9,
+        ];
+        const FUNCTION: [KeyCode; 12] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+        for (keys, first) in [
+            (&LETTERS[..], 65),
+            (&DIGITS[..], 48),
+            (&NUMPAD[..], 96),
+            (&FUNCTION[..], 112),
+        ] {
+            if let Some(i) = keys.iter().position(|&key| key == code) {
+                return Some(first + i);
+            }
+        }
+        Some(match code {
+            Backspace => 8,
+            Tab => 9,
+            Enter | NumpadEnter => 13,
+            ShiftLeft | ShiftRight => 16,
+            ControlLeft | ControlRight => 17,
+            AltLeft | AltRight => 18,
+            Escape => 27,
+            Space => 32,
+            PageUp => 33,
+            PageDown => 34,
+            End => 35,
+            Home => 36,
+            ArrowLeft => 37,
+            ArrowUp => 38,
+            ArrowRight => 39,
+            ArrowDown => 40,
+            Insert => 45,
+            Delete => 46,
+            Semicolon => 186,
+            Equal => 187,
+            Comma => 188,
+            Minus => 189,
+            Period => 190,
+            Slash => 191,
+            Backquote => 192,
+            BracketLeft => 219,
+            Backslash => 220,
+            BracketRight => 221,
+            Quote => 222,
+            _ => return None,
+        })
+    }
+
+    fn save_screenshot(runtime: &tokio::runtime::Runtime, wgputoy: &WgpuToyRenderer, path: &str) {
+        let png = runtime.block_on(wgputoy.screenshot_async());
+        match png.and_then(|png| std::fs::write(path, png).map_err(|e| e.to_string())) {
+            Ok(()) => println!("Saved screenshot to {path}"),
+            Err(e) => eprintln!("Unable to save screenshot: {e}"),
+        }
+    }
+
+    /// Resize the surface and compute resolution to the window's physical size, unless it is
+    /// minimised or already that size, since resizing restarts the toy.
+    fn fit_to_window(wgputoy: &mut WgpuToyRenderer, size: winit::dpi::PhysicalSize<u32>) {
+        let config = &wgputoy.wgpu.surface_config;
+        let scale = wgputoy.wgpu.window.as_ref().unwrap().scale_factor() as f32;
+        if size.width == 0
+            || size.height == 0
+            || ((config.width, config.height) == size.into() && wgputoy.pixel_ratio() == scale)
+        {
+            return;
+        }
+        wgputoy.resize_physical(size.width, size.height, scale);
+    }
+
+    fn device_profile(args: &Args) -> DeviceProfile {
+        match args.profile {
+            Profile::Full => DeviceProfile::Full,
+            Profile::Baseline => DeviceProfile::Baseline,
+            Profile::Downlevel => DeviceProfile::Downlevel,
+        }
+    }
+
+    async fn init(args: &Args, wgpu: WgpuContext) -> Result<WgpuToyRenderer, Box<dyn Error>> {
         let mut wgputoy = WgpuToyRenderer::new(wgpu);
+        load_metadata(&mut wgputoy, &args.shader).await?;
+        Ok(wgputoy)
+    }
+
+    /// Load the textures and uniforms of a shader from `{filename}.json`, if it exists,
+    /// replacing those of any previous shader.
+    async fn load_metadata(
+        wgputoy: &mut WgpuToyRenderer,
+        filename: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let metadata = match std::fs::read_to_string(std::format!("{filename}.json")) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(_) => ShaderMeta::default(),
+        };
+        println!("{:?}", metadata);
 
-        let filename = if std::env::args().len() > 1 {
-            std::env::args().nth(1).unwrap()
+        for (i, texture) in metadata.textures.iter().enumerate() {
+            let url = if texture.img.starts_with("http") {
+                texture.img.clone()
+            } else {
+                std::format!("https://compute.toys/{}", texture.img)
+            };
+            wgputoy.load_channel_url(i, &url).await?;
+        }
+        // blank the channels the shader doesn't use
+        for i in metadata.textures.len().. {
+            if wgputoy.clear_channel(i).is_err() {
+                break;
+            }
+        }
+
+        let uniform_names: Vec<String> = metadata.uniforms.iter().map(|u| u.name.clone()).collect();
+        let uniform_values: Vec<f32> = metadata.uniforms.iter().map(|u| u.value).collect();
+        if !uniform_names.is_empty() {
+            wgputoy.set_custom_floats(uniform_names, uniform_values);
+        }
+
+        wgputoy.set_pass_f32(metadata.float32_enabled);
+        Ok(())
+    }
+
+    /// A shader which has been read and preprocessed, ready to compile.
+    enum Shader {
+        Wgsl(Box<SourceMap>),
+        #[cfg(feature = "spirv")]
+        Spirv(Vec<u8>),
+    }
+
+    /// Read and preprocess a shader, returning it along with the files it was read from, which
+    /// are the shader itself and any local includes. The shader is `None` if preprocessing
+    /// failed, which has been reported.
+    async fn prepare_shader(
+        wgputoy: &WgpuToyRenderer,
+        filename: &str,
+    ) -> Result<(Option<Shader>, Vec<PathBuf>), Box<dyn Error>> {
+        let mut files = vec![std::fs::canonicalize(filename)?];
+        #[cfg(feature = "spirv")]
+        if filename.ends_with(".spv") {
+            return Ok((Some(Shader::Spirv(std::fs::read(filename)?)), files));
+        }
+        let shader = std::fs::read_to_string(filename)?;
+        #[cfg(feature = "glsl")]
+        let shader = if filename.ends_with(".glsl") {
+            wgputoy::shadertoy_to_wgsl(&shader).ok_or("failed to translate GLSL")?
         } else {
-            "examples/default.wgsl".to_string()
+            shader
         };
-        let shader = std::fs::read_to_string(&filename)?;
-
-        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
-            .with(Cache(HttpCache {
-                mode: CacheMode::Default,
-                manager: CACacheManager::default(),
-                options: HttpCacheOptions::default(),
-            }))
-            .build();
-
-        if let Ok(json) = std::fs::read_to_string(std::format!("{filename}.json")) {
-            let metadata: ShaderMeta = serde_json::from_str(&json)?;
-            println!("{:?}", metadata);
-
-            for (i, texture) in metadata.textures.iter().enumerate() {
-                let url = if texture.img.starts_with("http") {
-                    texture.img.clone()
-                } else {
-                    std::format!("https://compute.toys/{}", texture.img)
-                };
-                let resp = client.get(&url).send().await?;
-                let img = resp.bytes().await?.to_vec();
-                if texture.img.ends_with(".hdr") {
-                    wgputoy.load_channel_hdr(i, &img)?;
-                } else {
-                    wgputoy.load_channel(i, &img);
+        let source = wgputoy.preprocess_async(&shader).await;
+        if let Some(source) = &source {
+            println!("{}", source.source);
+            // includes the renderer reads from `./include` rather than fetching
+            for (name, _) in source.include_map.iter().flatten() {
+                let path = Path::new("include").join(format!("{name}.wgsl"));
+                if let Ok(path) = std::fs::canonicalize(path) {
+                    if !files.contains(&path) {
+                        files.push(path);
+                    }
                 }
             }
+        }
+        Ok((source.map(|source| Shader::Wgsl(Box::new(source))), files))
+    }
+
+    fn compile_shader(wgputoy: &mut WgpuToyRenderer, shader: Shader) {
+        match shader {
+            Shader::Wgsl(source) => wgputoy.compile(*source),
+            #[cfg(feature = "spirv")]
+            Shader::Spirv(spirv) => wgputoy.set_shader_spirv(&spirv),
+        }
+    }
+
+    /// Load and compile a shader, returning the files it was read from, which are the shader
+    /// itself and any local includes.
+    async fn load_shader(
+        wgputoy: &mut WgpuToyRenderer,
+        filename: &str,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let (shader, files) = prepare_shader(wgputoy, filename).await?;
+        if let Some(shader) = shader {
+            compile_shader(wgputoy, shader);
+        }
+        Ok(files)
+    }
 
-            let uniform_names: Vec<String> =
-                metadata.uniforms.iter().map(|u| u.name.clone()).collect();
-            let uniform_values: Vec<f32> = metadata.uniforms.iter().map(|u| u.value).collect();
-            if !uniform_names.is_empty() {
-                wgputoy.set_custom_floats(uniform_names, uniform_values);
+    /// Watch the directories of the given files, rather than the files themselves, as editors
+    /// often save by replacing the file.
+    fn watch(watcher: &mut notify::RecommendedWatcher, files: &[PathBuf]) -> notify::Result<()> {
+        let mut dirs: Vec<&Path> = files.iter().filter_map(|f| f.parent()).collect();
+        dirs.dedup();
+        for dir in dirs {
+            watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+        }
+        Ok(())
+    }
+
+    /// Render `args.frames` frames at a fixed timestep, writing each to `args.out` as a PNG,
+    /// until the shader signals that it has finished.
+    async fn render_headless(args: &Args) -> Result<(), Box<dyn Error>> {
+        env_logger::init_from_env(
+            env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+        );
+        let out = args.out.as_ref().ok_or("--headless requires --out")?;
+        std::fs::create_dir_all(out)?;
+        let wgpu = init_wgpu_headless(
+            args.width,
+            args.height,
+            device_profile(args),
+            adapter_options(args),
+        )
+        .await?;
+        let mut wgputoy = init(args, wgpu).await?;
+        load_shader(&mut wgputoy, &args.shader).await?;
+        let device = wgputoy.wgpu.device.clone();
+        std::thread::spawn(move || loop {
+            device.poll(wgpu::Maintain::Wait);
+        });
+        let dt = 1. / args.frame_rate;
+        wgputoy.set_time_delta(dt);
+        let mut rendered = 0;
+        while rendered < args.frames {
+            wgputoy.set_time_elapsed(rendered as f32 * dt);
+            wgputoy.render_async().await;
+            let png = wgputoy.screenshot_async().await?;
+            std::fs::write(out.join(format!("frame_{rendered:05}.png")), png)?;
+            rendered += 1;
+            if wgputoy.is_finished() {
+                break;
             }
+        }
+        println!("Rendered {rendered} frames to {}", out.display());
+        Ok(())
+    }
+
+    /// The frames rendered by `--bench` before it starts timing, while pipelines are created
+    /// and caches warm up
+    const BENCH_WARM_UP: u32 = 10;
+
+    /// The times measured by `--bench`, in milliseconds.
+    #[derive(Serialize)]
+    struct BenchReport {
+        shader: String,
+        adapter: String,
+        width: u32,
+        height: u32,
+        frames: u32,
+        /// From the start of each frame until the GPU finished it
+        frame_ms: Summary,
+        /// The GPU time of each entry point, if the GPU supports timestamp queries
+        passes: Vec<PassSummary>,
+    }
 
-            wgputoy.set_pass_f32(metadata.float32_enabled);
+    #[derive(Serialize)]
+    struct Summary {
+        min: f32,
+        avg: f32,
+        p99: f32,
+    }
+
+    impl Summary {
+        fn new(mut times: Vec<f32>) -> Self {
+            times.sort_by(f32::total_cmp);
+            let p99 = (times.len() as f32 * 0.99).ceil() as usize;
+            Summary {
+                min: times.first().copied().unwrap_or(0.),
+                avg: times.iter().sum::<f32>() / times.len().max(1) as f32,
+                p99: times
+                    .get(p99.saturating_sub(1).min(times.len().saturating_sub(1)))
+                    .copied()
+                    .unwrap_or(0.),
+            }
         }
+    }
 
-        if let Some(source) = wgputoy.preprocess_async(&shader).await {
-            println!("{}", source.source);
-            wgputoy.compile(source);
+    #[derive(Serialize)]
+    struct PassSummary {
+        name: String,
+        #[serde(flatten)]
+        ms: Summary,
+    }
+
+    async fn bench(args: &Args, frames: u32) -> Result<(), Box<dyn Error>> {
+        let wgpu = init_wgpu_headless(
+            args.width,
+            args.height,
+            device_profile(args),
+            adapter_options(args),
+        )
+        .await?;
+        let mut wgputoy = init(args, wgpu).await?;
+        load_shader(&mut wgputoy, &args.shader).await?;
+        wgputoy.set_profile_every_frame(true);
+        let device = wgputoy.wgpu.device.clone();
+        std::thread::spawn(move || loop {
+            device.poll(wgpu::Maintain::Wait);
+        });
+        let dt = 1. / args.frame_rate;
+        wgputoy.set_time_delta(dt);
+        let mut frame_ms = vec![];
+        let mut pass_ms: Vec<(String, Vec<f32>)> = vec![];
+        for frame in 0..BENCH_WARM_UP + frames {
+            let start = time::Instant::now();
+            wgputoy.set_time_elapsed(frame as f32 * dt);
+            wgputoy.render_async().await;
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            wgputoy.wgpu.queue.on_submitted_work_done(move || {
+                let _ = tx.send(());
+            });
+            rx.await?;
+            if frame >= BENCH_WARM_UP {
+                frame_ms.push(start.elapsed().as_secs_f32() * 1e3);
+                let timings = wgputoy.get_pass_timings();
+                for (name, ms) in timings.names().into_iter().zip(timings.ms()) {
+                    match pass_ms.iter_mut().find(|(n, _)| *n == name) {
+                        Some((_, times)) => times.push(ms),
+                        None => pass_ms.push((name, vec![ms])),
+                    }
+                }
+            }
+            if wgputoy.is_finished() {
+                break;
+            }
         }
-        Ok(wgputoy)
+        let report = BenchReport {
+            shader: args.shader.clone(),
+            adapter: wgputoy.wgpu.adapter_info.name.clone(),
+            width: args.width,
+            height: args.height,
+            frames: frame_ms.len() as u32,
+            frame_ms: Summary::new(frame_ms),
+            passes: pass_ms
+                .into_iter()
+                .map(|(name, times)| PassSummary {
+                    name,
+                    ms: Summary::new(times),
+                })
+                .collect(),
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        Ok(())
     }
 
     pub fn main() -> Result<(), Box<dyn Error>> {
+        let (mut args, config) = parse_args()?;
+        let keys = config.keys;
+        if args.list_adapters {
+            let adapters = list_adapters(adapter_options(&args).backends);
+            if adapters.is_empty() {
+                println!("No adapters found");
+            }
+            for (index, info) in adapters.iter().enumerate() {
+                println!(
+                    "{index}: {} ({:?}, {:?})",
+                    info.name, info.backend, info.device_type
+                );
+            }
+            return Ok(());
+        }
+        if args.fps_cap.is_some_and(|fps| fps.is_nan() || fps <= 0.) {
+            return Err("--fps-cap must be positive".into());
+        }
+        if args.frame_rate.is_nan() || args.frame_rate <= 0. {
+            return Err("--frame-rate must be positive".into());
+        }
+        #[cfg(not(feature = "audio"))]
+        if args.audio_input.is_some() {
+            return Err("--audio-input requires the audio feature".into());
+        }
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()?;
-        let mut wgputoy = runtime.block_on(init())?;
-        let screen_size = wgputoy.wgpu.window.inner_size();
-        let start_time = std::time::Instant::now();
+        if let Some(remote) = &args.remote {
+            args.shader = runtime.block_on(fetch_remote(remote, &args.remote_api))?;
+        }
+        let playlist = match &args.playlist {
+            Some(dir) => playlist(dir)?,
+            None => vec![],
+        };
+        if let Some(first) = playlist.first() {
+            args.shader = first.clone();
+        }
+        if args.headless {
+            return runtime.block_on(render_headless(&args));
+        }
+        if let Some(frames) = args.bench {
+            return runtime.block_on(bench(&args, frames));
+        }
+        let wgpu = runtime.block_on(init_wgpu(
+            args.width,
+            args.height,
+            "",
+            device_profile(&args),
+            adapter_options(&args),
+        ))?;
+        if args.fullscreen {
+            if let Some(window) = &wgpu.window {
+                window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+            }
+        }
+        let mut wgputoy = runtime.block_on(init(&args, wgpu))?;
+        if !args.vsync {
+            use wgputoy::context::PresentMode;
+            if let Err(e) = wgputoy
+                .set_present_mode(PresentMode::Mailbox)
+                .or_else(|_| wgputoy.set_present_mode(PresentMode::Immediate))
+            {
+                log::warn!("Unable to disable vsync: {e}");
+            }
+        }
+        let mut files = runtime.block_on(load_shader(&mut wgputoy, &args.shader))?;
+        #[cfg(feature = "audio")]
+        let mut audio = match &args.audio_input {
+            Some(_) if wgputoy.channel_info(args.audio_channel).is_none() => {
+                return Err(format!("Channel {} does not exist", args.audio_channel).into());
+            }
+            Some(device) => Some(crate::audio::AudioInput::new(device)?),
+            None => None,
+        };
+        // reload the shader when it or its includes are saved
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watch(&mut watcher, &files)?;
+        let mut screen_size = wgputoy.wgpu.window.as_ref().unwrap().inner_size();
+        // the window may not be the requested size, e.g. when fullscreen
+        fit_to_window(&mut wgputoy, screen_size);
+        println!(
+            "{:?}: pause, {:?}: step a frame while paused, {:?}: reset, {:?}: screenshot",
+            keys.pause, keys.step, keys.reset, keys.screenshot
+        );
+        // the shader's time, which doesn't advance while paused
+        let mut elapsed = 0.;
+        let mut last_tick = time::Instant::now();
+        // the position in the playlist, and the next shader once it has been prepared
+        let mut playing = 0;
+        let mut next = None;
+        let mut switch_at = time::Instant::now() + args.cycle;
         let event_loop = std::mem::take(&mut wgputoy.wgpu.event_loop).unwrap();
         let device_clone = wgputoy.wgpu.device.clone();
         std::thread::spawn(move || loop {
@@ -125,8 +850,20 @@ mod winit {
 
         let mode = Mode::Poll;
         let mut close_requested = false;
+        let frame_interval = args
+            .fps_cap
+            .map(|fps| time::Duration::from_secs_f32(1. / fps));
+        let mut last_frame = time::Instant::now();
+        let mut frames_rendered = 0;
+        let mut last_title = time::Instant::now();
+
+        #[cfg(feature = "egui")]
+        let mut overlay = crate::overlay::Overlay::new(&wgputoy);
 
         let _ = event_loop.run(move |event, elwt| match event {
+            #[cfg(feature = "egui")]
+            Event::WindowEvent { ref event, .. }
+                if overlay.on_window_event(wgputoy.wgpu.window.as_ref().unwrap(), event) => {}
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => {
                     close_requested = true;
@@ -140,29 +877,166 @@ mod winit {
                 WindowEvent::MouseInput { state, .. } => {
                     wgputoy.set_mouse_click(state == ElementState::Pressed);
                 }
-                WindowEvent::Resized(size) => {
-                    if size.width != 0 && size.height != 0 {
-                        wgputoy.resize(size.width, size.height, 1.);
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(code),
+                            state,
+                            repeat,
+                            ..
+                        },
+                    ..
+                } => {
+                    let pressed = state == ElementState::Pressed;
+                    if let Some(keycode) = key_code(code) {
+                        wgputoy.set_keydown(keycode, pressed);
                     }
+                    if pressed && !repeat {
+                        match code {
+                            _ if code == keys.pause => {
+                                if wgputoy.is_paused() {
+                                    wgputoy.resume();
+                                } else {
+                                    wgputoy.pause();
+                                }
+                            }
+                            #[cfg(feature = "egui")]
+                            _ if code == keys.panel => overlay.visible = !overlay.visible,
+                            _ if code == keys.step && wgputoy.is_paused() => {
+                                elapsed += STEP_TIME;
+                                wgputoy.step_frame();
+                            }
+                            _ if code == keys.reset => {
+                                elapsed = 0.;
+                                wgputoy.reset();
+                            }
+                            _ if code == keys.screenshot => {
+                                let secs = time::SystemTime::now()
+                                    .duration_since(time::UNIX_EPOCH)
+                                    .map_or(0, |d| d.as_secs());
+                                save_screenshot(
+                                    &runtime,
+                                    &wgputoy,
+                                    &format!("screenshot-{secs}.png"),
+                                );
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                WindowEvent::Resized(size) => {
+                    screen_size = size;
+                    fit_to_window(&mut wgputoy, size);
+                }
+                WindowEvent::ScaleFactorChanged { .. } => {
+                    // not all platforms follow this with a `Resized` event
+                    screen_size = wgputoy.wgpu.window.as_ref().unwrap().inner_size();
+                    fit_to_window(&mut wgputoy, screen_size);
                 }
                 WindowEvent::RedrawRequested => {
-                    let time = start_time.elapsed().as_micros() as f32 * 1e-6;
-                    wgputoy.set_time_elapsed(time);
-                    let future = wgputoy.render_async();
-                    runtime.block_on(future);
+                    last_frame = time::Instant::now();
+                    let tick = last_frame - last_tick;
+                    last_tick = last_frame;
+                    if !wgputoy.is_paused() {
+                        elapsed += tick.as_secs_f32();
+                    }
+                    wgputoy.set_time_elapsed(elapsed);
+                    #[cfg(feature = "audio")]
+                    if let Some(audio) = &mut audio {
+                        if let Err(e) = audio.update(&mut wgputoy, args.audio_channel) {
+                            log::error!("Audio input: {e}");
+                        }
+                    }
+                    #[cfg(feature = "egui")]
+                    {
+                        overlay.update(&mut wgputoy);
+                        let future =
+                            wgputoy.render_overlaid_async(|wgpu, view| overlay.paint(wgpu, view));
+                        runtime.block_on(future);
+                    }
+                    #[cfg(not(feature = "egui"))]
+                    runtime.block_on(wgputoy.render_async());
+                    frames_rendered += 1;
+                    if args.screenshot_after == Some(frames_rendered)
+                        || (args.screenshot_after.is_some() && wgputoy.is_finished())
+                    {
+                        save_screenshot(&runtime, &wgputoy, &args.screenshot_path);
+                        elwt.exit();
+                    }
                 }
                 _ => (),
             },
             Event::AboutToWait => {
-                wgputoy.wgpu.window.request_redraw();
+                let changed = rx.try_iter().any(|event| {
+                    event.is_ok_and(|event| {
+                        matches!(
+                            event.kind,
+                            notify::EventKind::Create(_) | notify::EventKind::Modify(_)
+                        ) && event.paths.iter().any(|path| files.contains(path))
+                    })
+                });
+                if changed {
+                    // errors are logged, and the last shader that compiled keeps running
+                    match runtime.block_on(load_shader(&mut wgputoy, &args.shader)) {
+                        Ok(reloaded) => {
+                            files = reloaded;
+                            if let Err(e) = watch(&mut watcher, &files) {
+                                eprintln!("Unable to watch the shader's includes: {e}");
+                            }
+                        }
+                        Err(e) => eprintln!("Unable to reload {}: {e}", args.shader),
+                    }
+                }
+                if playlist.len() > 1 {
+                    let upcoming = (playing + 1) % playlist.len();
+                    // prepare the next shader once the current one is playing, so switching
+                    // only has to compile it
+                    if next.is_none() {
+                        next =
+                            Some(runtime.block_on(prepare_shader(&wgputoy, &playlist[upcoming])));
+                    }
+                    if !wgputoy.is_paused() && time::Instant::now() >= switch_at {
+                        playing = upcoming;
+                        args.shader = playlist[playing].clone();
+                        println!("Playing {}", args.shader);
+                        let loaded = runtime
+                            .block_on(load_metadata(&mut wgputoy, &args.shader))
+                            .and(next.take().unwrap());
+                        match loaded {
+                            Ok((shader, prepared)) => {
+                                if let Some(shader) = shader {
+                                    compile_shader(&mut wgputoy, shader);
+                                }
+                                wgputoy.clear_state();
+                                elapsed = 0.;
+                                files = prepared;
+                                if let Err(e) = watch(&mut watcher, &files) {
+                                    eprintln!("Unable to watch the shader's includes: {e}");
+                                }
+                            }
+                            Err(e) => eprintln!("Unable to load {}: {e}", args.shader),
+                        }
+                        switch_at = time::Instant::now() + args.cycle;
+                    }
+                }
+                let window = wgputoy.wgpu.window.as_ref().unwrap();
+                if last_title.elapsed() >= TITLE_INTERVAL {
+                    last_title = time::Instant::now();
+                    window.set_title(&window_title(&wgputoy, &args.shader));
+                }
+                window.request_redraw();
 
                 match mode {
                     Mode::Poll => {
-                        std::thread::sleep(POLL_SLEEP_TIME);
+                        match frame_interval {
+                            Some(interval) => {
+                                std::thread::sleep(interval.saturating_sub(last_frame.elapsed()))
+                            }
+                            None => std::thread::sleep(POLL_SLEEP_TIME),
+                        }
                         elwt.set_control_flow(ControlFlow::Poll);
                     }
-                    _ => (),
-                };
+                }
 
                 if close_requested {
                     elwt.exit();
@@ -173,3 +1047,404 @@ mod winit {
         Ok(())
     }
 }
+
+/// A panel drawn over the shader with a slider for each custom uniform, a thumbnail of each
+/// channel and a checkbox to enable each entry point, like the website's.
+#[cfg(all(feature = "egui", not(target_arch = "wasm32")))]
+mod overlay {
+    use serde::Deserialize;
+    use wgputoy::context::WgpuContext;
+    use wgputoy::WgpuToyRenderer;
+    use winit::{event::WindowEvent, window::Window};
+
+    /// The parts of `reflection_json` shown in the panel.
+    #[derive(Deserialize, Default)]
+    struct Reflection {
+        entry_points: Vec<EntryPoint>,
+        custom_uniforms: Vec<CustomUniform>,
+    }
+
+    #[derive(Deserialize)]
+    struct EntryPoint {
+        name: String,
+        label: String,
+        enabled: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct CustomUniform {
+        name: String,
+        value: f32,
+        min: Option<f32>,
+        max: Option<f32>,
+        step: Option<f32>,
+    }
+
+    /// Changes made with the panel, applied once it has been laid out.
+    #[derive(Default)]
+    struct Changes {
+        uniforms: Option<(Vec<String>, Vec<f32>)>,
+        entry_points: Vec<(String, bool)>,
+    }
+
+    /// A channel's texture as registered with egui, and its size.
+    type Thumbnail = Option<(egui::TextureId, [u32; 2])>;
+
+    pub struct Overlay {
+        pub visible: bool,
+        state: egui_winit::State,
+        renderer: egui_wgpu::Renderer,
+        /// The format the renderer draws to, which it is recreated for if the surface changes
+        format: wgpu::TextureFormat,
+        thumbnails: Vec<Thumbnail>,
+        /// What `update` laid out, to be drawn by `paint`
+        output: Option<(Vec<egui::ClippedPrimitive>, egui::TexturesDelta, f32)>,
+    }
+
+    impl Overlay {
+        pub fn new(wgputoy: &WgpuToyRenderer) -> Self {
+            let window = wgputoy.wgpu.window.as_ref().unwrap();
+            let format = wgputoy.wgpu.surface_config.format;
+            Overlay {
+                visible: false,
+                state: egui_winit::State::new(
+                    egui::Context::default(),
+                    egui::ViewportId::ROOT,
+                    window,
+                    Some(window.scale_factor() as f32),
+                    None,
+                ),
+                renderer: egui_wgpu::Renderer::new(&wgputoy.wgpu.device, format, None, 1),
+                format,
+                thumbnails: vec![],
+                output: None,
+            }
+        }
+
+        /// Pass an event to the panel, returning whether it was used by it, in which case it
+        /// shouldn't reach the shader.
+        pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+            self.visible && self.state.on_window_event(window, event).consumed
+        }
+
+        /// Lay out the panel for the next frame, applying any changes made with it.
+        pub fn update(&mut self, wgputoy: &mut WgpuToyRenderer) {
+            if !self.visible {
+                self.output = None;
+                return;
+            }
+            self.update_thumbnails(wgputoy);
+            let reflection: Reflection =
+                serde_json::from_str(&wgputoy.reflection_json()).unwrap_or_default();
+            let mut changes = Changes::default();
+            let window = wgputoy.wgpu.window.as_ref().unwrap();
+            let input = self.state.take_egui_input(window);
+            let ctx = self.state.egui_ctx().clone();
+            let output = ctx.run(input, |ctx| {
+                panel(ctx, &reflection, &self.thumbnails, &mut changes);
+            });
+            self.state
+                .handle_platform_output(window, output.platform_output);
+            let primitives = ctx.tessellate(output.shapes, output.pixels_per_point);
+            self.output = Some((primitives, output.textures_delta, output.pixels_per_point));
+            if let Some((names, values)) = changes.uniforms {
+                wgputoy.set_custom_floats(names, values);
+            }
+            for (name, enabled) in changes.entry_points {
+                wgputoy.set_entry_point_enabled(&name, enabled);
+            }
+        }
+
+        /// Register the texture of each channel with egui, or update it if it has changed.
+        fn update_thumbnails(&mut self, wgputoy: &WgpuToyRenderer) {
+            let device = &wgputoy.wgpu.device;
+            let mut index = 0;
+            while let Some(texture) = wgputoy.channel_texture(index) {
+                // egui samples textures with filtering
+                let filterable = matches!(
+                    texture.format().sample_type(None, Some(device.features())),
+                    Some(wgpu::TextureSampleType::Float { filterable: true })
+                );
+                let view = filterable.then(|| texture.create_view(&Default::default()));
+                let existing = self.thumbnails.get(index).copied().flatten();
+                let thumbnail = match (view, existing) {
+                    (Some(view), Some((id, _))) => {
+                        self.renderer.update_egui_texture_from_wgpu_texture(
+                            device,
+                            &view,
+                            wgpu::FilterMode::Linear,
+                            id,
+                        );
+                        Some(id)
+                    }
+                    (Some(view), None) => Some(self.renderer.register_native_texture(
+                        device,
+                        &view,
+                        wgpu::FilterMode::Linear,
+                    )),
+                    (None, Some((id, _))) => {
+                        self.renderer.free_texture(&id);
+                        None
+                    }
+                    (None, None) => None,
+                };
+                let thumbnail = thumbnail.map(|id| (id, [texture.width(), texture.height()]));
+                match self.thumbnails.get_mut(index) {
+                    Some(existing) => *existing = thumbnail,
+                    None => self.thumbnails.push(thumbnail),
+                }
+                index += 1;
+            }
+        }
+
+        /// Draw the panel laid out by `update` over a frame.
+        pub fn paint(&mut self, wgpu: &WgpuContext, view: &wgpu::TextureView) {
+            let Some((primitives, textures, pixels_per_point)) = self.output.take() else {
+                return;
+            };
+            if wgpu.surface_config.format != self.format {
+                self.format = wgpu.surface_config.format;
+                self.renderer = egui_wgpu::Renderer::new(&wgpu.device, self.format, None, 1);
+                // registered with the previous renderer
+                self.thumbnails.clear();
+            }
+            for (id, delta) in &textures.set {
+                self.renderer
+                    .update_texture(&wgpu.device, &wgpu.queue, *id, delta);
+            }
+            let screen = egui_wgpu::ScreenDescriptor {
+                size_in_pixels: [wgpu.surface_config.width, wgpu.surface_config.height],
+                pixels_per_point,
+            };
+            let mut encoder = wgpu.device.create_command_encoder(&Default::default());
+            let commands = self.renderer.update_buffers(
+                &wgpu.device,
+                &wgpu.queue,
+                &mut encoder,
+                &primitives,
+                &screen,
+            );
+            {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("egui"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                self.renderer.render(&mut pass, &primitives, &screen);
+            }
+            wgpu.queue
+                .submit(commands.into_iter().chain(Some(encoder.finish())));
+            for id in &textures.free {
+                self.renderer.free_texture(id);
+            }
+        }
+    }
+
+    fn panel(
+        ctx: &egui::Context,
+        reflection: &Reflection,
+        thumbnails: &[Thumbnail],
+        changes: &mut Changes,
+    ) {
+        egui::Window::new("wgputoy")
+            .default_width(280.)
+            .show(ctx, |ui| {
+                if !reflection.custom_uniforms.is_empty() {
+                    ui.heading("Uniforms");
+                    let mut values: Vec<f32> =
+                        reflection.custom_uniforms.iter().map(|u| u.value).collect();
+                    let mut changed = false;
+                    for (uniform, value) in reflection.custom_uniforms.iter().zip(&mut values) {
+                        // uniforms without a `#param` range are sliders from 0 to 1 on the website
+                        let range = uniform.min.unwrap_or(0.)..=uniform.max.unwrap_or(1.);
+                        let slider = egui::Slider::new(value, range)
+                            .text(&uniform.name)
+                            .step_by(uniform.step.unwrap_or(0.) as f64);
+                        changed |= ui.add(slider).changed();
+                    }
+                    if changed {
+                        let names = reflection
+                            .custom_uniforms
+                            .iter()
+                            .map(|u| u.name.clone())
+                            .collect();
+                        changes.uniforms = Some((names, values));
+                    }
+                }
+                ui.heading("Channels");
+                ui.horizontal(|ui| {
+                    for (index, thumbnail) in thumbnails.iter().enumerate() {
+                        match thumbnail {
+                            Some((id, [width, height])) => {
+                                let size = egui::vec2(96., 96. * *height as f32 / *width as f32);
+                                ui.image((*id, size))
+                                    .on_hover_text(format!("channel{index}: {width}x{height}"));
+                            }
+                            None => {
+                                ui.label(format!("channel{index}"));
+                            }
+                        }
+                    }
+                });
+                ui.heading("Entry points");
+                for entry_point in &reflection.entry_points {
+                    let mut enabled = entry_point.enabled;
+                    if ui.checkbox(&mut enabled, &entry_point.label).changed() {
+                        changes
+                            .entry_points
+                            .push((entry_point.name.clone(), enabled));
+                    }
+                }
+            });
+    }
+}
+
+/// Audio captured from an input device and analysed each frame into an audio channel, as the
+/// Web Audio API's `AnalyserNode` would on the website.
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+mod audio {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::FromSample;
+    use realfft::{RealFftPlanner, RealToComplex};
+    use std::collections::VecDeque;
+    use std::error::Error;
+    use std::f32::consts::TAU;
+    use std::sync::{Arc, Mutex};
+    use wgputoy::{WgpuToyRenderer, AUDIO_SAMPLES};
+
+    /// The number of samples analysed, giving a bin of the spectrum for each texel
+    const FFT_SIZE: usize = 2 * AUDIO_SAMPLES;
+    /// How much of the previous frame's spectrum is kept, as `AnalyserNode.smoothingTimeConstant`
+    const SMOOTHING: f32 = 0.8;
+    /// The decibels shown as 0 and 1 in the spectrum, as `AnalyserNode.minDecibels` and
+    /// `maxDecibels`
+    const MIN_DB: f32 = -100.;
+    const MAX_DB: f32 = -30.;
+
+    pub struct AudioInput {
+        /// Capturing stops when the stream is dropped
+        _stream: cpal::Stream,
+        /// The latest `FFT_SIZE` samples, mixed down to mono
+        samples: Arc<Mutex<VecDeque<f32>>>,
+        fft: Arc<dyn RealToComplex<f32>>,
+        /// The Blackman window applied before the FFT
+        window: Vec<f32>,
+        /// The smoothed magnitude of each bin
+        magnitudes: Vec<f32>,
+    }
+
+    impl AudioInput {
+        /// Capture from the default input device, or the first whose name contains `name` if
+        /// it isn't empty.
+        pub fn new(name: &str) -> Result<Self, Box<dyn Error>> {
+            let host = cpal::default_host();
+            let device = if name.is_empty() {
+                host.default_input_device()
+            } else {
+                host.input_devices()?
+                    .find(|device| device.name().is_ok_and(|n| n.contains(name)))
+            }
+            .ok_or("No audio input device found")?;
+            let config = device.default_input_config()?;
+            let samples = Arc::new(Mutex::new(VecDeque::from(vec![0.; FFT_SIZE])));
+            let stream = match config.sample_format() {
+                cpal::SampleFormat::F32 => {
+                    capture::<f32>(&device, &config.into(), samples.clone())?
+                }
+                cpal::SampleFormat::I16 => {
+                    capture::<i16>(&device, &config.into(), samples.clone())?
+                }
+                cpal::SampleFormat::U16 => {
+                    capture::<u16>(&device, &config.into(), samples.clone())?
+                }
+                format => return Err(format!("Unsupported audio sample format {format}").into()),
+            };
+            stream.play()?;
+            println!("Capturing audio from {}", device.name()?);
+            let window = (0..FFT_SIZE)
+                .map(|i| {
+                    let x = i as f32 / FFT_SIZE as f32;
+                    0.42 - 0.5 * (TAU * x).cos() + 0.08 * (2. * TAU * x).cos()
+                })
+                .collect();
+            Ok(AudioInput {
+                _stream: stream,
+                samples,
+                fft: RealFftPlanner::new().plan_fft_forward(FFT_SIZE),
+                window,
+                magnitudes: vec![0.; AUDIO_SAMPLES],
+            })
+        }
+
+        /// Write the spectrum and waveform of the latest samples into a channel.
+        pub fn update(
+            &mut self,
+            wgputoy: &mut WgpuToyRenderer,
+            channel: usize,
+        ) -> Result<(), String> {
+            let samples: Vec<f32> = self.samples.lock().unwrap().iter().copied().collect();
+            let waveform: Vec<f32> = samples[FFT_SIZE - AUDIO_SAMPLES..]
+                .iter()
+                .map(|sample| 0.5 + 0.5 * sample)
+                .collect();
+            let mut input: Vec<f32> = samples
+                .iter()
+                .zip(&self.window)
+                .map(|(sample, weight)| sample * weight)
+                .collect();
+            let mut output = self.fft.make_output_vec();
+            self.fft
+                .process(&mut input, &mut output)
+                .map_err(|e| e.to_string())?;
+            let spectrum: Vec<f32> = self
+                .magnitudes
+                .iter_mut()
+                .zip(&output)
+                .map(|(magnitude, bin)| {
+                    *magnitude =
+                        SMOOTHING * *magnitude + (1. - SMOOTHING) * bin.norm() / FFT_SIZE as f32;
+                    let db = 20. * magnitude.log10();
+                    ((db - MIN_DB) / (MAX_DB - MIN_DB)).clamp(0., 1.)
+                })
+                .collect();
+            wgputoy.set_channel_audio(channel, &spectrum, &waveform)
+        }
+    }
+
+    /// Start a stream appending the mono mix of its samples to `samples`, keeping the last
+    /// `FFT_SIZE` of them.
+    fn capture<T>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        samples: Arc<Mutex<VecDeque<f32>>>,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: cpal::SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = config.channels as usize;
+        device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let mut samples = samples.lock().unwrap();
+                for frame in data.chunks(channels) {
+                    let sum: f32 = frame.iter().map(|sample| sample.to_sample::<f32>()).sum();
+                    samples.push_back(sum / channels as f32);
+                }
+                let excess = samples.len().saturating_sub(FFT_SIZE);
+                samples.drain(..excess);
+            },
+            |e| log::error!("Audio input: {e}"),
+            None,
+        )
+    }
+}
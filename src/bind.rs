@@ -1,26 +1,127 @@
+use crate::stats::MemoryUsage;
 use crate::WgpuContext;
 use bitvec::prelude::*;
+use std::collections::BTreeMap;
 use std::mem::size_of;
+use std::rc::Rc;
 
 const NUM_KEYCODES: usize = 256;
-const MAX_CUSTOM_PARAMS: usize = 32;
+pub const MAX_CUSTOM_PARAMS: usize = 32;
 pub const NUM_ASSERT_COUNTERS: usize = 10;
+/// The number of `debug_print_*` calls recorded per stats period, beyond which they are dropped
+pub const MAX_DEBUG_PRINTS: usize = 1024;
+/// The size of a `DebugPrint` record: a tag, a kind and four words of value
+pub const DEBUG_PRINT_SIZE: usize = 6 * size_of::<u32>();
+/// The assertion counters, followed by the print count and the print records
+pub const DEBUG_BUFFER_SIZE: usize =
+    (NUM_ASSERT_COUNTERS + 1) * size_of::<u32>() + MAX_DEBUG_PRINTS * DEBUG_PRINT_SIZE;
 const USER_DATA_BYTES: usize = 4096;
 pub const OFFSET_ALIGNMENT: usize = 256;
+pub const MAX_DISPATCHES: usize = 256;
+pub const MAX_INDIRECT_DISPATCHES: usize = 16;
+pub const INDIRECT_ARGS_SIZE: u64 = 3 * size_of::<u32>() as u64;
+
+// https://www.w3.org/TR/WGSL/#storage-texel-formats
+const STORAGE_FORMATS: &[(&str, wgpu::TextureFormat)] = &[
+    ("rgba8unorm", wgpu::TextureFormat::Rgba8Unorm),
+    ("rgba8snorm", wgpu::TextureFormat::Rgba8Snorm),
+    ("rgba8uint", wgpu::TextureFormat::Rgba8Uint),
+    ("rgba8sint", wgpu::TextureFormat::Rgba8Sint),
+    ("rgba16uint", wgpu::TextureFormat::Rgba16Uint),
+    ("rgba16sint", wgpu::TextureFormat::Rgba16Sint),
+    ("rgba16float", wgpu::TextureFormat::Rgba16Float),
+    ("r32uint", wgpu::TextureFormat::R32Uint),
+    ("r32sint", wgpu::TextureFormat::R32Sint),
+    ("r32float", wgpu::TextureFormat::R32Float),
+    ("rg32uint", wgpu::TextureFormat::Rg32Uint),
+    ("rg32sint", wgpu::TextureFormat::Rg32Sint),
+    ("rg32float", wgpu::TextureFormat::Rg32Float),
+    ("rgba32uint", wgpu::TextureFormat::Rgba32Uint),
+    ("rgba32sint", wgpu::TextureFormat::Rgba32Sint),
+    ("rgba32float", wgpu::TextureFormat::Rgba32Float),
+];
+
+pub fn parse_storage_format(name: &str) -> Option<wgpu::TextureFormat> {
+    STORAGE_FORMATS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, f)| *f)
+}
+
+/// The WGSL scalar type of texels of the given format.
+pub fn sample_scalar(format: wgpu::TextureFormat) -> &'static str {
+    match format.sample_type(None, None) {
+        Some(wgpu::TextureSampleType::Uint) => "u32",
+        Some(wgpu::TextureSampleType::Sint) => "i32",
+        _ => "f32",
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1. } else { 1. };
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0. => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1. + mantissa / 1024.) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// The components of texels of a storage format as floats, with `unorm` and `snorm` values
+/// normalised.
+pub fn texels_to_f32(format: wgpu::TextureFormat, bytes: &[u8]) -> Vec<f32> {
+    use wgpu::TextureFormat as F;
+    let words16 = || bytes.chunks_exact(2).map(|c| [c[0], c[1]]);
+    let words32 = || bytes.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]);
+    match format {
+        F::Rgba8Unorm => bytes.iter().map(|&x| x as f32 / 255.).collect(),
+        F::Rgba8Snorm => bytes
+            .iter()
+            .map(|&x| (x as i8 as f32 / 127.).max(-1.))
+            .collect(),
+        F::Rgba8Uint => bytes.iter().map(|&x| x as f32).collect(),
+        F::Rgba8Sint => bytes.iter().map(|&x| x as i8 as f32).collect(),
+        F::Rgba16Uint => words16().map(|x| u16::from_le_bytes(x) as f32).collect(),
+        F::Rgba16Sint => words16().map(|x| i16::from_le_bytes(x) as f32).collect(),
+        F::Rgba16Float => words16()
+            .map(|x| f16_to_f32(u16::from_le_bytes(x)))
+            .collect(),
+        F::R32Uint | F::Rg32Uint | F::Rgba32Uint => {
+            words32().map(|x| u32::from_le_bytes(x) as f32).collect()
+        }
+        F::R32Sint | F::Rg32Sint | F::Rgba32Sint => {
+            words32().map(|x| i32::from_le_bytes(x) as f32).collect()
+        }
+        _ => words32().map(f32::from_le_bytes).collect(),
+    }
+}
+
+pub fn storage_format_name(format: wgpu::TextureFormat) -> &'static str {
+    STORAGE_FORMATS
+        .iter()
+        .find(|(_, f)| *f == format)
+        .map(|(n, _)| *n)
+        .unwrap_or("rgba16float")
+}
 
 trait Binding {
     fn layout(&self) -> wgpu::BindingType;
-    fn binding(&self) -> wgpu::BindingResource;
+    fn binding(&self) -> wgpu::BindingResource<'_>;
     fn to_wgsl(&self) -> &str;
 }
 
+type SerialiseFn<H> = Box<dyn Fn(&H) -> Vec<u8>>;
+type BindFn = Box<dyn for<'a> Fn(&'a wgpu::Buffer) -> wgpu::BufferBinding<'a>>;
+
 pub struct BufferBinding<H> {
     pub host: H,
     //serialise: Box<dyn for<'a> Fn(&'a H) -> &'a [u8]>,
-    serialise: Box<dyn Fn(&H) -> Vec<u8>>,
+    serialise: SerialiseFn<H>,
     device: wgpu::Buffer,
     layout: wgpu::BindingType,
-    bind: Box<dyn for<'a> Fn(&'a wgpu::Buffer) -> wgpu::BufferBinding<'a>>,
+    bind: BindFn,
     decl: String,
 }
 
@@ -34,7 +135,7 @@ impl<H> Binding for BufferBinding<H> {
     fn layout(&self) -> wgpu::BindingType {
         self.layout
     }
-    fn binding(&self) -> wgpu::BindingResource {
+    fn binding(&self) -> wgpu::BindingResource<'_> {
         wgpu::BindingResource::Buffer((self.bind)(&self.device))
     }
     fn to_wgsl(&self) -> &str {
@@ -56,6 +157,39 @@ impl<H> BufferBinding<H> {
     }
 }
 
+/// A storage buffer, whose memory may be shared with views declared by `#storage_view`.
+pub struct StorageBinding {
+    device: Rc<wgpu::Buffer>,
+    layout: wgpu::BindingType,
+    decl: String,
+}
+
+impl Drop for StorageBinding {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.device) == 1 {
+            self.device.destroy();
+        }
+    }
+}
+
+impl Binding for StorageBinding {
+    fn layout(&self) -> wgpu::BindingType {
+        self.layout
+    }
+    fn binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(self.device.as_entire_buffer_binding())
+    }
+    fn to_wgsl(&self) -> &str {
+        &self.decl
+    }
+}
+
+impl StorageBinding {
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.device
+    }
+}
+
 pub struct TextureBinding {
     device: wgpu::Texture,
     view: wgpu::TextureView,
@@ -73,7 +207,7 @@ impl Binding for TextureBinding {
     fn layout(&self) -> wgpu::BindingType {
         self.layout
     }
-    fn binding(&self) -> wgpu::BindingResource {
+    fn binding(&self) -> wgpu::BindingResource<'_> {
         wgpu::BindingResource::TextureView(&self.view)
     }
     fn to_wgsl(&self) -> &str {
@@ -81,19 +215,150 @@ impl Binding for TextureBinding {
     }
 }
 
+pub struct ChannelInfo {
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+}
+
 impl TextureBinding {
+    pub fn info(&self) -> ChannelInfo {
+        ChannelInfo {
+            width: self.device.width(),
+            height: self.device.height(),
+            format: format!("{:?}", self.device.format()),
+        }
+    }
     pub fn texture(&self) -> &wgpu::Texture {
         &self.device
     }
     pub fn view(&self) -> &wgpu::TextureView {
         &self.view
     }
-    pub fn set_texture(&mut self, texture: wgpu::Texture) {
-        self.device = texture;
-        self.view = self.device.create_view(&Default::default());
+    /// A sampled channel texture, bound as `channel{index}`.
+    pub fn channel(index: usize, texture: wgpu::Texture) -> Self {
+        TextureBinding {
+            view: texture.create_view(&Default::default()),
+            device: texture,
+            layout: wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            decl: format!("var channel{index}: texture_2d<f32>"),
+        }
+    }
+
+    /// The sampled texture of a pass layer, bound as `pass_in_{index}`.
+    pub fn pass_layer_read(index: u32, texture: wgpu::Texture) -> Self {
+        let format = texture.format();
+        TextureBinding {
+            view: texture.create_view(&Default::default()),
+            device: texture,
+            layout: wgpu::BindingType::Texture {
+                multisampled: false,
+                sample_type: format
+                    .sample_type(None, None)
+                    .expect("pass formats are colour formats"),
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            decl: format!("var pass_in_{index}: texture_2d<{}>", sample_scalar(format)),
+        }
+    }
+
+    /// The storage texture of a pass layer, bound as `pass_out_{index}`.
+    pub fn pass_layer_write(index: u32, texture: wgpu::Texture) -> Self {
+        let format = texture.format();
+        TextureBinding {
+            view: texture.create_view(&Default::default()),
+            device: texture,
+            layout: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            decl: format!(
+                "var pass_out_{index}: texture_storage_2d<{},write>",
+                storage_format_name(format)
+            ),
+        }
+    }
+
+    /// A read-write storage channel texture, bound as `channel{index}`.
+    pub fn storage_channel(index: usize, texture: wgpu::Texture) -> Self {
+        let format = texture.format();
+        TextureBinding {
+            view: texture.create_view(&Default::default()),
+            device: texture,
+            layout: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::ReadWrite,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            decl: format!(
+                "var channel{index}: texture_storage_2d<{},read_write>",
+                storage_format_name(format)
+            ),
+        }
     }
 }
 
+fn pass_texture(
+    wgpu: &WgpuContext,
+    width: u32,
+    height: u32,
+    layers: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    wgpu.device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        // both pass textures support every usage so that they can be swapped for ping-ponging
+        usage: wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    })
+}
+
+/// The bytes of every mip level and layer of a texture.
+pub fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+    let texel_size = texture.format().block_copy_size(None).unwrap_or(4) as u64;
+    (0..texture.mip_level_count())
+        .map(|level| {
+            let size = texture.size().mip_level_size(level, texture.dimension());
+            size.width as u64 * size.height as u64 * size.depth_or_array_layers as u64
+        })
+        .sum::<u64>()
+        * texel_size
+}
+
+pub fn blank_texture(wgpu: &WgpuContext) -> wgpu::Texture {
+    wgpu.device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        label: None,
+        view_formats: &[],
+    })
+}
+
 struct SamplerBinding {
     layout: wgpu::BindingType,
     bind: wgpu::Sampler,
@@ -104,7 +369,7 @@ impl Binding for SamplerBinding {
     fn layout(&self) -> wgpu::BindingType {
         self.layout
     }
-    fn binding(&self) -> wgpu::BindingResource {
+    fn binding(&self) -> wgpu::BindingResource<'_> {
         wgpu::BindingResource::Sampler(&self.bind)
     }
     fn to_wgsl(&self) -> &str {
@@ -127,6 +392,12 @@ pub struct Mouse {
     pub click: i32,
 }
 
+pub struct PassLayer {
+    pub index: u32,
+    pub read: TextureBinding,
+    pub write: TextureBinding,
+}
+
 pub struct Bindings {
     pub time: BufferBinding<Time>,
     pub mouse: BufferBinding<Mouse>,
@@ -134,15 +405,25 @@ pub struct Bindings {
     pub custom: BufferBinding<(Vec<String>, Vec<f32>)>,
     pub user_data: BufferBinding<indexmap::IndexMap<String, Vec<u32>>>,
 
-    pub storage1: BufferBinding<()>,
-    pub storage2: BufferBinding<()>,
+    /// Buffers declared with `#storage` or `#storage_view`, in order of declaration
+    pub storage: Vec<StorageBinding>,
+    /// Indices of the `#storage_double` pairs in `storage`
+    storage_swaps: Vec<(usize, usize)>,
     pub debug_buffer: BufferBinding<()>,
     pub dispatch_info: BufferBinding<()>,
+    pub finished: BufferBinding<()>,
+    pub dispatch_indirect: BufferBinding<()>,
+    pub indirect_args: wgpu::Buffer,
 
     pub tex_screen: TextureBinding,
+    /// Downsampled copies of the screen, generated after the last pass that uses it, so that
+    /// this and earlier passes sample the previous frame and later passes the current one
+    pub tex_screen_mips: TextureBinding,
     pub tex_read: TextureBinding,
     pub tex_write: TextureBinding,
     pub channels: Vec<TextureBinding>,
+    /// Pass layers declared with `#pass_format`, stored outside of `pass_in`/`pass_out`
+    pub pass_layers: Vec<PassLayer>,
 
     nearest: SamplerBinding,
     bilinear: SamplerBinding,
@@ -163,8 +444,30 @@ fn uniform_buffer_size<T>() -> u64 {
     size.div_ceil(16) * 16
 }
 
+/// A storage buffer, declared by `#storage` or `add_storage_buffer`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StorageBuffer {
+    pub name: String,
+    pub size: u64,
+    /// The declaration for the prelude, or empty if declared by the preprocessor
+    pub decl: String,
+    /// The buffer whose memory this declares with another type, for `#storage_view`
+    pub view_of: Option<String>,
+    pub read_only: bool,
+    /// The buffer this is swapped with every frame, for `#storage_double`
+    pub swap_with: Option<String>,
+}
+
 impl Bindings {
-    pub fn new(wgpu: &WgpuContext, width: u32, height: u32, pass_f32: bool) -> Self {
+    pub fn new(
+        wgpu: &WgpuContext,
+        width: u32,
+        height: u32,
+        pass_f32: bool,
+        pass_count: u32,
+        pass_formats: &BTreeMap<u32, wgpu::TextureFormat>,
+        storage_buffers: &[StorageBuffer],
+    ) -> Self {
         log::info!("Creating bindings");
         let uniform_buffer = wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
@@ -176,30 +479,49 @@ impl Bindings {
             has_dynamic_offset: false,
             min_binding_size: None,
         };
+        let mut storage: Vec<StorageBinding> = vec![];
+        for buffer in storage_buffers {
+            let viewed = buffer
+                .view_of
+                .as_ref()
+                .and_then(|of| storage_buffers.iter().position(|b| &b.name == of));
+            let device = match viewed {
+                Some(i) => storage[i].device.clone(),
+                None => Rc::new(wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&buffer.name),
+                    size: buffer.size.max(4).next_multiple_of(4),
+                    usage: wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_SRC
+                        | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })),
+            };
+            storage.push(StorageBinding {
+                device,
+                layout: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage {
+                        read_only: buffer.read_only,
+                    },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                decl: buffer.decl.clone(),
+            });
+        }
+        let storage_swaps = storage_buffers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, buffer)| {
+                let other = buffer.swap_with.as_ref()?;
+                let j = storage_buffers.iter().position(|b| &b.name == other)?;
+                (i < j).then_some((i, j))
+            })
+            .collect();
         let pass_format = if pass_f32 {
             "rgba32float"
         } else {
             "rgba16float"
         };
-        let blank = wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: 1,
-                height: 1,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING,
-            label: None,
-            view_formats: &[],
-        };
-        let channel_layout = wgpu::BindingType::Texture {
-            multisampled: false,
-            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-            view_dimension: wgpu::TextureViewDimension::D2,
-        };
         let repeat = wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
@@ -217,47 +539,37 @@ impl Bindings {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
-        let tex_read = wgpu.device.create_texture(&wgpu::TextureDescriptor {
+        let tex_screen_mips = wgpu.device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: wgpu::Extent3d {
                 width,
                 height,
-                depth_or_array_layers: 4,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: if pass_f32 {
-                wgpu::TextureFormat::Rgba32Float
-            } else {
-                wgpu::TextureFormat::Rgba16Float
-            },
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-        let tex_write = wgpu.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 4,
+                depth_or_array_layers: 1,
             },
-            mip_level_count: 1,
+            mip_level_count: width.max(height).ilog2() + 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: if pass_f32 {
-                wgpu::TextureFormat::Rgba32Float
-            } else {
-                wgpu::TextureFormat::Rgba16Float
-            },
-            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
         });
-        let channel0 = wgpu.device.create_texture(&blank);
-        let channel1 = wgpu.device.create_texture(&blank);
+        let pass_layers = pass_formats
+            .keys()
+            .filter(|&&index| index < pass_count)
+            .count() as u32;
+        let array_format = if pass_f32 {
+            wgpu::TextureFormat::Rgba32Float
+        } else {
+            wgpu::TextureFormat::Rgba16Float
+        };
+        let array_layers = (pass_count - pass_layers).max(1);
+        let tex_read = pass_texture(wgpu, width, height, array_layers, array_format);
+        let tex_write = pass_texture(wgpu, width, height, array_layers, array_format);
         Bindings {
             time: BufferBinding {
                 host: Time {
@@ -347,38 +659,14 @@ impl Bindings {
                 decl: "var<storage,read> data: Data".to_string(),
             },
 
-            storage1: BufferBinding {
-                host: (),
-                serialise: Box::new(|_| vec![]),
-                device: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
-                    label: None,
-                    size: 134217728, // default limit (128 MiB)
-                    usage: wgpu::BufferUsages::STORAGE,
-                    mapped_at_creation: false,
-                }),
-                layout: storage_buffer,
-                bind: Box::new(wgpu::Buffer::as_entire_buffer_binding),
-                decl: String::new(),
-            },
-            storage2: BufferBinding {
-                host: (),
-                serialise: Box::new(|_| vec![]),
-                device: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
-                    label: None,
-                    size: 134217728, // default limit (128 MiB)
-                    usage: wgpu::BufferUsages::STORAGE,
-                    mapped_at_creation: false,
-                }),
-                layout: storage_buffer,
-                bind: Box::new(wgpu::Buffer::as_entire_buffer_binding),
-                decl: String::new(),
-            },
+            storage,
+            storage_swaps,
             debug_buffer: BufferBinding {
                 host: (),
                 serialise: Box::new(|_| vec![]),
                 device: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
                     label: None,
-                    size: uniform_buffer_size::<[u32; NUM_ASSERT_COUNTERS]>(),
+                    size: DEBUG_BUFFER_SIZE as u64,
                     usage: wgpu::BufferUsages::STORAGE
                         | wgpu::BufferUsages::COPY_SRC
                         | wgpu::BufferUsages::COPY_DST,
@@ -386,14 +674,14 @@ impl Bindings {
                 }),
                 layout: storage_buffer,
                 bind: Box::new(wgpu::Buffer::as_entire_buffer_binding),
-                decl: "var<storage,read_write> _assert_counts: array<atomic<u32>>".to_string(),
+                decl: "var<storage,read_write> _debug: Debug".to_string(),
             },
             dispatch_info: BufferBinding {
                 host: (),
                 serialise: Box::new(|_| vec![]),
                 device: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
                     label: None,
-                    size: 256 * OFFSET_ALIGNMENT as u64,
+                    size: (MAX_DISPATCHES * OFFSET_ALIGNMENT) as u64,
                     usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
                     mapped_at_creation: false,
                 }),
@@ -409,6 +697,40 @@ impl Bindings {
                 }),
                 decl: "var<uniform> dispatch: DispatchInfo".to_string(),
             },
+            finished: BufferBinding {
+                host: (),
+                serialise: Box::new(|_| vec![]),
+                device: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size: size_of::<u32>() as u64,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                layout: storage_buffer,
+                bind: Box::new(wgpu::Buffer::as_entire_buffer_binding),
+                decl: "var<storage,read_write> _finished: atomic<u32>".to_string(),
+            },
+            dispatch_indirect: BufferBinding {
+                host: (),
+                serialise: Box::new(|_| vec![]),
+                device: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size: MAX_INDIRECT_DISPATCHES as u64 * INDIRECT_ARGS_SIZE,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                layout: storage_buffer,
+                bind: Box::new(wgpu::Buffer::as_entire_buffer_binding),
+                decl: format!("var<storage,read_write> dispatch_indirect: array<DispatchIndirect,{MAX_INDIRECT_DISPATCHES}>"),
+            },
+            // the shader-visible arguments are copied here before each indirect dispatch,
+            // as a buffer cannot be used as both writable storage and indirect arguments
+            indirect_args: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: MAX_INDIRECT_DISPATCHES as u64 * INDIRECT_ARGS_SIZE,
+                usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
 
             tex_screen: TextureBinding {
                 view: tex_screen.create_view(&Default::default()),
@@ -420,6 +742,16 @@ impl Bindings {
                 },
                 decl: "var screen: texture_storage_2d<rgba16float,write>".to_string(),
             },
+            tex_screen_mips: TextureBinding {
+                view: tex_screen_mips.create_view(&Default::default()),
+                device: tex_screen_mips,
+                layout: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                decl: "var screen_mips: texture_2d<f32>".to_string(),
+            },
             tex_read: TextureBinding {
                 view: tex_read.create_view(&wgpu::TextureViewDescriptor {
                     dimension: Some(wgpu::TextureViewDimension::D2Array),
@@ -443,29 +775,28 @@ impl Bindings {
                 device: tex_write,
                 layout: wgpu::BindingType::StorageTexture {
                     access: wgpu::StorageTextureAccess::WriteOnly,
-                    format: if pass_f32 {
-                        wgpu::TextureFormat::Rgba32Float
-                    } else {
-                        wgpu::TextureFormat::Rgba16Float
-                    },
+                    format: array_format,
                     view_dimension: wgpu::TextureViewDimension::D2Array,
                 },
                 decl: format!("var pass_out: texture_storage_2d_array<{pass_format},write>"),
             },
-            channels: vec![
-                TextureBinding {
-                    view: channel0.create_view(&Default::default()),
-                    device: channel0,
-                    layout: channel_layout,
-                    decl: "var channel0: texture_2d<f32>".to_string(),
-                },
-                TextureBinding {
-                    view: channel1.create_view(&Default::default()),
-                    device: channel1,
-                    layout: channel_layout,
-                    decl: "var channel1: texture_2d<f32>".to_string(),
-                },
-            ],
+            // kept when the bindings are recreated, so added by the renderer once
+            channels: vec![],
+            pass_layers: pass_formats
+                .iter()
+                .filter(|(&index, _)| index < pass_count)
+                .map(|(&index, &format)| PassLayer {
+                    index,
+                    read: TextureBinding::pass_layer_read(
+                        index,
+                        pass_texture(wgpu, width, height, 1, format),
+                    ),
+                    write: TextureBinding::pass_layer_write(
+                        index,
+                        pass_texture(wgpu, width, height, 1, format),
+                    ),
+                })
+                .collect(),
 
             nearest: SamplerBinding {
                 layout: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
@@ -519,42 +850,139 @@ impl Bindings {
     }
 
     fn to_vec(&self) -> Vec<&dyn Binding> {
-        vec![
-            &self.storage1,
-            &self.storage2,
-            &self.time,
+        let mut bindings: Vec<&dyn Binding> = vec![];
+        for storage in &self.storage {
+            bindings.push(storage);
+        }
+        bindings.extend_from_slice(&[
+            &self.time as &dyn Binding,
             &self.mouse,
             &self.keys,
             &self.custom,
             &self.user_data,
             &self.debug_buffer,
             &self.dispatch_info,
+            &self.finished,
+            &self.dispatch_indirect,
             &self.tex_screen,
+            &self.tex_screen_mips,
             &self.tex_read,
             &self.tex_write,
-            &self.channels[0],
-            &self.channels[1],
-            &self.nearest,
+        ]);
+        for channel in &self.channels {
+            bindings.push(channel);
+        }
+        bindings.extend_from_slice(&[
+            &self.nearest as &dyn Binding,
             &self.bilinear,
             &self.trilinear,
             &self.nearest_repeat,
             &self.bilinear_repeat,
             &self.trilinear_repeat,
-        ]
+        ]);
+        for layer in &self.pass_layers {
+            bindings.push(&layer.read);
+            bindings.push(&layer.write);
+        }
+        bindings
+    }
+
+    /// Replace a channel binding, returning whether the bind group layout changed.
+    pub fn set_channel(&mut self, index: usize, binding: TextureBinding) -> bool {
+        let changed = self.channels[index].layout != binding.layout;
+        self.channels[index] = binding;
+        changed
+    }
+
+    /// The memory of the textures and buffers, with buffers viewed by `#storage_view` counted
+    /// once.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        fn bytes<'a>(textures: impl IntoIterator<Item = &'a TextureBinding>) -> f64 {
+            textures
+                .into_iter()
+                .map(|t| texture_bytes(&t.device))
+                .sum::<u64>() as f64
+        }
+        let mut storage: Vec<&Rc<wgpu::Buffer>> = vec![];
+        for binding in &self.storage {
+            if !storage.iter().any(|b| Rc::ptr_eq(b, &binding.device)) {
+                storage.push(&binding.device);
+            }
+        }
+        let other = [
+            self.time.buffer(),
+            self.mouse.buffer(),
+            self.keys.buffer(),
+            self.custom.buffer(),
+            self.user_data.buffer(),
+            self.debug_buffer.buffer(),
+            self.dispatch_info.buffer(),
+            self.finished.buffer(),
+            self.dispatch_indirect.buffer(),
+            &self.indirect_args,
+        ];
+        MemoryUsage {
+            passes: bytes(
+                [&self.tex_read, &self.tex_write]
+                    .into_iter()
+                    .chain(self.pass_layers.iter().flat_map(|l| [&l.read, &l.write])),
+            ),
+            screen: bytes([&self.tex_screen, &self.tex_screen_mips]),
+            channels: bytes(&self.channels),
+            storage: storage.iter().map(|b| b.size()).sum::<u64>() as f64,
+            other: other.iter().map(|b| b.size()).sum::<u64>() as f64,
+            total: 0.,
+        }
+        .totalled()
+    }
+
+    /// Indices of all bindings, for shaders that can't be reflected.
+    /// The binding of the `screen` texture.
+    pub fn screen_binding(&self) -> u32 {
+        let screen = &self.tex_screen as &dyn Binding;
+        self.to_vec()
+            .iter()
+            .position(|&b| std::ptr::addr_eq(b, screen))
+            .unwrap() as u32
     }
 
-    pub fn create_bind_group_layout(&self, wgpu: &WgpuContext) -> wgpu::BindGroupLayout {
+    pub fn all_bindings(&self) -> Vec<u32> {
+        (0..self.to_vec().len() as u32).collect()
+    }
+
+    pub fn layouts(&self) -> Vec<wgpu::BindingType> {
+        self.to_vec().iter().map(|b| b.layout()).collect()
+    }
+
+    /// Whether any of the given bindings requires a dynamic offset.
+    pub fn has_dynamic_offset(&self, used: &[u32]) -> bool {
+        let bindings = self.to_vec();
+        used.iter().any(|&i| {
+            matches!(
+                bindings[i as usize].layout(),
+                wgpu::BindingType::Buffer {
+                    has_dynamic_offset: true,
+                    ..
+                }
+            )
+        })
+    }
+
+    pub fn create_bind_group_layout(
+        &self,
+        wgpu: &WgpuContext,
+        used: &[u32],
+    ) -> wgpu::BindGroupLayout {
+        let bindings = self.to_vec();
         wgpu.device
             .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &self
-                    .to_vec()
+                entries: &used
                     .iter()
-                    .enumerate()
-                    .map(|(i, b)| wgpu::BindGroupLayoutEntry {
-                        binding: i as u32,
+                    .map(|&i| wgpu::BindGroupLayoutEntry {
+                        binding: i,
                         visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: b.layout(),
+                        ty: bindings[i as usize].layout(),
                         count: None,
                     })
                     .collect::<Vec<_>>(),
@@ -578,22 +1006,59 @@ impl Bindings {
         &self,
         wgpu: &WgpuContext,
         layout: &wgpu::BindGroupLayout,
+        used: &[u32],
     ) -> wgpu::BindGroup {
+        let bindings = self.to_vec();
         wgpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout,
-            entries: &self
-                .to_vec()
+            entries: &used
                 .iter()
-                .enumerate()
-                .map(|(i, b)| wgpu::BindGroupEntry {
-                    binding: i as u32,
-                    resource: b.binding(),
+                .map(|&i| wgpu::BindGroupEntry {
+                    binding: i,
+                    resource: bindings[i as usize].binding(),
                 })
                 .collect::<Vec<_>>(),
         })
     }
 
+    /// The texture and array layer holding the latest output of a pass.
+    pub fn pass_texture(&self, index: u32) -> (&wgpu::Texture, u32) {
+        match self.pass_layers.iter().find(|layer| layer.index == index) {
+            Some(layer) => (layer.read.texture(), 0),
+            None => {
+                let below = self
+                    .pass_layers
+                    .iter()
+                    .filter(|layer| layer.index < index)
+                    .count() as u32;
+                (self.tex_read.texture(), index - below)
+            }
+        }
+    }
+
+    /// Swap the textures bound as `pass_in` and `pass_out`.
+    pub fn swap_pass_textures(&mut self) {
+        std::mem::swap(&mut self.tex_read.device, &mut self.tex_write.device);
+        std::mem::swap(&mut self.tex_read.view, &mut self.tex_write.view);
+        for layer in self.pass_layers.iter_mut() {
+            std::mem::swap(&mut layer.read.device, &mut layer.write.device);
+            std::mem::swap(&mut layer.read.view, &mut layer.write.view);
+        }
+    }
+
+    pub fn has_storage_swaps(&self) -> bool {
+        !self.storage_swaps.is_empty()
+    }
+
+    /// Swap the buffers of each `#storage_double` pair.
+    pub fn swap_storage_buffers(&mut self) {
+        for &(i, j) in &self.storage_swaps {
+            let (first, second) = self.storage.split_at_mut(j);
+            std::mem::swap(&mut first[i].device, &mut second[0].device);
+        }
+    }
+
     pub fn to_wgsl(&self) -> String {
         // Note: In the future, the Rust standard library might get a intersperse method, see https://github.com/rust-lang/rust/issues/79524
         itertools::Itertools::intersperse(
@@ -8,12 +8,76 @@ pub enum ColourSpace {
     Rgbe,
 }
 
+/// How the final blit maps the screen's linear colours to the displayable range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Tonemap {
+    /// Clip values above 1
+    #[default]
+    None,
+    Reinhard,
+    Aces,
+    AgX,
+}
+
+/// The primaries of the colours the surface is displayed with. Shaders work in linear sRGB.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Gamut {
+    #[default]
+    Srgb,
+    DisplayP3,
+}
+
+/// Settings of the final blit to the surface, matching `Output` in `blit.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OutputSettings {
+    pub exposure: f32,
+    pub tonemap: u32,
+    pub dither: u32,
+    pub premultiply: u32,
+    pub gamut: u32,
+    _padding: [u32; 3],
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            exposure: 1.,
+            tonemap: Tonemap::None as u32,
+            dither: 0,
+            premultiply: 0,
+            gamut: Gamut::Srgb as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// The final blit to the surface: its shader, either `blit.wgsl` or from
+/// `post_process_source`, and its uniforms, starting with the `OutputSettings`.
+struct Screen<'a> {
+    source: &'a str,
+    post_process: bool,
+    uniforms: &'a [&'a wgpu::Buffer],
+}
+
 pub struct Blitter {
     render_pipeline: wgpu::RenderPipeline,
     render_bind_group: wgpu::BindGroup,
     dest_format: wgpu::TextureFormat,
 }
 
+/// The blit shader with a post-processing snippet, which defines
+/// `fn post_process(uv: vec2<f32>) -> vec4<f32>` after the given prelude, and the line of
+/// the shader at which the snippet starts.
+pub fn post_process_source(prelude: &str, snippet: &str) -> (String, usize) {
+    let head = format!("{}\n{prelude}\n", include_str!("blit.wgsl"));
+    let line = head.lines().count();
+    (
+        format!("{head}{snippet}\n{}", include_str!("blit_post.wgsl")),
+        line,
+    )
+}
+
 impl Blitter {
     pub fn new(
         wgpu: &WgpuContext,
@@ -21,14 +85,66 @@ impl Blitter {
         src_space: ColourSpace,
         dest_format: wgpu::TextureFormat,
         filter: wgpu::FilterMode,
+    ) -> Self {
+        Self::create(wgpu, src, src_space, dest_format, filter, None)
+    }
+
+    /// A blitter drawing the screen to the surface, tonemapped according to `settings`.
+    /// A shader from `post_process_source` can be given to run on the colour of `src` first,
+    /// with its uniforms bound from binding 3 in order.
+    pub fn screen(
+        wgpu: &WgpuContext,
+        src: &wgpu::TextureView,
+        dest_format: wgpu::TextureFormat,
+        settings: &wgpu::Buffer,
+        post_process: Option<(&str, &[&wgpu::Buffer])>,
+    ) -> Self {
+        let mut uniforms = vec![settings];
+        uniforms.extend(post_process.map_or(&[][..], |(_, uniforms)| uniforms));
+        let screen = Screen {
+            source: post_process.map_or(include_str!("blit.wgsl"), |(source, _)| source),
+            post_process: post_process.is_some(),
+            uniforms: &uniforms,
+        };
+        Self::create(
+            wgpu,
+            src,
+            ColourSpace::Linear,
+            dest_format,
+            wgpu::FilterMode::Linear,
+            Some(screen),
+        )
+    }
+
+    fn create(
+        wgpu: &WgpuContext,
+        src: &wgpu::TextureView,
+        src_space: ColourSpace,
+        dest_format: wgpu::TextureFormat,
+        filter: wgpu::FilterMode,
+        screen: Option<Screen>,
     ) -> Self {
         let render_shader = wgpu
             .device
             .create_shader_module(wgpu::ShaderModuleDescriptor {
                 label: None,
-                source: wgpu::ShaderSource::Wgsl(include_str!("blit.wgsl").into()),
+                source: wgpu::ShaderSource::Wgsl(match &screen {
+                    Some(screen) => screen.source.into(),
+                    None => include_str!("blit.wgsl").into(),
+                }),
             });
+        let uniforms = screen.as_ref().map_or(&[][..], |screen| screen.uniforms);
         let filterable = filter == wgpu::FilterMode::Linear;
+        let uniform_entries = (0..uniforms.len()).map(|i| wgpu::BindGroupLayoutEntry {
+            binding: 2 + i as u32,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
         let render_bind_group_layout =
             wgpu.device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -54,20 +170,30 @@ impl Blitter {
                             }),
                             count: None,
                         },
-                    ],
+                    ]
+                    .into_iter()
+                    .chain(uniform_entries)
+                    .collect::<Vec<_>>(),
                 });
+        let sampler = wgpu.device.create_sampler(&wgpu::SamplerDescriptor {
+            min_filter: filter,
+            mag_filter: filter,
+            ..Default::default()
+        });
         Blitter {
             render_bind_group: wgpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
                 layout: &render_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(src) },
-                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&wgpu.device.create_sampler(&wgpu::SamplerDescriptor {
-                        min_filter: filter,
-                        mag_filter: filter,
-                        ..Default::default()
-                    })) },
-                ],
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) },
+                ]
+                .into_iter()
+                .chain(uniforms.iter().enumerate().map(|(i, buffer)| wgpu::BindGroupEntry {
+                    binding: 2 + i as u32,
+                    resource: buffer.as_entire_binding(),
+                }))
+                .collect::<Vec<_>>(),
             }),
             render_pipeline: wgpu.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: None,
@@ -85,6 +211,15 @@ impl Blitter {
                 fragment: Some(wgpu::FragmentState {
                     module: &render_shader,
                     entry_point: match (src_space, dest_format) {
+                        _ if screen.is_some() => {
+                            let post_process = screen.as_ref().is_some_and(|s| s.post_process);
+                            match (post_process, dest_format) {
+                                (false, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Rgba8Unorm) => "fs_screen_linear_to_srgb",
+                                (false, _) => "fs_screen",
+                                (true, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Rgba8Unorm) => "fs_post_linear_to_srgb",
+                                (true, _) => "fs_post",
+                            }
+                        }
                         // FIXME use sRGB viewFormats instead once the API stabilises
                         (ColourSpace::Linear, wgpu::TextureFormat::Bgra8Unorm) => "fs_main_linear_to_srgb",
                         (ColourSpace::Linear, wgpu::TextureFormat::Rgba8Unorm) => "fs_main_linear_to_srgb",
@@ -107,13 +242,25 @@ impl Blitter {
     }
 
     pub fn blit(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        self.blit_viewport(encoder, view, wgpu::Color::GREEN, None);
+    }
+
+    /// Blit into the given `[x, y, width, height]` rectangle of `view`, or all of it if `None`,
+    /// clearing the rest to `background`.
+    pub fn blit_viewport(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        background: wgpu::Color,
+        viewport: Option<[f32; 4]>,
+    ) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                    load: wgpu::LoadOp::Clear(background),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -121,6 +268,9 @@ impl Blitter {
             occlusion_query_set: None,
             timestamp_writes: None,
         });
+        if let Some([x, y, width, height]) = viewport {
+            render_pass.set_viewport(x, y, width, height, 0., 1.);
+        }
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.render_bind_group, &[]);
         render_pass.draw(0..3, 0..1);
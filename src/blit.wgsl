@@ -40,6 +40,103 @@ fn linear_to_srgb(rgb: vec3<f32>) -> vec3<f32> {
         rgb <= vec3<f32>(0.0031308));
 }
 
+struct Output {
+    exposure: f32,
+    tonemap: u32,
+    dither: u32,
+    premultiply: u32,
+    gamut: u32,
+};
+
+// only bound for the final blit to the surface
+@group(0) @binding(2) var<uniform> output: Output;
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(rgb: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    var v = clamp(log2(max(inset * rgb, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = agx_contrast((v - min_ev) / (max_ev - min_ev));
+    return pow(max(outset * v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+// the order of operators matches `Tonemap`
+fn tonemap(rgb: vec3<f32>) -> vec3<f32> {
+    let x = max(rgb * output.exposure, vec3<f32>(0.0));
+    switch output.tonemap {
+        case 1u: { return x / (1.0 + x); }
+        case 2u: { return aces(x); }
+        case 3u: { return agx(x); }
+        default: { return x; }
+    }
+}
+
+// convert from linear sRGB to the primaries of the surface, in the order of `Gamut`,
+// before tonemapping so that colours outside the sRGB gamut aren't clipped
+fn to_gamut(rgb: vec3<f32>) -> vec3<f32> {
+    if (output.gamut == 1u) {
+        let srgb_to_p3 = mat3x3<f32>(
+            0.8224621, 0.0331941, 0.0170827,
+            0.1775380, 0.9668058, 0.0723974,
+            0.0, 0.0, 0.9105199);
+        return srgb_to_p3 * rgb;
+    }
+    return rgb;
+}
+
+// encode as sRGB, offset by up to half an 8-bit step by a 4x4 Bayer matrix if dithering
+fn encode_dithered(rgb: vec3<f32>, position: vec2<f32>) -> vec3<f32> {
+    var bayer = array<f32, 16>(0., 8., 2., 10., 12., 4., 14., 6., 3., 11., 1., 9., 15., 7., 13., 5.);
+    let p = vec2<u32>(position) % 4u;
+    let offset = select(0.0, ((bayer[p.y * 4u + p.x] + 0.5) / 16.0 - 0.5) / 255.0, output.dither != 0u);
+    return linear_to_srgb(rgb) + offset;
+}
+
+// for surfaces that encode as sRGB themselves
+fn linear_dithered(rgb: vec3<f32>, position: vec2<f32>) -> vec3<f32> {
+    if (output.dither == 0u) {
+        return rgb;
+    }
+    return srgb_to_linear(encode_dithered(rgb, position));
+}
+
+// multiply by alpha for surfaces composited with premultiplied alpha
+fn with_alpha(rgb: vec3<f32>, alpha: f32) -> vec4<f32> {
+    let a = clamp(alpha, 0.0, 1.0);
+    return vec4<f32>(select(rgb, rgb * a, output.premultiply != 0u), a);
+}
+
+@fragment
+fn fs_screen(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rgba = textureSample(r_color, r_sampler, in.tex_coords);
+    return with_alpha(linear_dithered(tonemap(to_gamut(rgba.rgb)), in.position.xy), rgba.a);
+}
+
+@fragment
+fn fs_screen_linear_to_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rgba = textureSample(r_color, r_sampler, in.tex_coords);
+    return with_alpha(encode_dithered(tonemap(to_gamut(rgba.rgb)), in.position.xy), rgba.a);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(r_color, r_sampler, in.tex_coords);
@@ -0,0 +1,19 @@
+fn screen_sample(uv: vec2<f32>) -> vec4<f32> {
+    return textureSample(r_color, r_sampler, uv);
+}
+
+fn screen_size() -> vec2<f32> {
+    return vec2<f32>(textureDimensions(r_color));
+}
+
+@fragment
+fn fs_post(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rgba = post_process(in.tex_coords);
+    return with_alpha(linear_dithered(tonemap(to_gamut(rgba.rgb)), in.position.xy), rgba.a);
+}
+
+@fragment
+fn fs_post_linear_to_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rgba = post_process(in.tex_coords);
+    return with_alpha(encode_dithered(tonemap(to_gamut(rgba.rgb)), in.position.xy), rgba.a);
+}
@@ -0,0 +1,80 @@
+use crate::context::WgpuContext;
+use crate::WgpuToyRenderer;
+
+/// The number of channels unless set with `RendererBuilder::channels`, `channel0` and `channel1`.
+pub(crate) const DEFAULT_CHANNEL_COUNT: usize = 2;
+
+/// Creates a renderer with the options that size its textures and buffers, which are
+/// expensive to change afterwards, e.g.
+/// `RendererBuilder::new(wgpu).pass_f32(true).channels(4).storage_bytes(1 << 20).build()`.
+pub struct RendererBuilder {
+    pub(crate) wgpu: WgpuContext,
+    pub(crate) pass_f32: bool,
+    pub(crate) pass_count: u32,
+    pub(crate) channels: usize,
+    pub(crate) storage_bytes: Option<u64>,
+}
+
+impl RendererBuilder {
+    pub fn new(wgpu: WgpuContext) -> Self {
+        RendererBuilder {
+            wgpu,
+            pass_f32: false,
+            pass_count: crate::DEFAULT_PASS_COUNT,
+            channels: DEFAULT_CHANNEL_COUNT,
+            storage_bytes: None,
+        }
+    }
+
+    /// Store pass textures as `rgba32float` rather than `rgba16float`, as `set_pass_f32` does.
+    pub fn pass_f32(mut self, pass_f32: bool) -> Self {
+        self.pass_f32 = pass_f32;
+        self
+    }
+
+    /// The number of pass layers of shaders without `#pass_count`, 4 by default.
+    pub fn pass_count(mut self, pass_count: u32) -> Self {
+        self.pass_count = pass_count;
+        self
+    }
+
+    /// The number of channels, bound as `channel0` onwards, 2 by default.
+    pub fn channels(mut self, channels: usize) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// The size in bytes of `#storage` buffers of runtime-sized arrays declared without an
+    /// element count, as `set_storage_buffer_size` sets.
+    pub fn storage_bytes(mut self, bytes: u64) -> Self {
+        self.storage_bytes = Some(bytes);
+        self
+    }
+
+    /// Create the renderer, unless the options exceed the device's limits.
+    pub fn build(self) -> Result<WgpuToyRenderer, String> {
+        let limits = self.wgpu.device.limits();
+        let max_passes = limits.max_texture_array_layers;
+        if self.pass_count == 0 || self.pass_count > max_passes {
+            return Err(format!("Pass count must be between 1 and {max_passes}"));
+        }
+        // the pass textures and screen mips are also sampled
+        let max_channels =
+            (limits.max_sampled_textures_per_shader_stage as usize).saturating_sub(2);
+        if self.channels > max_channels {
+            return Err(format!(
+                "There can be at most {max_channels} channels on this device"
+            ));
+        }
+        let max_storage = limits.max_storage_buffer_binding_size as u64;
+        if self
+            .storage_bytes
+            .is_some_and(|bytes| bytes == 0 || bytes > max_storage)
+        {
+            return Err(format!(
+                "Storage buffer size must be between 1 and {max_storage} bytes"
+            ));
+        }
+        Ok(WgpuToyRenderer::from_builder(self))
+    }
+}
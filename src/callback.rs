@@ -0,0 +1,107 @@
+//! The callbacks the host registers to hear about the shader as it compiles and runs. Without
+//! one, what it would have been told is logged.
+
+use crate::{Diagnostic, WgpuToyRenderer};
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+pub(crate) struct SuccessCallback(Option<Rc<dyn Fn(Vec<String>)>>);
+
+impl SuccessCallback {
+    pub fn call(&self, entry_points: Vec<String>) {
+        match &self.0 {
+            None => log::info!("Entry points: {entry_points:?}"),
+            Some(callback) => callback(entry_points),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct WarningCallback(Option<Rc<dyn Fn(Diagnostic)>>);
+
+impl WarningCallback {
+    pub fn call(&self, diagnostic: Diagnostic) {
+        match &self.0 {
+            None => log::warn!(
+                "{}:{}: {}",
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.summary()
+            ),
+            Some(callback) => callback(diagnostic),
+        }
+    }
+}
+
+type DebugPrintFn = dyn Fn(u32, &str);
+
+#[derive(Clone, Default)]
+pub(crate) struct DebugPrintCallback(Option<Rc<DebugPrintFn>>);
+
+impl DebugPrintCallback {
+    pub fn call(&self, tag: u32, message: &str) {
+        match &self.0 {
+            None => log::info!("debug_print {tag}: {message}"),
+            Some(callback) => callback(tag, message),
+        }
+    }
+}
+
+type NanFn = dyn Fn(&str, u32, u32);
+
+#[derive(Clone, Default)]
+pub(crate) struct NanCallback(Option<Rc<NanFn>>);
+
+impl NanCallback {
+    pub fn call(&self, texture: &str, x: u32, y: u32) {
+        match &self.0 {
+            None => log::warn!("Non-finite value in {texture} at ({x}, {y})"),
+            Some(callback) => callback(texture, x, y),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct DeviceRestoredCallback(Option<Rc<dyn Fn()>>);
+
+impl DeviceRestoredCallback {
+    pub fn call(&self) {
+        match &self.0 {
+            None => log::info!("Device restored"),
+            Some(callback) => callback(),
+        }
+    }
+}
+
+impl WgpuToyRenderer {
+    /// Register a callback taking the entry points of each shader that compiles.
+    pub fn on_success(&mut self, callback: impl Fn(Vec<String>) + 'static) {
+        self.on_success_cb = SuccessCallback(Some(Rc::new(callback)));
+    }
+
+    /// Register a callback taking a `Diagnostic` for non-fatal problems found when compiling.
+    pub fn on_warning(&mut self, callback: impl Fn(Diagnostic) + 'static) {
+        self.on_warning_cb = WarningCallback(Some(Rc::new(callback)));
+    }
+
+    /// Register a callback `(tag, message)` for the values recorded by the shader with
+    /// `debug_print_f32`, `debug_print_u32`, `debug_print_i32` and `debug_print_vec4f`. These
+    /// are read back every 100 frames, up to 1024 at a time, so prints should be limited to a
+    /// few threads.
+    pub fn on_debug_print(&mut self, callback: impl Fn(u32, &str) + 'static) {
+        self.on_debug_print_cb = DebugPrintCallback(Some(Rc::new(callback)));
+    }
+
+    /// Register a callback `(texture, x, y)` for the first non-finite value found in the screen
+    /// (`"screen"`) or a pass layer (`"pass 0"` onwards) when `set_nan_check` is enabled.
+    pub fn on_nan(&mut self, callback: impl Fn(&str, u32, u32) + 'static) {
+        self.on_nan_cb = NanCallback(Some(Rc::new(callback)));
+    }
+
+    /// Register a callback for when the device has been replaced after being lost, e.g. by a
+    /// driver reset, and the shader recompiled on the new one. Pass textures and storage
+    /// buffers start from zero again, as on `reset`.
+    pub fn on_device_restored(&mut self, callback: impl Fn() + 'static) {
+        self.on_device_restored_cb = DeviceRestoredCallback(Some(Rc::new(callback)));
+    }
+}
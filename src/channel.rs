@@ -0,0 +1,341 @@
+//! The textures bound as channels, loaded from images, audio, or storage the shader writes.
+
+use crate::context::WgpuContext;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils;
+use crate::{bind, blit, ChannelInfo, WgpuToyRenderer};
+
+/// What a channel was loaded from, to load it again onto a new device if the device is lost.
+#[derive(Clone)]
+pub(crate) enum ChannelSource {
+    Image(Vec<u8>),
+    Hdr(Vec<u8>),
+    Storage {
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    },
+    /// Written each frame by `set_channel_audio`
+    Audio,
+}
+
+/// The number of values in each row of an audio channel, as on Shadertoy.
+pub const AUDIO_SAMPLES: usize = 512;
+
+impl WgpuToyRenderer {
+    fn set_channel(&mut self, index: usize, binding: bind::TextureBinding) {
+        self.texture_urls.remove(&index);
+        self.channel_sources.remove(&index);
+        if self.bindings.set_channel(index, binding) {
+            self.recompile();
+        } else {
+            self.rebuild_bind_groups();
+        }
+    }
+
+    pub fn channel_info(&self, index: usize) -> Option<ChannelInfo> {
+        self.bindings
+            .channels
+            .get(index)
+            .map(bind::TextureBinding::info)
+    }
+
+    pub fn load_channel(&mut self, index: usize, bytes: &[u8]) {
+        let now = instant::Instant::now();
+        if let Err(e) = self.load_channel_source(index, ChannelSource::Image(bytes.to_vec())) {
+            log::error!("load_channel: {e}");
+        }
+        log::info!("Channel {index} loaded in {}s", now.elapsed().as_secs_f32());
+    }
+
+    pub fn load_channel_hdr(&mut self, index: usize, bytes: &[u8]) -> Result<(), String> {
+        let now = instant::Instant::now();
+        self.load_channel_source(index, ChannelSource::Hdr(bytes.to_vec()))?;
+        log::info!("Channel {index} loaded in {}s", now.elapsed().as_secs_f32());
+        Ok(())
+    }
+
+    fn load_channel_source(&mut self, index: usize, source: ChannelSource) -> Result<(), String> {
+        let binding = self.channel_binding(index, &source)?;
+        self.set_channel(index, binding);
+        self.channel_sources.insert(index, source);
+        Ok(())
+    }
+
+    /// Create the texture of a channel on the current device.
+    pub(crate) fn channel_binding(
+        &self,
+        index: usize,
+        source: &ChannelSource,
+    ) -> Result<bind::TextureBinding, String> {
+        let max_size = self.wgpu.device.limits().max_texture_dimension_2d;
+        let check_size = |width: u32, height: u32| {
+            if width == 0 || height == 0 || width > max_size || height > max_size {
+                Err(format!("Channel textures must be between 1x1 and {max_size}x{max_size} on this device, not {width}x{height}"))
+            } else {
+                Ok(())
+            }
+        };
+        let (rgba, width, height, format, colour_space, blit_format) = match source {
+            ChannelSource::Image(bytes) => {
+                let im = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+                let (width, height) = (im.width(), im.height());
+                (
+                    im.to_rgba8().into_raw(),
+                    width,
+                    height,
+                    wgpu::TextureFormat::Rgba8UnormSrgb,
+                    blit::ColourSpace::Linear,
+                    wgpu::TextureFormat::Rgba8UnormSrgb,
+                )
+            }
+            ChannelSource::Hdr(bytes) => {
+                let decoder =
+                    image::codecs::hdr::HdrDecoder::new(&bytes[..]).map_err(|e| e.to_string())?;
+                let meta = decoder.metadata();
+                let pixels = decoder.read_image_native().map_err(|e| e.to_string())?;
+                (
+                    pixels
+                        .iter()
+                        .flat_map(|p| [p.c[0], p.c[1], p.c[2], p.e])
+                        .collect(),
+                    meta.width,
+                    meta.height,
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    blit::ColourSpace::Rgbe,
+                    wgpu::TextureFormat::Rgba16Float,
+                )
+            }
+            &ChannelSource::Storage {
+                width,
+                height,
+                format,
+            } => {
+                check_size(width, height)?;
+                let texture = self.wgpu.device.create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                return Ok(bind::TextureBinding::storage_channel(index, texture));
+            }
+            ChannelSource::Audio => {
+                let texture = self.wgpu.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("audio"),
+                    size: wgpu::Extent3d {
+                        width: AUDIO_SAMPLES as u32,
+                        height: 2,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::R8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                return Ok(bind::TextureBinding::channel(index, texture));
+            }
+        };
+        check_size(width, height)?;
+        let texture = blit::Blitter::new(
+            &self.wgpu,
+            &create_texture_from_image(&self.wgpu, &rgba, width, height, format)
+                .create_view(&Default::default()),
+            colour_space,
+            blit_format,
+            wgpu::FilterMode::Linear,
+        )
+        .create_texture(
+            &self.wgpu,
+            width,
+            height,
+            1 + (std::cmp::max(width, height) as f32).log2() as u32,
+        );
+        Ok(bind::TextureBinding::channel(index, texture))
+    }
+
+    /// Download an image and load it into a channel.
+    /// URLs ending in `.hdr` are decoded as Radiance HDR images.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn load_channel_url(&mut self, index: usize, url: &str) -> Result<(), String> {
+        let bytes = utils::fetch_bytes(url.to_string()).await?;
+        self.load_channel_bytes(index, url, &bytes)
+    }
+
+    pub(crate) fn load_channel_bytes(
+        &mut self,
+        index: usize,
+        url: &str,
+        bytes: &[u8],
+    ) -> Result<(), String> {
+        if index >= self.bindings.channels.len() {
+            return Err(format!("Channel {index} does not exist"));
+        }
+        if url.ends_with(".hdr") {
+            self.load_channel_hdr(index, bytes)
+        } else {
+            self.load_channel(index, bytes);
+            Ok(())
+        }
+    }
+
+    /// The texture bound to a channel, e.g. to show a thumbnail of it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn channel_texture(&self, index: usize) -> Option<&wgpu::Texture> {
+        self.bindings.channels.get(index).map(|c| c.texture())
+    }
+
+    /// Show audio in a channel as Shadertoy does, as a texture 512 texels wide with the
+    /// spectrum in its top row and the waveform in its bottom row, sampled at `y = 0.25` and
+    /// `y = 0.75`. Both are given as 512 values between 0 and 1, where the waveform is centred
+    /// on 0.5, and missing values are zero. The channel becomes an audio channel the first time.
+    pub fn set_channel_audio(
+        &mut self,
+        index: usize,
+        spectrum: &[f32],
+        waveform: &[f32],
+    ) -> Result<(), String> {
+        if index >= self.bindings.channels.len() {
+            return Err(format!("Channel {index} does not exist"));
+        }
+        if !matches!(self.channel_sources.get(&index), Some(ChannelSource::Audio)) {
+            self.load_channel_source(index, ChannelSource::Audio)?;
+        }
+        let texels: Vec<u8> = [spectrum, waveform]
+            .iter()
+            .flat_map(|row| {
+                (0..AUDIO_SAMPLES)
+                    .map(|i| (row.get(i).copied().unwrap_or(0.).clamp(0., 1.) * 255.).round() as u8)
+            })
+            .collect();
+        let texture = self.bindings.channels[index].texture();
+        self.wgpu.queue.write_texture(
+            texture.as_image_copy(),
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(AUDIO_SAMPLES as u32),
+                rows_per_image: Some(2),
+            },
+            texture.size(),
+        );
+        Ok(())
+    }
+
+    /// Release a channel's texture, resetting it to the default blank texture.
+    pub fn clear_channel(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.bindings.channels.len() {
+            return Err(format!("Channel {index} does not exist"));
+        }
+        let texture = bind::blank_texture(&self.wgpu);
+        self.set_channel(index, bind::TextureBinding::channel(index, texture));
+        Ok(())
+    }
+
+    /// Replace a channel with a read-write storage texture that persists across frames and resizes.
+    pub fn create_channel_storage(
+        &mut self,
+        index: usize,
+        width: u32,
+        height: u32,
+        format: &str,
+    ) -> Result<(), String> {
+        if index >= self.bindings.channels.len() {
+            return Err(format!("Channel {index} does not exist"));
+        }
+        let format = bind::parse_storage_format(format)
+            .ok_or(format!("Unsupported storage texture format {format}"))?;
+        if !matches!(
+            format,
+            wgpu::TextureFormat::R32Float
+                | wgpu::TextureFormat::R32Uint
+                | wgpu::TextureFormat::R32Sint
+        ) && !self
+            .wgpu
+            .device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            return Err(format!(
+                "Read-write storage textures with format {format:?} are not supported by this device"
+            ));
+        }
+        self.load_channel_source(
+            index,
+            ChannelSource::Storage {
+                width,
+                height,
+                format,
+            },
+        )
+    }
+}
+
+/// Zero every layer of a texture.
+pub(crate) fn zero_texture(wgpu: &WgpuContext, texture: &wgpu::Texture) {
+    let size = texture.size();
+    let row_bytes = size.width * texture.format().block_copy_size(None).unwrap_or(4);
+    wgpu.queue.write_texture(
+        texture.as_image_copy(),
+        &vec![0; (row_bytes * size.height * size.depth_or_array_layers) as usize],
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(row_bytes),
+            rows_per_image: Some(size.height),
+        },
+        size,
+    );
+}
+
+pub(crate) fn create_texture_from_image(
+    wgpu: &WgpuContext,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    let texture = wgpu.device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        label: None,
+        view_formats: &[],
+    });
+    wgpu.queue.write_texture(
+        texture.as_image_copy(),
+        rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * width),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    texture
+}
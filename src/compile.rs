@@ -0,0 +1,1467 @@
+//! Compiling shaders: preprocessing, the prelude declaring the bindings, validation, and the
+//! pipelines, bind groups and defines, overrides and storage buffers they are built from.
+
+#[cfg(feature = "export")]
+use crate::export;
+use crate::pipeline::{self, create_compute_pipeline, ComputePipeline};
+use crate::preprocessor::{self, SourceMap, WGSLError};
+#[cfg(feature = "spirv")]
+use crate::spirv;
+use crate::{
+    bind, create_screen_mip_blitters, error_message, on_scope_error, reflect, CompileStats,
+    Diagnostic, WgpuToyRenderer, SHADER_ERROR,
+};
+use lazy_regex::regex;
+use std::collections::HashMap;
+use std::mem::take;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+
+/// The helper functions of the prelude, which don't depend on the bindings.
+const PRELUDE_FUNCTIONS: &str = r#"
+fn keyDown(keycode: uint) -> bool {
+    return ((_keyboard[keycode / 128u][(keycode % 128u) / 32u] >> (keycode % 32u)) & 1u) == 1u;
+}
+
+fn signalFinished() {
+    atomicStore(&_finished, 1u);
+}
+
+fn assert(index: int, success: bool) {
+    if (!success) {
+        atomicAdd(&_debug.assert_counts[index], 1u);
+    }
+}
+
+fn _debug_print(tag: uint, kind: uint, value: uint4) {
+    let index = atomicAdd(&_debug.print_count, 1u);
+    if (index < _MAX_DEBUG_PRINTS) {
+        _debug.prints[index] = DebugPrint(tag, kind, array<uint,4>(value.x, value.y, value.z, value.w));
+    }
+}
+
+fn debug_print_f32(tag: uint, value: float) {
+    _debug_print(tag, 0u, uint4(bitcast<uint>(value), 0u, 0u, 0u));
+}
+
+fn debug_print_u32(tag: uint, value: uint) {
+    _debug_print(tag, 1u, uint4(value, 0u, 0u, 0u));
+}
+
+fn debug_print_i32(tag: uint, value: int) {
+    _debug_print(tag, 2u, uint4(bitcast<uint>(value), 0u, 0u, 0u));
+}
+
+fn debug_print_vec4f(tag: uint, value: float4) {
+    _debug_print(tag, 3u, bitcast<uint4>(value));
+}
+
+"#;
+
+impl WgpuToyRenderer {
+    /// The entry points of the current shader, with how they are dispatched and the bindings
+    /// they use, along with the custom uniforms and storage buffers, as JSON.
+    pub fn reflection_json(&self) -> String {
+        let names = reflect::binding_names(&format!(
+            "{}\n{}",
+            self.bindings.to_wgsl(),
+            self.source.source
+        ));
+        let (custom_names, custom_values) = &self.bindings.custom.host;
+        let reflection = reflect::ShaderReflection {
+            entry_points: self
+                .compute_pipelines
+                .iter()
+                .map(|p| reflect::EntryPointReflection {
+                    name: p.name.clone(),
+                    label: p.label.clone(),
+                    enabled: !self.disabled_entry_points.contains(&p.name),
+                    workgroup_size: p.workgroup_size,
+                    workgroup_count: p.workgroup_count(self.screen_width, self.screen_height),
+                    dispatch_count: p.dispatch_count,
+                    dispatch_once: p.dispatch_once,
+                    dispatch_every: p.dispatch_every,
+                    bindings: p
+                        .bindings
+                        .iter()
+                        .map(|&binding| reflect::BindingReflection {
+                            binding,
+                            name: names.get(&binding).cloned().unwrap_or_default(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            custom_uniforms: custom_names
+                .iter()
+                .zip(custom_values)
+                .filter(|(name, _)| *name != "_dummy")
+                .map(|(name, &value)| {
+                    let param = self.source.params.iter().find(|p| &p.name == name);
+                    reflect::UniformReflection {
+                        name: name.clone(),
+                        value,
+                        default: param.map(|p| p.default),
+                        min: param.and_then(|p| p.range).map(|r| r.0),
+                        max: param.and_then(|p| p.range).map(|r| r.1),
+                        step: param.and_then(|p| p.step),
+                    }
+                })
+                .collect(),
+            storage: self
+                .storage_buffers
+                .iter()
+                .filter(|buffer| buffer.view_of.is_none())
+                .map(|buffer| reflect::StorageReflection {
+                    name: buffer.name.clone(),
+                    size: buffer.size,
+                })
+                .collect(),
+        };
+        serde_json::to_string(&reflection).unwrap()
+    }
+
+    /// The aliases, structs, bindings and functions declared by the prelude, as JSON.
+    pub fn prelude_json(&self) -> String {
+        reflect::describe_prelude(&self.prelude())
+    }
+
+    /// The type aliases and the structs of the uniforms, shared by the prelude and
+    /// post-processing shaders.
+    pub(crate) fn prelude_types(&self) -> String {
+        let (custom_names, _) = &self.bindings.custom.host;
+        prelude_types(custom_names)
+    }
+
+    pub fn prelude(&self) -> String {
+        let (custom_names, _) = &self.bindings.custom.host;
+        let mut s = prelude_types(custom_names);
+        s.push_str(&data_struct(&self.bindings.user_data.host));
+        s.push_str(&self.prelude_body());
+        s
+    }
+
+    /// The prelude besides the types, which depend on the `#param` and `#data` directives of
+    /// the shader being compiled.
+    fn prelude_body(&self) -> String {
+        let mut s = String::new();
+        s.push_str(&format!(
+            r#"const _MAX_DEBUG_PRINTS = {}u;
+struct DebugPrint {{ tag: uint, kind: uint, value: array<uint,4> }}
+struct Debug {{
+    assert_counts: array<atomic<u32>,{}>,
+    print_count: atomic<u32>,
+    prints: array<DebugPrint,_MAX_DEBUG_PRINTS>,
+}}
+"#,
+            bind::MAX_DEBUG_PRINTS,
+            bind::NUM_ASSERT_COUNTERS
+        ));
+        s.push_str(&self.bindings.to_wgsl());
+        s.push_str(PRELUDE_FUNCTIONS);
+        s.push_str(&self.pass_prelude());
+        if self.pass_f32 {
+            // https://iquilezles.org/articles/hwinterpolation/
+            s.push_str(
+                r#"
+    let res = float2(textureDimensions(pass_in));
+    let st = uv * res - 0.5;
+    let iuv = floor(st);
+    let fuv = fract(st);
+    let a = textureSampleLevel(pass_in, nearest, fract((iuv + float2(0.5,0.5)) / res), passLayer(pass_index), lod);
+    let b = textureSampleLevel(pass_in, nearest, fract((iuv + float2(1.5,0.5)) / res), passLayer(pass_index), lod);
+    let c = textureSampleLevel(pass_in, nearest, fract((iuv + float2(0.5,1.5)) / res), passLayer(pass_index), lod);
+    let d = textureSampleLevel(pass_in, nearest, fract((iuv + float2(1.5,1.5)) / res), passLayer(pass_index), lod);
+    return mix(mix(a, b, fuv.x), mix(c, d, fuv.x), fuv.y);
+"#,
+            );
+        } else {
+            s.push_str(
+                r#"
+    return textureSampleLevel(pass_in, bilinear, fract(uv), passLayer(pass_index), lod);
+"#,
+            );
+        }
+        s.push('}');
+        s
+    }
+
+    /// The prelude to compile a shader with, if it uses one.
+    fn source_prelude(&self, source: &SourceMap) -> String {
+        if source.prelude {
+            self.prelude()
+        } else {
+            String::new()
+        }
+    }
+
+    /// A shader with its extensions and prelude, and the number of lines they add. The prelude
+    /// leaves out its declarations of host buffers that the shader now declares itself.
+    fn compose(&self, source: &SourceMap) -> (String, usize) {
+        let prelude: String = self
+            .source_prelude(source)
+            .split_inclusive('\n')
+            .filter(|line| {
+                !self.storage_buffers.iter().any(|b| {
+                    !b.decl.is_empty()
+                        && line.trim_end().ends_with(&format!("{};", b.decl))
+                        && source.storage.iter().any(|(name, _)| *name == b.name)
+                })
+            })
+            .collect();
+        let prelude = format!("{}{prelude}", source.extensions);
+        let prelude_len = count_newlines(&prelude);
+        (prelude + &source.source, prelude_len)
+    }
+
+    /// The pass texture helpers, which route layers declared with `#pass_format` to their own textures.
+    fn pass_prelude(&self) -> String {
+        let layers = &self.bindings.pass_layers;
+        let mut s = String::from("\nfn passLayer(pass_index: int) -> int {\n    return pass_index");
+        for layer in layers {
+            s.push_str(&format!(" - int(pass_index > {})", layer.index));
+        }
+        s.push_str(";\n}\n");
+        if layers.is_empty() {
+            s.push_str(
+                r#"
+fn passStore(pass_index: int, coord: int2, value: float4) {
+    textureStore(pass_out, coord, pass_index, value);
+}
+
+fn passLoad(pass_index: int, coord: int2, lod: int) -> float4 {
+    return textureLoad(pass_in, coord, pass_index, lod);
+}
+
+fn passSampleLevelBilinearRepeat(pass_index: int, uv: float2, lod: float) -> float4 {"#,
+            );
+            return s;
+        }
+        s.push_str("\nfn passStore(pass_index: int, coord: int2, value: float4) {\n    switch pass_index {\n");
+        for layer in layers {
+            let scalar = bind::sample_scalar(layer.read.texture().format());
+            s.push_str(&format!(
+                "        case {i}: {{ textureStore(pass_out_{i}, coord, vec4<{scalar}>(value)); }}\n",
+                i = layer.index
+            ));
+        }
+        s.push_str(
+            r#"        default: { textureStore(pass_out, coord, passLayer(pass_index), value); }
+    }
+}
+
+fn passLoad(pass_index: int, coord: int2, lod: int) -> float4 {
+    switch pass_index {
+"#,
+        );
+        for layer in layers {
+            s.push_str(&format!(
+                "        case {i}: {{ return float4(textureLoad(pass_in_{i}, coord, lod)); }}\n",
+                i = layer.index
+            ));
+        }
+        let indices = itertools::Itertools::join(&mut layers.iter().map(|layer| layer.index), ", ");
+        s.push_str(&format!(
+            r#"        default: {{ return textureLoad(pass_in, coord, passLayer(pass_index), lod); }}
+    }}
+}}
+
+fn passSampleLevelBilinearRepeat(pass_index: int, uv: float2, lod: float) -> float4 {{
+    switch pass_index {{
+        case {indices}: {{
+            let res = int2(textureDimensions(pass_in));
+            let st = uv * float2(res) - 0.5;
+            let iuv = int2(floor(st));
+            let fuv = fract(st);
+            let a = passLoad(pass_index, (iuv % res + res) % res, 0);
+            let b = passLoad(pass_index, ((iuv + int2(1,0)) % res + res) % res, 0);
+            let c = passLoad(pass_index, ((iuv + int2(0,1)) % res + res) % res, 0);
+            let d = passLoad(pass_index, ((iuv + int2(1,1)) % res + res) % res, 0);
+            return mix(mix(a, b, fuv.x), mix(c, d, fuv.x), fuv.y);
+        }}
+        default: {{}}
+    }}"#
+        ));
+        s
+    }
+
+    fn handle_success(&self, entry_points: Vec<String>) {
+        self.on_success_cb.call(entry_points);
+    }
+
+    /// Report a problem with the shader that doesn't prevent it from running.
+    fn handle_warning(&self, diagnostic: Diagnostic) {
+        self.on_warning_cb.call(diagnostic);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn preprocess_async(&self, shader: &str) -> Option<SourceMap> {
+        let shader = shader.to_owned();
+        let defines = self.defines();
+        preprocessor::Preprocessor::new(defines)
+            .with_include_resolver(self.include_resolver.clone())
+            .run(&shader)
+            .await
+    }
+
+    /// Preprocess, parse and validate a shader against the prelude it would be compiled with,
+    /// without compiling it or touching the current pipelines.
+    pub(crate) fn check(&self, shader: &str) -> impl std::future::Future<Output = Vec<Diagnostic>> {
+        let shader = shader.to_owned();
+        let preprocessor = preprocessor::Preprocessor::new(self.defines())
+            .with_include_resolver(self.include_resolver.clone());
+        let body = self.prelude_body();
+        // the custom uniforms set by the host rather than by the current shader's `#param`s
+        let (custom_names, _) = &self.bindings.custom.host;
+        let host_names: Vec<String> = custom_names
+            .iter()
+            .filter(|&name| name != "_dummy" && !self.source.params.iter().any(|p| &p.name == name))
+            .cloned()
+            .collect();
+        let host_decls: Vec<(String, String)> = self
+            .storage_buffers
+            .iter()
+            .filter(|b| !b.decl.is_empty())
+            .map(|b| (b.name.clone(), format!("{};", b.decl)))
+            .collect();
+        async move {
+            let mut preprocessor = preprocessor;
+            let source = match preprocessor.check(&shader).await {
+                Ok(source) => source,
+                Err(e) => return vec![e.diagnostic()],
+            };
+            // naga doesn't parse `enable` directives yet
+            if !source.extensions.is_empty() {
+                return vec![];
+            }
+            let mut custom_names = host_names;
+            for param in &source.params {
+                if !custom_names.contains(&param.name) {
+                    custom_names.push(param.name.clone());
+                }
+            }
+            if custom_names.is_empty() {
+                custom_names.push("_dummy".into());
+            }
+            // leave out the declarations of host buffers which the shader now declares itself
+            let body = body
+                .lines()
+                .filter(|line| {
+                    !host_decls.iter().any(|(name, decl)| {
+                        line.ends_with(decl.as_str())
+                            && source.storage.iter().any(|(n, _)| n == name)
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let prelude = format!(
+                "{}{}{body}",
+                prelude_types(&custom_names),
+                data_struct(&source.user_data)
+            );
+            let prelude_len = count_newlines(&prelude);
+            match reflect::validate(&(prelude + &source.source)) {
+                Ok(_) => vec![],
+                Err(e) => vec![source.locate(shader_diagnostic(e), prelude_len)],
+            }
+        }
+    }
+
+    /// Preprocess, parse and validate a shader without compiling it, returning the errors found.
+    /// Shaders which enable extensions are only preprocessed, as naga can't parse them yet.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn check_shader_async(&self, shader: &str) -> Vec<Diagnostic> {
+        self.check(shader).await
+    }
+
+    /// Resolve `#include "name"` directives with a callback returning the source. Includes it
+    /// doesn't resolve are fetched from the default location on the web, and natively are read
+    /// from `./include`, or fetched if they are URLs.
+    pub fn set_include_resolver(&mut self, resolver: impl Fn(&str) -> Option<String> + 'static) {
+        self.include_resolver = Some(preprocessor::IncludeResolver(Rc::new(move |name| {
+            let source = resolver(name);
+            Box::pin(async move { source })
+        })));
+    }
+
+    pub(crate) fn defines(&self) -> HashMap<String, String> {
+        let mut defines = self.defines.clone();
+        defines.insert("SCREEN_WIDTH".to_owned(), self.screen_width.to_string());
+        defines.insert("SCREEN_HEIGHT".to_owned(), self.screen_height.to_string());
+        if self.supports_f16() {
+            defines.insert("SHADER_F16".to_owned(), "1".to_owned());
+        }
+        defines
+    }
+
+    /// Whether shaders can `enable f16;`, in which case `SHADER_F16` is defined.
+    pub fn supports_f16(&self) -> bool {
+        self.wgpu
+            .device
+            .features()
+            .contains(wgpu::Features::SHADER_F16)
+    }
+
+    /// The adapter's name and backend, with the features and the limits relevant to compute
+    /// shaders of the device, as JSON, e.g. to show which optional features a GPU supports.
+    pub fn get_device_info(&self) -> String {
+        let info = &self.wgpu.adapter_info;
+        let limits = self.wgpu.device.limits();
+        macro_rules! limits_json {
+            ($($field:ident),*) => {
+                serde_json::json!({ $(stringify!($field): limits.$field),* })
+            };
+        }
+        serde_json::json!({
+            "name": info.name,
+            "vendor": info.vendor,
+            "device": info.device,
+            "device_type": format!("{:?}", info.device_type),
+            "driver": info.driver,
+            "driver_info": info.driver_info,
+            "backend": info.backend.to_str(),
+            "features": self
+                .wgpu
+                .device
+                .features()
+                .iter_names()
+                .map(|(name, _)| name.to_lowercase())
+                .collect::<Vec<_>>(),
+            "limits": limits_json!(
+                max_texture_dimension_2d,
+                max_texture_dimension_3d,
+                max_texture_array_layers,
+                max_bindings_per_bind_group,
+                max_sampled_textures_per_shader_stage,
+                max_storage_buffers_per_shader_stage,
+                max_storage_textures_per_shader_stage,
+                max_uniform_buffer_binding_size,
+                max_storage_buffer_binding_size,
+                max_buffer_size,
+                min_storage_buffer_offset_alignment,
+                max_compute_workgroup_storage_size,
+                max_compute_invocations_per_workgroup,
+                max_compute_workgroup_size_x,
+                max_compute_workgroup_size_y,
+                max_compute_workgroup_size_z,
+                max_compute_workgroups_per_dimension
+            ),
+        })
+        .to_string()
+    }
+
+    /// Define a name for `#ifdef` and substitution, e.g. to select a quality variant.
+    /// Takes effect the next time a shader is preprocessed.
+    pub fn set_define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn remove_define(&mut self, name: &str) {
+        self.defines.remove(name);
+    }
+
+    /// Compile a SPIR-V module, e.g. from rust-gpu or glslang. As the prelude isn't included,
+    /// the module must declare the resources it uses in group 0 at the bindings the prelude would
+    /// declare them at.
+    #[cfg(feature = "spirv")]
+    pub fn set_shader_spirv(&mut self, spirv: &[u8]) {
+        match spirv::to_wgsl(spirv) {
+            Ok(wgsl) => self.compile(SourceMap::without_prelude(&wgsl)),
+            Err(e) => WGSLError::handler(&e, 0, 0),
+        }
+    }
+
+    pub fn compile(&mut self, mut source: SourceMap) {
+        let now = instant::Instant::now();
+        let pass_count = source.pass_count.unwrap_or(self.pass_count);
+        let max_pass_count = self.wgpu.device.limits().max_texture_array_layers;
+        if pass_count == 0 || pass_count > max_pass_count {
+            WGSLError::handler(
+                &format!("Pass count must be between 1 and {max_pass_count}"),
+                0,
+                0,
+            );
+            return;
+        }
+        if let Some(index) = source.pass_formats.keys().find(|&&i| i >= pass_count) {
+            WGSLError::handler(
+                &format!("#pass_format refers to pass {index}, but the pass count is {pass_count}"),
+                0,
+                0,
+            );
+            return;
+        }
+        self.bindings.user_data.host = source.user_data.clone();
+        self.register_params(&source.params);
+        if regex!(r"\bf16\b").is_match(&source.extensions) && !self.supports_f16() {
+            WGSLError::handler("f16 is not supported by this device", 0, 0);
+            return;
+        }
+        let parse_start = instant::Instant::now();
+        let (mut wgsl, mut prelude_len) = self.compose(&source);
+        let mut reflection = match reflect_shader(&source, &wgsl) {
+            Ok(reflection) => reflection,
+            Err(e) => {
+                source.report_error(shader_diagnostic(e), prelude_len);
+                return;
+            }
+        };
+        let storage_buffers = match &reflection {
+            Some(reflection) => match self.storage_buffers_for(&source, reflection) {
+                Ok(buffers) => buffers,
+                Err(e) => {
+                    WGSLError::handler(&e, 0, 0);
+                    return;
+                }
+            },
+            None => self.storage_buffers.clone(),
+        };
+        let max_binding_size = self.wgpu.device.limits().max_storage_buffer_binding_size as u64;
+        if let Some(buffer) = storage_buffers.iter().find(|b| b.size > max_binding_size) {
+            let re = regex::Regex::new(&format!(
+                r"\bvar<storage,\w+>\s+{}\b",
+                regex::escape(&buffer.name)
+            ))
+            .unwrap();
+            WGSLError::handler(
+                &format!(
+                    "Storage buffer {} is {} bytes, which exceeds this device's limit of {max_binding_size} bytes",
+                    buffer.name, buffer.size
+                ),
+                source.find_line(&re),
+                0,
+            );
+            return;
+        }
+        if source.pass_count != self.source.pass_count
+            || source.pass_formats != self.source.pass_formats
+            || storage_buffers != self.storage_buffers
+        {
+            self.source.pass_count = source.pass_count;
+            self.source.pass_formats = source.pass_formats.clone();
+            self.storage_buffers = storage_buffers;
+            // the pipelines are rebuilt below
+            self.reset_bindings();
+            // the prelude declares the new bindings, so the shader is composed again
+            (wgsl, prelude_len) = self.compose(&source);
+            reflection = match reflect_shader(&source, &wgsl) {
+                Ok(reflection) => reflection,
+                Err(e) => {
+                    source.report_error(shader_diagnostic(e), prelude_len);
+                    return;
+                }
+            };
+        }
+        let parse_ms = parse_start.elapsed().as_secs_f32() * 1e3;
+        let re_entry_point = regex!(r"(?s)@compute.*?@workgroup_size\((.*?)\).*?fn\s+(\w+)");
+        let entry_points: Vec<(String, [u32; 3])> = re_entry_point
+            .captures_iter(&preprocessor::strip_comments(&wgsl))
+            .map(|cap| {
+                // TODO: Handle error if failed to parse the capture
+                let mut sizes = cap[1].split(',').map(|s| s.trim().parse().unwrap_or(1));
+                let workgroup_size: [u32; 3] = std::array::from_fn(|_| sizes.next().unwrap_or(1));
+
+                (cap[2].to_owned(), workgroup_size)
+            })
+            .collect();
+        let directives = source
+            .dispatch_count
+            .keys()
+            .map(|k| ("#dispatch_count", k))
+            .chain(
+                source
+                    .workgroup_count
+                    .keys()
+                    .map(|k| ("#workgroup_count", k)),
+            )
+            .chain(source.dispatch_size.keys().map(|k| ("#dispatch_size", k)))
+            .chain(source.dispatch_once.keys().map(|k| ("#dispatch_once", k)))
+            .chain(source.dispatch_every.keys().map(|k| ("#dispatch_every", k)))
+            .chain(
+                source
+                    .dispatch_indirect
+                    .keys()
+                    .map(|k| ("#dispatch_indirect", k)),
+            );
+        for (directive, name) in directives {
+            if !entry_points
+                .iter()
+                .any(|(entry_point, _)| entry_point == name)
+            {
+                let names = entry_points
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.handle_warning(
+                    Diagnostic::warning(
+                        &format!("{directive} refers to unknown entry point {name}"),
+                        0,
+                        0,
+                    )
+                    .with_fix(Some(format!("The entry points are {names}"))),
+                );
+            }
+        }
+        let max_workgroups = self
+            .wgpu
+            .device
+            .limits()
+            .max_compute_workgroups_per_dimension;
+        let directive_line = |directive, name: &String| {
+            *source
+                .directive_lines
+                .get(&(directive, name.clone()))
+                .unwrap_or(&0)
+        };
+        for (name, count) in source.workgroup_count.iter() {
+            if count.iter().any(|&c| c > max_workgroups) {
+                WGSLError::handler(
+                    &format!("Workgroup count for {name} exceeds the device limit of {max_workgroups} per dimension"),
+                    directive_line("#workgroup_count", name),
+                    0,
+                );
+                return;
+            }
+        }
+        for (name, workgroup_size) in entry_points.iter() {
+            // `#workgroup_count` takes precedence, and has been checked above
+            let size = match source.dispatch_size.get(name) {
+                Some(size) if !source.workgroup_count.contains_key(name) => *size,
+                _ => continue,
+            };
+            let count = pipeline::workgroups_covering(size, *workgroup_size);
+            if count.iter().any(|&c| c > max_workgroups) {
+                WGSLError::handler(
+                    &format!(
+                        "Dispatch size for {name} needs {} workgroups, which exceeds the device limit of {max_workgroups} per dimension",
+                        count.map(|c| c.to_string()).join("x")
+                    ),
+                    directive_line("#dispatch_size", name),
+                    0,
+                );
+                return;
+            }
+        }
+        let dispatches: u32 = entry_points
+            .iter()
+            .map(|(name, _)| *source.dispatch_count.get(name).unwrap_or(&1))
+            .sum();
+        if dispatches as usize > bind::MAX_DISPATCHES {
+            WGSLError::handler(
+                &format!(
+                    "Too many dispatches per frame ({dispatches}), the maximum is {}",
+                    bind::MAX_DISPATCHES
+                ),
+                0,
+                0,
+            );
+            return;
+        }
+        let used_bindings = reflection
+            .as_ref()
+            .map(reflect::Reflection::used_bindings)
+            .unwrap_or_default();
+        self.check_warnings(&source, &entry_points, &used_bindings);
+        let entry_point_names = entry_points.iter().map(|t| t.0.clone()).collect();
+        self.handle_success(entry_point_names);
+        self.shader_overrides = reflection
+            .as_ref()
+            .map(reflect::Reflection::overrides)
+            .unwrap_or_default();
+        let constants = self.pipeline_constants();
+        let pipeline_start = instant::Instant::now();
+        // only the first error is kept, rather than those about the invalid objects that follow
+        self.wgpu
+            .device
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        // natively the validated module is given to wgpu, rather than having it parse the shader
+        // again, while browsers are given the source
+        #[cfg(not(target_arch = "wasm32"))]
+        let shader_source = match reflection {
+            Some(reflection) => {
+                wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(reflection.into_module()))
+            }
+            None => wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(&wgsl)),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let shader_source = wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(&wgsl));
+        let compute_shader = self
+            .wgpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: shader_source,
+            });
+        self.last_compute_pipelines = Some(take(&mut self.compute_pipelines));
+        self.compute_pipelines = entry_points
+            .iter()
+            .map(|entry_point| {
+                let bindings = used_bindings
+                    .get(&entry_point.0)
+                    .cloned()
+                    .unwrap_or_else(|| self.bindings.all_bindings());
+                let bind_group_layout = self
+                    .bindings
+                    .create_bind_group_layout(&self.wgpu, &bindings);
+                let pipeline_layout = self
+                    .bindings
+                    .create_pipeline_layout(&self.wgpu, &bind_group_layout);
+                ComputePipeline {
+                    name: entry_point.0.clone(),
+                    label: source
+                        .pass_names
+                        .get(&entry_point.0)
+                        .unwrap_or(&entry_point.0)
+                        .clone(),
+                    workgroup_size: entry_point.1,
+                    workgroup_count: source.workgroup_count.get(&entry_point.0).cloned(),
+                    dispatch_size: source.dispatch_size.get(&entry_point.0).cloned(),
+                    dispatch_size_override: self
+                        .dispatch_size_overrides
+                        .get(&entry_point.0)
+                        .cloned(),
+                    dispatch_indirect: source.dispatch_indirect.get(&entry_point.0).cloned(),
+                    dispatch_once: pipeline::dispatches_once(&entry_point.0, &source.dispatch_once),
+                    dispatch_count: *source.dispatch_count.get(&entry_point.0).unwrap_or(&1),
+                    dispatch_every: *source.dispatch_every.get(&entry_point.0).unwrap_or(&1),
+                    dynamic_offset: self.bindings.has_dynamic_offset(&bindings),
+                    bindings,
+                    bind_group_layout,
+                    bind_groups: vec![],
+                    pipeline: create_compute_pipeline(
+                        &self.wgpu,
+                        source
+                            .pass_names
+                            .get(&entry_point.0)
+                            .unwrap_or(&entry_point.0),
+                        &pipeline_layout,
+                        &compute_shader,
+                        &entry_point.0,
+                        &constants,
+                    ),
+                }
+            })
+            .collect();
+        let sourcemap = source.clone();
+        let composed = wgsl.clone();
+        on_scope_error(self.wgpu.device.pop_error_scope(), move |e| {
+            SHADER_ERROR.store(true, Ordering::SeqCst);
+            if let Some(e) = reflect::shader_module_error(&e, &composed) {
+                sourcemap.report_error(shader_diagnostic(e), prelude_len);
+                return;
+            }
+            log::error!("{e}");
+            WGSLError::handler(&error_message(&e), 0, 0);
+        });
+        let pipeline_ms = pipeline_start.elapsed().as_secs_f32() * 1e3;
+        self.compute_shader = Some(compute_shader);
+        self.composed_wgsl = wgsl;
+        self.query_set = if !self
+            .wgpu
+            .device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            || self.compute_pipelines.is_empty()
+        {
+            None
+        } else {
+            Some(
+                self.wgpu
+                    .device
+                    .create_query_set(&wgpu::QuerySetDescriptor {
+                        label: None,
+                        count: 2 * self.compute_pipelines.len() as u32,
+                        ty: wgpu::QueryType::Timestamp,
+                    }),
+            )
+        };
+        self.poll_finished = regex!(r"\bsignalFinished\s*\(").is_match(&source.source);
+        self.generate_screen_mips = regex!(r"\bscreen_mips\b").is_match(&source.source);
+        self.compile_stats = CompileStats {
+            preprocess_ms: source.preprocess_ms,
+            parse_ms,
+            pipeline_ms,
+            total_ms: source.preprocess_ms + now.elapsed().as_secs_f32() * 1e3,
+        };
+        log::info!(
+            "Shader compiled in {}s (preprocessing {}ms, parsing {}ms, pipelines {}ms)",
+            now.elapsed().as_micros() as f32 * 1e-6,
+            source.preprocess_ms,
+            parse_ms,
+            pipeline_ms
+        );
+        let textures = take(&mut source.textures);
+        self.source = source;
+        self.rebuild_bind_groups();
+        self.load_textures(textures);
+    }
+
+    /// Load the images given by `#texture` into their channels, unless already loaded.
+    fn load_textures(&mut self, textures: Vec<preprocessor::ChannelTexture>) {
+        for texture in textures {
+            if self.texture_urls.get(&texture.index) == Some(&texture.url) {
+                continue;
+            }
+            let result = if texture.index >= self.bindings.channels.len() {
+                Err(format!("Channel {} does not exist", texture.index))
+            } else if texture.hdr {
+                self.load_channel_hdr(texture.index, &texture.bytes)
+            } else {
+                self.load_channel(texture.index, &texture.bytes);
+                Ok(())
+            };
+            match result {
+                Ok(()) => {
+                    self.texture_urls.insert(texture.index, texture.url);
+                }
+                Err(e) => WGSLError::handler(&format!("{}: {e}", texture.url), 0, 0),
+            }
+        }
+    }
+
+    /// The complete shader, prelude included, that the current pipelines were compiled from.
+    pub fn get_composed_wgsl(&self) -> String {
+        self.composed_wgsl.clone()
+    }
+
+    /// Translate the current shader for use elsewhere: to `spirv` bytecode, or to `hlsl`, `msl`
+    /// or `glsl` source as UTF-8. Overridable constants take the values given by `set_override`.
+    #[cfg(feature = "export")]
+    pub fn export_shader(&self, target: &str) -> Result<Vec<u8>, String> {
+        if self.composed_wgsl.is_empty() {
+            return Err("No shader has been compiled".to_string());
+        }
+        export::export(&self.composed_wgsl, target, &self.pipeline_constants())
+    }
+
+    /// The human-readable name of an entry point, given by a `/// name: ...` comment.
+    pub fn pass_name(&self, entry_point: &str) -> Option<String> {
+        self.compute_pipelines
+            .iter()
+            .find(|p| p.name == entry_point)
+            .map(|p| p.label.clone())
+    }
+
+    /// Skip dispatching an entry point without recompiling. Persists across recompilation.
+    pub fn set_entry_point_enabled(&mut self, entry_point: &str, enabled: bool) {
+        if enabled {
+            self.disabled_entry_points.remove(entry_point);
+        } else {
+            self.disabled_entry_points.insert(entry_point.to_string());
+        }
+    }
+
+    /// Dispatch an entry point over a domain of `x * y * z` invocations, overriding
+    /// `#dispatch_size` and `#workgroup_count`. Persists across recompilation.
+    /// Pass zero to revert to the shader's own dispatch size.
+    pub fn set_dispatch_size(
+        &mut self,
+        entry_point: &str,
+        x: u32,
+        y: u32,
+        z: u32,
+    ) -> Result<(), String> {
+        let size = if x == 0 || y == 0 || z == 0 {
+            None
+        } else {
+            Some([x, y, z])
+        };
+        let max_workgroups = self
+            .wgpu
+            .device
+            .limits()
+            .max_compute_workgroups_per_dimension;
+        if let Some(p) = self
+            .compute_pipelines
+            .iter_mut()
+            .find(|p| p.name == entry_point)
+        {
+            let previous = std::mem::replace(&mut p.dispatch_size_override, size);
+            let count = p.workgroup_count(self.screen_width, self.screen_height);
+            if count.iter().any(|&c| c > max_workgroups) {
+                p.dispatch_size_override = previous;
+                return Err(format!(
+                    "Dispatch size for {entry_point} exceeds the device limit of {max_workgroups} workgroups per dimension"
+                ));
+            }
+        }
+        match size {
+            Some(size) => self
+                .dispatch_size_overrides
+                .insert(entry_point.to_string(), size),
+            None => self.dispatch_size_overrides.remove(entry_point),
+        };
+        Ok(())
+    }
+
+    /// Set a pipeline-overridable constant, declared in WGSL with `override`, by its name or id.
+    /// The pipelines are specialised with the new value without recompiling the shader, failing
+    /// if the current shader declares it with a type the value doesn't fit. Persists across
+    /// recompilation.
+    pub fn set_override(&mut self, name: &str, value: f64) -> Result<(), String> {
+        if let Some(&scalar) = self.shader_overrides.get(name) {
+            reflect::check_override(name, scalar, value)?;
+        }
+        self.overrides.insert(name.to_string(), value);
+        if self.shader_overrides.contains_key(name) {
+            self.specialise_pipelines();
+        }
+        Ok(())
+    }
+
+    /// Revert a pipeline-overridable constant to the default value given in the shader.
+    pub fn remove_override(&mut self, name: &str) {
+        if self.overrides.remove(name).is_some() && self.shader_overrides.contains_key(name) {
+            self.specialise_pipelines();
+        }
+    }
+
+    /// Set the custom uniforms controlled by the host, e.g. with sliders, alongside the
+    /// shader's own `#param`s.
+    pub fn set_custom_floats(&mut self, names: Vec<String>, values: Vec<f32>) {
+        self.bindings.custom.host = (names, values);
+        // keep the shader's own parameters that the host doesn't know about
+        let params = self.source.params.clone();
+        self.register_params(&params);
+        if self.post_process.is_some() {
+            // the uniforms are declared by the post-processing shader too
+            self.screen_blitter = self.create_screen_blitter();
+        }
+    }
+
+    /// Set the number of `pass_in`/`pass_out` texture layers, unless overridden by `#pass_count`.
+    pub fn set_pass_count(&mut self, pass_count: u32) -> Result<(), String> {
+        let max = self.wgpu.device.limits().max_texture_array_layers;
+        if pass_count == 0 || pass_count > max {
+            return Err(format!("Pass count must be between 1 and {max}"));
+        }
+        self.pass_count = pass_count;
+        self.reset();
+        Ok(())
+    }
+
+    /// Add a storage buffer of the given size in bytes, or resize an existing one. Buffers the
+    /// shader doesn't declare with `#storage` are declared in the prelude as `array<u32>`.
+    pub fn add_storage_buffer(&mut self, name: &str, bytes: u64) -> Result<(), String> {
+        let max = self.wgpu.device.limits().max_storage_buffer_binding_size as u64;
+        if bytes == 0 || bytes > max {
+            return Err(format!(
+                "Storage buffer size must be between 1 and {max} bytes"
+            ));
+        }
+        match self.host_storage.iter_mut().find(|(n, _)| n == name) {
+            Some(buffer) => buffer.1 = bytes,
+            None => self.host_storage.push((name.to_string(), bytes)),
+        }
+        self.recompile();
+        Ok(())
+    }
+
+    pub fn remove_storage_buffer(&mut self, name: &str) {
+        self.host_storage.retain(|(n, _)| n != name);
+        self.recompile();
+    }
+
+    /// Set the size in bytes of `#storage` buffers of runtime-sized arrays declared without an
+    /// element count, which otherwise take the device's maximum storage buffer binding size.
+    pub fn set_storage_buffer_size(&mut self, bytes: u64) -> Result<(), String> {
+        let max = self.wgpu.device.limits().max_storage_buffer_binding_size as u64;
+        if bytes == 0 || bytes > max {
+            return Err(format!(
+                "Storage buffer size must be between 1 and {max} bytes"
+            ));
+        }
+        self.storage_buffer_size = Some(bytes);
+        self.recompile();
+        Ok(())
+    }
+
+    /// The storage buffer of the given name, if `len` bytes at `offset` lie within it.
+    /// Both must be multiples of 4.
+    pub(crate) fn storage_buffer_range(
+        &self,
+        name: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<&wgpu::Buffer, String> {
+        let index = self
+            .storage_buffers
+            .iter()
+            .position(|buffer| buffer.name == name)
+            .ok_or(format!("No storage buffer named {name}"))?;
+        let buffer = self.bindings.storage[index].buffer();
+        if !offset.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+            || !len.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+        {
+            return Err("Offset and length must be multiples of 4 bytes".to_string());
+        }
+        if len == 0 || offset + len > buffer.size() {
+            return Err(format!(
+                "{len} bytes at offset {offset} are outside {name}, which is {} bytes",
+                buffer.size()
+            ));
+        }
+        Ok(buffer)
+    }
+
+    /// Store pass textures as `rgba32float` rather than `rgba16float`, for more precision at
+    /// twice the memory. The pass textures are recreated and the current shader recompiled
+    /// straight away, restarting it from frame 0.
+    pub fn set_pass_f32(&mut self, pass_f32: bool) {
+        if pass_f32 == self.pass_f32 {
+            return;
+        }
+        self.pass_f32 = pass_f32;
+        self.reset_bindings();
+        // the prelude samples pass textures differently, even if the layout were unchanged
+        if self.compute_pipelines.is_empty() {
+            self.rebuild_bind_groups();
+        } else {
+            self.recompile();
+        }
+    }
+
+    fn check_warnings(
+        &self,
+        source: &SourceMap,
+        entry_points: &[(String, [u32; 3])],
+        used_bindings: &HashMap<String, Vec<u32>>,
+    ) {
+        for (name, _) in entry_points {
+            if used_bindings.get(name).is_some_and(Vec::is_empty) {
+                let re = regex::Regex::new(&format!(r"\bfn\s+{}\b", regex::escape(name))).unwrap();
+                self.handle_warning(Diagnostic::warning(
+                    &format!("Entry point {name} does not use any bindings, so has no effect"),
+                    source.find_line(&re),
+                    0,
+                ));
+            }
+        }
+        let code = preprocessor::strip_comments(&source.source);
+        let (custom_names, _) = &self.bindings.custom.host;
+        // `_dummy` stands in for the custom uniforms when there are none
+        for name in custom_names.iter().filter(|name| *name != "_dummy") {
+            let re =
+                regex::Regex::new(&format!(r"\bcustom\s*\.\s*{}\b", regex::escape(name))).unwrap();
+            if !re.is_match(&code) {
+                self.handle_warning(
+                    Diagnostic::warning(&format!("Custom uniform {name} is never used"), 0, 0)
+                        .with_fix(Some(format!("Read it as custom.{name}"))),
+                );
+            }
+        }
+        // the largest storage buffer every WebGPU implementation supports
+        let guaranteed_size = wgpu::Limits::default().max_storage_buffer_binding_size as u64;
+        // views share the memory of the buffer they view, which is reported itself
+        let buffers = self.storage_buffers.iter().filter(|b| b.view_of.is_none());
+        for bind::StorageBuffer { name, size, .. } in buffers {
+            if *size > guaranteed_size {
+                let re =
+                    regex::Regex::new(&format!(r"\bvar<storage,\w+>\s+{}\b", regex::escape(name)))
+                        .unwrap();
+                self.handle_warning(Diagnostic::warning(
+                    &format!("Storage buffer {name} is {size} bytes, which exceeds the {guaranteed_size} bytes guaranteed to be supported"),
+                    source.find_line(&re),
+                    0,
+                ));
+            }
+        }
+    }
+
+    /// Recreate the bindings, returning whether their layout changed.
+    pub(crate) fn reset_bindings(&mut self) -> bool {
+        let pass_count = self.source.pass_count.unwrap_or(self.pass_count);
+        self.wgpu
+            .device
+            .push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        let mut bindings = bind::Bindings::new(
+            &self.wgpu,
+            self.screen_width,
+            self.screen_height,
+            self.pass_f32,
+            pass_count,
+            &self.source.pass_formats,
+            &self.storage_buffers,
+        );
+        let usage = bindings.memory_usage();
+        let (width, height) = (self.screen_width, self.screen_height);
+        on_scope_error(self.wgpu.device.pop_error_scope(), move |e| {
+            let mb = |bytes: f64| bytes / (1 << 20) as f64;
+            log::error!(
+                "Out of GPU memory allocating {:.0} MB for {pass_count} passes at {width}x{height}, of which {:.0} MB are pass textures and {:.0} MB storage buffers. Try a lower resolution, fewer passes or disabling pass_f32. {e}",
+                mb(usage.total),
+                mb(usage.passes),
+                mb(usage.storage)
+            );
+        });
+        let layout_changed = bindings.layouts() != self.bindings.layouts();
+        std::mem::swap(&mut self.bindings, &mut bindings);
+        self.bindings.custom.host = bindings.custom.host.clone();
+        self.bindings.user_data.host = bindings.user_data.host.clone();
+        self.bindings.channels = take(&mut bindings.channels);
+        self.pass_swapped = false;
+        self.storage_swapped = false;
+        self.finished.store(false, Ordering::SeqCst);
+        self.screen_blitter = self.create_screen_blitter();
+        self.screen_mip_blitters = create_screen_mip_blitters(&self.wgpu, &self.bindings);
+        layout_changed
+    }
+
+    /// Recreate the bind groups of every pipeline, keeping `pass_in`/`pass_out` and the
+    /// `#storage_double` buffers as they are, so that the shader carries on from its last frame.
+    pub(crate) fn rebuild_bind_groups(&mut self) {
+        let pass_variants = 1 + self.source.pass_ping_pong as usize;
+        let storage_variants = 1 + self.bindings.has_storage_swaps() as usize;
+        // without ping-ponging, whichever texture is bound as `pass_in` is simply left there
+        self.pass_swapped &= pass_variants > 1;
+        self.storage_swapped &= storage_variants > 1;
+        for p in self.compute_pipelines.iter_mut() {
+            p.bind_groups.clear();
+        }
+        let swaps = pipeline::bind_group_swaps(
+            self.pass_swapped,
+            self.storage_swapped,
+            pass_variants,
+            storage_variants,
+        );
+        for (swap_pass, swap_storage) in swaps {
+            if swap_pass {
+                self.bindings.swap_pass_textures();
+            }
+            if swap_storage {
+                self.bindings.swap_storage_buffers();
+            }
+            for p in self.compute_pipelines.iter_mut() {
+                p.bind_groups.push(self.bindings.create_bind_group(
+                    &self.wgpu,
+                    &p.bind_group_layout,
+                    &p.bindings,
+                ));
+            }
+            if swap_pass {
+                self.bindings.swap_pass_textures();
+            }
+            if swap_storage {
+                self.bindings.swap_storage_buffers();
+            }
+        }
+    }
+
+    /// The storage buffers a shader declares, sized by their types, element counts and the
+    /// host, followed by those the host added that the shader doesn't declare.
+    fn storage_buffers_for(
+        &self,
+        source: &SourceMap,
+        reflection: &reflect::Reflection,
+    ) -> Result<Vec<bind::StorageBuffer>, String> {
+        let default_size = self
+            .storage_buffer_size
+            .unwrap_or(self.wgpu.device.limits().max_storage_buffer_binding_size as u64);
+        let sizes = reflection.storage_sizes(&source.storage, default_size)?;
+        let host_size = |name: &str| {
+            self.host_storage
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|&(_, size)| size)
+        };
+        let declared = source.storage.iter().zip(sizes).map(|((name, _), size)| {
+            match source.storage_views.get(name) {
+                Some(buffer) => bind::StorageBuffer {
+                    name: name.clone(),
+                    size: 0,
+                    decl: String::new(),
+                    view_of: Some(buffer.clone()),
+                    read_only: false,
+                    swap_with: None,
+                },
+                None => {
+                    let double = source.storage_double.iter().find_map(|d| {
+                        match name.strip_prefix(d.as_str())? {
+                            "_read" => Some((true, format!("{d}_write"))),
+                            "_write" => Some((false, format!("{d}_read"))),
+                            _ => None,
+                        }
+                    });
+                    bind::StorageBuffer {
+                        name: name.clone(),
+                        size: host_size(name).unwrap_or(size),
+                        decl: String::new(),
+                        view_of: None,
+                        read_only: double.as_ref().is_some_and(|(read, _)| *read),
+                        swap_with: double.map(|(_, other)| other),
+                    }
+                }
+            }
+        });
+        let host_only = self
+            .host_storage
+            .iter()
+            .filter(|(name, _)| !source.storage.iter().any(|(n, _)| n == name))
+            .map(|(name, size)| bind::StorageBuffer {
+                name: name.clone(),
+                size: *size,
+                decl: format!("var<storage,read_write> {name}: array<u32>"),
+                view_of: None,
+                read_only: false,
+                swap_with: None,
+            });
+        Ok(declared.chain(host_only).collect())
+    }
+
+    /// Add the custom uniforms declared with `#param` that the host hasn't set already,
+    /// and remove those of the previous shader that are no longer declared.
+    pub(crate) fn register_params(&mut self, params: &[preprocessor::Param]) {
+        let (names, values) = &mut self.bindings.custom.host;
+        for old in &self.source.params {
+            if !params.iter().any(|p| p.name == old.name) {
+                if let Some(i) = names.iter().position(|n| n == &old.name) {
+                    names.remove(i);
+                    values.remove(i);
+                }
+            }
+        }
+        for param in params {
+            if !names.contains(&param.name) {
+                names.push(param.name.clone());
+                values.push(param.default);
+            }
+        }
+        // the placeholder is only needed to avoid an empty struct
+        if names.len() > 1 {
+            if let Some(i) = names.iter().position(|n| n == "_dummy") {
+                names.remove(i);
+                values.remove(i);
+            }
+        }
+        if names.is_empty() {
+            names.push("_dummy".into());
+            values.push(0.);
+        }
+    }
+
+    /// Values of the overridable constants the current shader declares.
+    fn pipeline_constants(&self) -> HashMap<String, f64> {
+        self.overrides
+            .iter()
+            .filter(|(name, _)| self.shader_overrides.contains_key(*name))
+            .map(|(name, &value)| (name.clone(), value))
+            .collect()
+    }
+
+    /// Recreate the pipelines of the current shader module with new override values. Like
+    /// `compile`, if they can't be created the error is reported and the current pipelines are
+    /// restored at the start of the next frame.
+    fn specialise_pipelines(&mut self) {
+        let Some(module) = &self.compute_shader else {
+            return;
+        };
+        let constants = self.pipeline_constants();
+        self.wgpu
+            .device
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = self
+            .compute_pipelines
+            .iter()
+            .map(|p| p.specialise(&self.wgpu, &self.bindings, module, &constants))
+            .collect();
+        self.last_compute_pipelines =
+            Some(std::mem::replace(&mut self.compute_pipelines, pipelines));
+        on_scope_error(self.wgpu.device.pop_error_scope(), |e| {
+            SHADER_ERROR.store(true, Ordering::SeqCst);
+            log::error!("{e}");
+            WGSLError::handler(&error_message(&e), 0, 0);
+        });
+        self.rebuild_bind_groups();
+    }
+
+    /// Rebuild the pipelines of the current shader, e.g. after the prelude has changed.
+    pub(crate) fn recompile(&mut self) {
+        if !self.compute_pipelines.is_empty() {
+            self.compile(self.source.clone());
+        }
+    }
+}
+
+/// The type aliases and the structs of the uniforms, with the given custom uniforms.
+fn prelude_types(custom_names: &[String]) -> String {
+    let mut s = String::new();
+    for (a, t) in [("int", "i32"), ("uint", "u32"), ("float", "f32")] {
+        s.push_str(&format!("alias {a} = {t};\n"));
+    }
+    for (a, t) in [
+        ("int", "i32"),
+        ("uint", "u32"),
+        ("float", "f32"),
+        ("bool", "bool"),
+    ] {
+        for n in 2..5 {
+            s.push_str(&format!("alias {a}{n} = vec{n}<{t}>;\n"));
+        }
+    }
+    for n in 2..5 {
+        for m in 2..5 {
+            s.push_str(&format!("alias float{n}x{m} = mat{n}x{m}<f32>;\n"));
+        }
+    }
+    s.push_str(
+        r#"
+struct Time { frame: uint, elapsed: float, delta: float }
+struct Mouse { pos: uint2, click: int }
+struct DispatchInfo { id: uint }
+struct DispatchIndirect { x: uint, y: uint, z: uint }
+"#,
+    );
+    s.push_str("struct Custom {\n");
+    for name in custom_names {
+        s.push_str("    ");
+        s.push_str(name);
+        s.push_str(": float,\n");
+    }
+    s.push_str("};\n");
+    s
+}
+
+/// The struct of the data given by `#data` directives.
+fn data_struct(user_data: &indexmap::IndexMap<String, Vec<u32>>) -> String {
+    let mut s = String::from("struct Data {\n");
+    for (key, val) in user_data.iter() {
+        let n = val.len();
+        s.push_str(&format!("    {key}: array<u32,{n}>,\n"));
+    }
+    s.push_str("};\n");
+    s
+}
+
+/// Parse and validate a composed shader, unless it enables extensions, which naga can't parse
+/// yet, so are left for the browser to validate.
+fn reflect_shader(
+    source: &SourceMap,
+    wgsl: &str,
+) -> Result<Option<reflect::Reflection>, reflect::ShaderError> {
+    if !source.extensions.is_empty() {
+        return Ok(None);
+    }
+    reflect::validate(wgsl).map(Some)
+}
+
+/// The diagnostic reporting a shader error, located in the composed shader.
+fn shader_diagnostic(e: reflect::ShaderError) -> Diagnostic {
+    let (row, col) = e.location.unwrap_or((0, 0));
+    Diagnostic::error(&e.message, row, col)
+        .with_end(e.end)
+        .with_fix(e.fix)
+}
+
+// https://llogiq.github.io/2016/09/24/newline.html
+fn count_newlines(s: &str) -> usize {
+    s.as_bytes().iter().filter(|&&c| c == b'\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{init_wgpu_headless, DeviceProfile};
+    use std::cell::RefCell;
+
+    /// A headless renderer, or `None` where there is no GPU to test with.
+    fn renderer() -> Option<WgpuToyRenderer> {
+        let wgpu = pollster::block_on(init_wgpu_headless(
+            64,
+            64,
+            DeviceProfile::Full,
+            Default::default(),
+        ))
+        .ok()?;
+        Some(WgpuToyRenderer::new(wgpu))
+    }
+
+    /// The messages of the warnings found when compiling a shader.
+    fn warnings(wgputoy: &mut WgpuToyRenderer, shader: &str) -> Vec<String> {
+        let warnings = Rc::new(RefCell::new(vec![]));
+        let sink = warnings.clone();
+        wgputoy.on_warning(move |diagnostic| sink.borrow_mut().push(diagnostic.message()));
+        let source = pollster::block_on(wgputoy.preprocess_async(shader)).unwrap();
+        wgputoy.compile(source);
+        warnings.take()
+    }
+
+    #[test]
+    fn custom_uniform_warnings() {
+        let Some(mut wgputoy) = renderer() else {
+            return;
+        };
+        let shader = "\
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    textureStore(screen, id.xy, float4(1.));
+}
+";
+        let custom = |warnings: Vec<String>| {
+            warnings
+                .into_iter()
+                .filter(|message| message.starts_with("Custom uniform"))
+                .collect::<Vec<_>>()
+        };
+        assert!(custom(warnings(&mut wgputoy, shader)).is_empty());
+        let shader = format!("#param brightness 0.5\n{shader}");
+        assert_eq!(
+            custom(warnings(&mut wgputoy, &shader)),
+            ["Custom uniform brightness is never used"]
+        );
+    }
+
+    #[test]
+    fn describes_the_prelude() {
+        let user_data = indexmap::IndexMap::from([("lut".to_string(), vec![0; 4])]);
+        // the bindings are declared as by `Bindings::to_wgsl`, which needs a device
+        let prelude = format!(
+            "{}{}{}\n{PRELUDE_FUNCTIONS}",
+            prelude_types(&["speed".to_string()]),
+            data_struct(&user_data),
+            [
+                "@group(0) @binding(0) var<uniform> time: Time;",
+                "@group(0) @binding(1) var<storage,read> data: Data;",
+                "@group(0) @binding(2) var<storage,read_write> _debug: Debug;",
+            ]
+            .join("\n"),
+        );
+        let description: serde_json::Value =
+            serde_json::from_str(&reflect::describe_prelude(&prelude)).unwrap();
+        let find = |list: &str, name: &str| {
+            description[list]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|item| item["name"] == name)
+                .cloned()
+        };
+        assert_eq!(find("aliases", "float4").unwrap()["type"], "vec4<f32>");
+        assert_eq!(
+            find("structs", "Custom").unwrap()["members"],
+            serde_json::json!([{ "name": "speed", "type": "float" }])
+        );
+        assert_eq!(
+            find("structs", "Data").unwrap()["members"],
+            serde_json::json!([{ "name": "lut", "type": "array<u32,4>" }])
+        );
+        assert_eq!(
+            find("bindings", "data").unwrap(),
+            serde_json::json!({
+                "binding": 1,
+                "name": "data",
+                "space": "storage,read",
+                "type": "Data",
+            })
+        );
+        assert_eq!(find("bindings", "time").unwrap()["space"], "uniform");
+        assert!(find("bindings", "_debug").is_none());
+        let key_down = find("functions", "keyDown").unwrap();
+        assert_eq!(
+            key_down["params"],
+            serde_json::json!([{ "name": "keycode", "type": "uint" }])
+        );
+        assert_eq!(key_down["returns"], "bool");
+        assert!(find("functions", "signalFinished").unwrap()["returns"].is_null());
+        assert!(find("functions", "_debug_print").is_none());
+    }
+}
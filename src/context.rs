@@ -6,20 +6,248 @@ use raw_window_handle::{
     WebDisplayHandle, WebWindowHandle, WindowHandle,
 };
 
+/// How frames are presented, see `wgpu::PresentMode`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Wait for vertical blank, the default
+    Fifo,
+    /// Replace the queued frame without tearing, dropping frames if rendering is faster
+    Mailbox,
+    /// Present immediately, which may tear
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+/// The features and limits requested of the adapter, to check that shaders work on weaker
+/// devices than the one at hand.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceProfile {
+    /// Every feature the adapter supports, with the limits every WebGPU implementation
+    /// supports, the default
+    Full,
+    /// No optional features, e.g. `f16` or filtering float32 textures
+    Baseline,
+    /// No optional features, and the lower limits of downlevel devices such as those
+    /// only supporting GLES 3.1 or D3D11, e.g. 2048x2048 textures
+    Downlevel,
+}
+
+impl DeviceProfile {
+    fn features(self, adapter: &wgpu::Adapter) -> wgpu::Features {
+        match self {
+            DeviceProfile::Full => adapter.features(),
+            DeviceProfile::Baseline | DeviceProfile::Downlevel => wgpu::Features::empty(),
+        }
+    }
+
+    fn limits(self) -> wgpu::Limits {
+        match self {
+            DeviceProfile::Full | DeviceProfile::Baseline => wgpu::Limits::default(),
+            DeviceProfile::Downlevel => wgpu::Limits::downlevel_defaults(),
+        }
+    }
+}
+
+/// Which of the system's adapters to request a device from, where there are several.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdapterOptions {
+    /// The graphics APIs to choose from, or the primary ones (Vulkan, Metal, DX12 and WebGPU)
+    /// if `None`
+    pub backends: Option<wgpu::Backends>,
+    /// The index of the adapter in `list_adapters`, or part of its name ignoring case. Only
+    /// supported natively
+    pub adapter: Option<String>,
+    /// The kind of adapter to prefer, or that of the `WGPU_POWER_PREF` environment variable
+    /// (`low`, `high` or `none`) if `None`, which defaults to high performance
+    pub power_preference: Option<wgpu::PowerPreference>,
+}
+
+impl AdapterOptions {
+    fn backends(&self) -> wgpu::Backends {
+        self.backends.unwrap_or(wgpu::Backends::PRIMARY)
+    }
+}
+
+/// What frames are drawn to.
+pub enum Target {
+    /// A surface, shared with requests for a new device that must be able to present to it
+    Surface(Arc<wgpu::Surface<'static>>),
+    /// A texture drawn to instead of a surface when headless, with the size and format of
+    /// `surface_config`
+    Offscreen(wgpu::Texture),
+    /// Nothing, once the renderer has been destroyed
+    Detached,
+}
+
 pub struct WgpuContext {
     #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
     pub event_loop: Option<winit::event_loop::EventLoop<()>>,
+    /// The window of the surface, or `None` when headless
     #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
-    pub window: winit::window::Window,
+    pub window: Option<winit::window::Window>,
+    /// The canvas of the surface, or `None` when headless
+    #[cfg(target_arch = "wasm32")]
+    pub canvas: Option<web_sys::HtmlCanvasElement>,
+    /// The instance the device was requested from, to request another if it is lost
+    pub instance: Arc<wgpu::Instance>,
+    pub profile: DeviceProfile,
+    pub adapter_options: AdapterOptions,
+    pub adapter_info: wgpu::AdapterInfo,
     pub device: Arc<wgpu::Device>,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface<'static>,
+    pub target: Target,
     pub surface_config: wgpu::SurfaceConfiguration,
+    pub surface_capabilities: wgpu::SurfaceCapabilities,
+}
+
+/// A device requested to replace one that was lost, see `WgpuContext::request_new_device`.
+pub struct NewDevice {
+    adapter_info: wgpu::AdapterInfo,
+    device: Arc<wgpu::Device>,
+    queue: wgpu::Queue,
+    /// What the surface supports with the new adapter, unless headless
+    surface_capabilities: Option<wgpu::SurfaceCapabilities>,
+}
+
+impl WgpuContext {
+    /// Request a device with the same profile and adapter options as the current one, from an
+    /// adapter that can present to the surface, e.g. once it is lost.
+    pub fn request_new_device(
+        &self,
+    ) -> impl std::future::Future<Output = Result<NewDevice, String>> + 'static {
+        let instance = self.instance.clone();
+        let profile = self.profile;
+        let options = self.adapter_options.clone();
+        let surface = match &self.target {
+            Target::Surface(surface) => Some(surface.clone()),
+            Target::Offscreen(_) | Target::Detached => None,
+        };
+        async move {
+            let (adapter, device, queue) =
+                request_device(&instance, surface.as_deref(), profile, &options).await?;
+            Ok(NewDevice {
+                adapter_info: adapter.get_info(),
+                device,
+                queue,
+                surface_capabilities: surface.map(|surface| surface.get_capabilities(&adapter)),
+            })
+        }
+    }
+
+    /// Switch to a device from `request_new_device`, configuring the surface for it. Every
+    /// other resource created on the previous device must be recreated.
+    pub fn replace_device(&mut self, new: NewDevice) {
+        self.adapter_info = new.adapter_info;
+        self.device = new.device;
+        self.queue = new.queue;
+        if let Some(capabilities) = new.surface_capabilities {
+            // the new adapter may not support how the surface was configured for the old one
+            let config = &mut self.surface_config;
+            if !capabilities.formats.contains(&config.format) {
+                config.format = preferred_framebuffer_format(&capabilities.formats);
+                config.view_formats = vec![
+                    config.format.add_srgb_suffix(),
+                    config.format.remove_srgb_suffix(),
+                ];
+            }
+            if !capabilities.present_modes.contains(&config.present_mode) {
+                config.present_mode = wgpu::PresentMode::Fifo;
+            }
+            if !capabilities.alpha_modes.contains(&config.alpha_mode) {
+                config.alpha_mode = capabilities.alpha_modes[0];
+            }
+            self.surface_capabilities = capabilities;
+        }
+        self.configure_surface();
+    }
+
+    /// The number of physical pixels per logical pixel of the window or canvas, e.g. 2 on a
+    /// Retina display, or 1 when headless.
+    pub fn pixel_ratio(&self) -> f32 {
+        #[cfg(target_arch = "wasm32")]
+        if self.canvas.is_some() {
+            return web_sys::window().map_or(1., |window| window.device_pixel_ratio() as f32);
+        }
+        #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
+        if let Some(window) = &self.window {
+            return window.scale_factor() as f32;
+        }
+        1.
+    }
+
+    /// Apply changes to `surface_config`, by reconfiguring the surface or recreating the
+    /// offscreen texture.
+    pub fn configure_surface(&mut self) {
+        match &mut self.target {
+            Target::Surface(surface) => surface.configure(&self.device, &self.surface_config),
+            Target::Offscreen(texture) => {
+                texture.destroy();
+                *texture = create_offscreen_texture(&self.device, &self.surface_config);
+            }
+            Target::Detached => (),
+        }
+    }
+
+    /// Stop drawing to the window or canvas, releasing the surface, or the offscreen texture
+    /// when headless. The window is closed, while the canvas is left for the page to reuse.
+    pub fn detach(&mut self) {
+        if let Target::Offscreen(texture) = &self.target {
+            texture.destroy();
+        }
+        self.target = Target::Detached;
+        #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
+        {
+            self.window = None;
+        }
+        #[cfg(target_arch = "wasm32")]
+        if let Some(canvas) = self.canvas.take() {
+            use wasm_bindgen::JsCast;
+            // so that another context can be configured for the canvas
+            if let Ok(Some(context)) = canvas.get_context("webgpu") {
+                if let Some(unconfigure) = js_sys::Reflect::get(&context, &"unconfigure".into())
+                    .ok()
+                    .and_then(|f| f.dyn_into::<js_sys::Function>().ok())
+                {
+                    let _ = unconfigure.call0(&context);
+                }
+            }
+        }
+    }
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &config.view_formats,
+    })
 }
 
 #[cfg(target_arch = "wasm32")]
 struct CanvasWindow {
     id: u32,
+    canvas: web_sys::HtmlCanvasElement,
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -62,7 +290,7 @@ fn init_window(bind_id: &str) -> Result<CanvasWindow, Box<dyn std::error::Error>
     canvas
         .set_attribute("data-raw-handle", "42")
         .or(Err("cannot set attribute"))?;
-    Ok(CanvasWindow { id: 42 })
+    Ok(CanvasWindow { id: 42, canvas })
 }
 
 #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
@@ -80,7 +308,13 @@ fn init_window(
 }
 
 #[cfg(feature = "winit")]
-pub async fn init_wgpu(width: u32, height: u32, bind_id: &str) -> Result<WgpuContext, String> {
+pub async fn init_wgpu(
+    width: u32,
+    height: u32,
+    bind_id: &str,
+    profile: DeviceProfile,
+    adapter_options: AdapterOptions,
+) -> Result<WgpuContext, String> {
     #[cfg(not(target_arch = "wasm32"))]
     let event_loop = winit::event_loop::EventLoop::new().map_err(|e| e.to_string())?;
     #[cfg(not(target_arch = "wasm32"))]
@@ -92,44 +326,24 @@ pub async fn init_wgpu(width: u32, height: u32, bind_id: &str) -> Result<WgpuCon
 
     #[cfg(target_arch = "wasm32")]
     let window = init_window(bind_id).map_err(|e| e.to_string())?;
+    // the window is sized natively, while on the web it is the canvas with id `bind_id`
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = bind_id;
+    #[cfg(target_arch = "wasm32")]
+    let _ = (width, height);
 
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::PRIMARY,
-        dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
-        flags: wgpu::InstanceFlags::default(),
-        gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
-    });
+    let instance = create_instance(adapter_options.backends());
 
     let surface = unsafe {
         instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(&window).unwrap())
     }
     .map_err(|e| e.to_string())?;
 
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            force_fallback_adapter: false,
-            compatible_surface: Some(&surface),
-        })
-        .await
-        .ok_or("unable to create adapter")?;
-
-    log::info!("adapter.features = {:#?}", adapter.features());
-    log::info!("adapter.limits = {:#?}", adapter.limits());
-
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("GPU Device"),
-                required_features: adapter.features(),
-                required_limits: wgpu::Limits::default(),
-            },
-            None,
-        )
-        .await
-        .map_err(|e| e.to_string())?;
+    let (adapter, device, queue) =
+        request_device(&instance, Some(&surface), profile, &adapter_options).await?;
 
-    let surface_format = preferred_framebuffer_format(&surface.get_capabilities(&adapter).formats);
+    let surface_capabilities = surface.get_capabilities(&adapter);
+    let surface_format = preferred_framebuffer_format(&surface_capabilities.formats);
     let surface_config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
@@ -144,20 +358,191 @@ pub async fn init_wgpu(width: u32, height: u32, bind_id: &str) -> Result<WgpuCon
         desired_maximum_frame_latency: 1,
     };
     surface.configure(&device, &surface_config);
+    // the surface is only shared across threads natively, where it is `Sync`
+    #[cfg_attr(target_arch = "wasm32", allow(clippy::arc_with_non_send_sync))]
+    let surface = Arc::new(surface);
 
     Ok(WgpuContext {
         #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
         event_loop: Some(event_loop),
         #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
-        window,
-        device: Arc::new(device),
+        window: Some(window),
+        #[cfg(target_arch = "wasm32")]
+        canvas: Some(window.canvas.clone()),
+        instance,
+        profile,
+        adapter_options,
+        adapter_info: adapter.get_info(),
+        device,
+        queue,
+        target: Target::Surface(surface),
+        surface_config,
+        surface_capabilities,
+    })
+}
+
+/// Create a context which draws to an offscreen texture of the given size, rather than to a
+/// window or canvas, e.g. to render thumbnails on a server.
+pub async fn init_wgpu_headless(
+    width: u32,
+    height: u32,
+    profile: DeviceProfile,
+    adapter_options: AdapterOptions,
+) -> Result<WgpuContext, String> {
+    #[cfg(target_arch = "wasm32")]
+    crate::utils::set_panic_hook();
+
+    let instance = create_instance(adapter_options.backends());
+    let (adapter, device, queue) =
+        request_device(&instance, None, profile, &adapter_options).await?;
+
+    let surface_capabilities = wgpu::SurfaceCapabilities {
+        formats: vec![
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureFormat::Rgba16Float,
+        ],
+        present_modes: vec![wgpu::PresentMode::Fifo],
+        alpha_modes: vec![
+            wgpu::CompositeAlphaMode::Opaque,
+            wgpu::CompositeAlphaMode::PostMultiplied,
+        ],
+        usages: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+    };
+    let surface_config = wgpu::SurfaceConfiguration {
+        usage: surface_capabilities.usages,
+        format: surface_capabilities.formats[0],
+        width,
+        height,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![],
+        desired_maximum_frame_latency: 1,
+    };
+    let texture = create_offscreen_texture(&device, &surface_config);
+
+    Ok(WgpuContext {
+        #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
+        event_loop: None,
+        #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
+        window: None,
+        #[cfg(target_arch = "wasm32")]
+        canvas: None,
+        instance,
+        profile,
+        adapter_options,
+        adapter_info: adapter.get_info(),
+        device,
         queue,
-        surface,
+        target: Target::Offscreen(texture),
         surface_config,
+        surface_capabilities,
     })
 }
 
-fn preferred_framebuffer_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+// the instance is only shared across threads natively, where it is `Sync`
+#[cfg_attr(target_arch = "wasm32", allow(clippy::arc_with_non_send_sync))]
+fn create_instance(backends: wgpu::Backends) -> Arc<wgpu::Instance> {
+    Arc::new(wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
+        flags: wgpu::InstanceFlags::default(),
+        gles_minor_version: wgpu::Gles3MinorVersion::Automatic,
+    }))
+}
+
+/// The adapters available with the given backends, or the primary ones, in the order
+/// `AdapterOptions::adapter` indexes them.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_adapters(backends: Option<wgpu::Backends>) -> Vec<wgpu::AdapterInfo> {
+    let backends = backends.unwrap_or(wgpu::Backends::PRIMARY);
+    create_instance(backends)
+        .enumerate_adapters(backends)
+        .iter()
+        .map(wgpu::Adapter::get_info)
+        .collect()
+}
+
+/// The adapter with the given index in `list_adapters`, or whose name contains the given text.
+#[cfg(not(target_arch = "wasm32"))]
+fn select_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+    backends: wgpu::Backends,
+    selector: &str,
+) -> Result<wgpu::Adapter, String> {
+    let adapters = instance.enumerate_adapters(backends);
+    let adapter = match selector.parse::<usize>() {
+        Ok(index) => adapters.into_iter().nth(index),
+        Err(_) => {
+            let name = selector.to_lowercase();
+            adapters
+                .into_iter()
+                .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name))
+        }
+    }
+    .ok_or_else(|| format!("No adapter matches {selector}"))?;
+    if compatible_surface.is_some_and(|surface| !adapter.is_surface_supported(surface)) {
+        return Err(format!(
+            "{} can't present to the window",
+            adapter.get_info().name
+        ));
+    }
+    Ok(adapter)
+}
+
+async fn request_device(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+    profile: DeviceProfile,
+    options: &AdapterOptions,
+) -> Result<(wgpu::Adapter, Arc<wgpu::Device>, wgpu::Queue), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let selected = match &options.adapter {
+        Some(selector) => Some(select_adapter(
+            instance,
+            compatible_surface,
+            options.backends(),
+            selector,
+        )?),
+        None => None,
+    };
+    #[cfg(target_arch = "wasm32")]
+    let selected = None;
+    let adapter = match selected {
+        Some(adapter) => adapter,
+        None => instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: options
+                    .power_preference
+                    .or_else(wgpu::util::power_preference_from_env)
+                    .unwrap_or(wgpu::PowerPreference::HighPerformance),
+                force_fallback_adapter: false,
+                compatible_surface,
+            })
+            .await
+            .ok_or("unable to create adapter")?,
+    };
+
+    log::info!("adapter.features = {:#?}", adapter.features());
+    log::info!("adapter.limits = {:#?}", adapter.limits());
+
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("GPU Device"),
+                required_features: profile.features(&adapter),
+                required_limits: profile.limits(),
+            },
+            None,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((adapter, Arc::new(device), queue))
+}
+
+pub fn preferred_framebuffer_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
     for &format in formats {
         if matches!(
             format,
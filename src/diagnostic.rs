@@ -0,0 +1,139 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The shader can't run
+    Error,
+    /// The shader runs, but probably not as intended
+    Warning,
+}
+
+/// A problem with a shader, located in the source the user wrote. Lines and columns are
+/// 1-based, where 0 means the location isn't known. Problems in an include are located at the
+/// `#include` directive, with `file`, `file_line` and `file_column` giving where in the include.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) end_line: usize,
+    pub(crate) end_column: usize,
+    /// The include the problem is in, or `"prelude"`
+    pub(crate) file: Option<String>,
+    pub(crate) file_line: usize,
+    pub(crate) file_column: usize,
+    pub(crate) fix: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The line the problem ends on, the same as `line` if only its start is known.
+    pub fn end_line(&self) -> usize {
+        self.end_line
+    }
+
+    pub fn end_column(&self) -> usize {
+        self.end_column
+    }
+
+    /// The name of the include the problem is in, `"prelude"` if it is in the prelude, or
+    /// `undefined` if it is in the shader itself.
+    pub fn file(&self) -> Option<String> {
+        self.file.clone()
+    }
+
+    pub fn file_line(&self) -> usize {
+        self.file_line
+    }
+
+    pub fn file_column(&self) -> usize {
+        self.file_column
+    }
+
+    /// How the problem might be fixed, if known.
+    pub fn fix(&self) -> Option<String> {
+        self.fix.clone()
+    }
+
+    /// The message along with where the problem is in an include, if it is in one.
+    pub fn summary(&self) -> String {
+        match &self.file {
+            None => self.message.clone(),
+            Some(file) if file == "prelude" => {
+                format!("{} (in prelude, line {})", self.message, self.file_line)
+            }
+            Some(file) => format!(
+                "{} (in include {file}, line {}:{})",
+                self.message, self.file_line, self.file_column
+            ),
+        }
+    }
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: &str, line: usize, column: usize) -> Self {
+        Diagnostic {
+            severity,
+            message: message.to_string(),
+            line,
+            column,
+            end_line: line,
+            end_column: column,
+            file: None,
+            file_line: 0,
+            file_column: 0,
+            fix: None,
+        }
+    }
+
+    pub fn error(message: &str, line: usize, column: usize) -> Self {
+        Self::new(Severity::Error, message, line, column)
+    }
+
+    pub fn warning(message: &str, line: usize, column: usize) -> Self {
+        Self::new(Severity::Warning, message, line, column)
+    }
+
+    pub fn with_end(mut self, end: Option<(usize, usize)>) -> Self {
+        if let Some((line, column)) = end {
+            self.end_line = line;
+            self.end_column = column;
+        }
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Option<String>) -> Self {
+        self.fix = fix;
+        self
+    }
+
+    /// Record that the problem is at a line and column of an include, or of the prelude.
+    pub fn in_file(mut self, file: &str, line: usize, column: usize) -> Self {
+        self.file = Some(file.to_string());
+        self.file_line = line;
+        self.file_column = column;
+        self
+    }
+
+    /// Report an error to the page's `wgsl_error_handler`, or log it natively.
+    pub fn submit(self) {
+        #[cfg(target_arch = "wasm32")]
+        crate::wasm::report_error(self);
+        #[cfg(not(target_arch = "wasm32"))]
+        log::error!("{}:{}: {}", self.line, self.column, self.summary());
+    }
+}
@@ -0,0 +1,123 @@
+use naga::back;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use std::collections::HashMap;
+
+/// Translate a shader with naga's backends, to `spirv` bytecode or `hlsl`, `msl` or `glsl` source.
+/// Overridable constants are replaced by the given values, or their defaults.
+pub fn export(
+    wgsl: &str,
+    target: &str,
+    constants: &HashMap<String, f64>,
+) -> Result<Vec<u8>, String> {
+    let module = naga::front::wgsl::parse_str(wgsl).map_err(|e| e.message().to_string())?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| e.into_inner().to_string())?;
+    let (module, info) = back::pipeline_constants::process_overrides(&module, &info, constants)
+        .map_err(|e| e.to_string())?;
+    match target {
+        "spirv" => {
+            let words = back::spv::write_vec(&module, &info, &Default::default(), None)
+                .map_err(|e| e.to_string())?;
+            Ok(bytemuck::cast_slice(&words).to_vec())
+        }
+        "hlsl" => {
+            let mut out = String::new();
+            back::hlsl::Writer::new(&mut out, &Default::default())
+                .write(&module, &info)
+                .map_err(|e| e.to_string())?;
+            Ok(out.into_bytes())
+        }
+        "msl" => {
+            let (out, _) =
+                back::msl::write_string(&module, &info, &Default::default(), &Default::default())
+                    .map_err(|e| e.to_string())?;
+            Ok(out.into_bytes())
+        }
+        "glsl" => {
+            // GLSL has a single entry point per shader, so write one after another
+            let options = back::glsl::Options {
+                version: back::glsl::Version::Desktop(450),
+                ..Default::default()
+            };
+            let mut out = String::new();
+            for entry_point in &module.entry_points {
+                let pipeline_options = back::glsl::PipelineOptions {
+                    shader_stage: entry_point.stage,
+                    entry_point: entry_point.name.clone(),
+                    multiview: None,
+                };
+                out.push_str(&format!("// entry point: {}\n", entry_point.name));
+                back::glsl::Writer::new(
+                    &mut out,
+                    &module,
+                    &info,
+                    &options,
+                    &pipeline_options,
+                    Default::default(),
+                )
+                .and_then(|mut writer| writer.write())
+                .map_err(|e| e.to_string())?;
+                out.push('\n');
+            }
+            Ok(out.into_bytes())
+        }
+        _ => Err(format!("Unsupported export target {target}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+override scale: f32 = 2.;
+@group(0) @binding(0) var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(64)
+fn main_image(@builtin(global_invocation_id) id: vec3u) {
+    values[id.x] *= scale;
+}
+";
+
+    fn export_to(target: &str) -> Vec<u8> {
+        let constants = HashMap::from([("scale".to_string(), 3.)]);
+        let out = export(SHADER, target, &constants).unwrap();
+        assert!(!out.is_empty());
+        out
+    }
+
+    #[test]
+    fn exports_spirv() {
+        let out = export_to("spirv");
+        assert_eq!(out.len() % 4, 0);
+        #[cfg(feature = "spirv")]
+        {
+            let module = naga::front::spv::parse_u8_slice(&out, &Default::default()).unwrap();
+            assert_eq!(module.entry_points[0].name, "main_image");
+        }
+    }
+
+    #[test]
+    fn exports_hlsl() {
+        let out = String::from_utf8(export_to("hlsl")).unwrap();
+        assert!(out.contains("main_image"));
+    }
+
+    #[test]
+    fn exports_msl() {
+        let out = String::from_utf8(export_to("msl")).unwrap();
+        assert!(out.contains("main_image"));
+    }
+
+    #[test]
+    fn exports_glsl() {
+        let out = String::from_utf8(export_to("glsl")).unwrap();
+        assert!(out.contains("main_image"));
+    }
+
+    #[test]
+    fn rejects_unknown_targets() {
+        assert!(export(SHADER, "dxil", &HashMap::new()).is_err());
+    }
+}
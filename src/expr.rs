@@ -0,0 +1,192 @@
+//! Evaluation of integer constant expressions in preprocessor directives.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Token {
+    Int(i64),
+    Op(&'static str),
+}
+
+// longest operators first, so that e.g. `<<` isn't read as two `<`
+const OPERATORS: [&str; 21] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "(", ")", "+", "-", "*", "/", "%", "<", ">",
+    "&", "|", "^", "!",
+];
+
+/// Split an expression into tokens, or `None` if it isn't made of integers and operators.
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            let literal = rest[..end].trim_end_matches(['i', 'u']);
+            let value = match literal.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => literal.parse(),
+            };
+            tokens.push(Token::Int(value.ok()?));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Some(tokens)
+}
+
+fn binary_precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some(Token::Int(value)) => Ok(value),
+            Some(Token::Op("(")) => {
+                let value = self.binary(0)?;
+                match self.next() {
+                    Some(Token::Op(")")) => Ok(value),
+                    _ => Err("Expected )".to_string()),
+                }
+            }
+            Some(Token::Op("-")) => Ok(self.primary()?.wrapping_neg()),
+            Some(Token::Op("!")) => Ok((self.primary()? == 0) as i64),
+            Some(Token::Op(op)) => Err(format!("Unexpected {op}")),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    /// Parse operators binding tighter than `min_precedence`.
+    fn binary(&mut self, min_precedence: u8) -> Result<i64, String> {
+        let mut lhs = self.primary()?;
+        while let Some(&Token::Op(op)) = self.tokens.get(self.pos) {
+            let Some(precedence) = binary_precedence(op).filter(|&p| p > min_precedence) else {
+                break;
+            };
+            self.pos += 1;
+            let rhs = self.binary(precedence)?;
+            lhs = match op {
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("Division by zero".to_string()),
+                "/" => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+}
+
+/// Evaluate an expression of integer literals, arithmetic, comparison and logical operators,
+/// where comparisons and logical operators give 0 or 1. Returns `None` if the expression refers
+/// to anything else, e.g. shader variables, so can only be evaluated on the GPU.
+pub fn eval(expr: &str) -> Option<Result<i64, String>> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+    };
+    Some(parser.binary(0).and_then(|value| match parser.next() {
+        None => Ok(value),
+        Some(Token::Int(value)) => Err(format!("Unexpected {value}")),
+        Some(Token::Op(op)) => Err(format!("Unexpected {op}")),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eval;
+
+    fn value(expr: &str) -> i64 {
+        eval(expr).unwrap().unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7);
+        assert_eq!(value("(1 + 2) * 3"), 9);
+        assert_eq!(value("10 - 4 - 3"), 3);
+        assert_eq!(value("64 / 4 / 2"), 8);
+        assert_eq!(value("1 << 2 + 1"), 8);
+        assert_eq!(value("1 | 2 ^ 3 & 1"), 3);
+        assert_eq!(value("1 + 1 == 2 && 3 > 2"), 1);
+        assert_eq!(value("0 || 1 && 0"), 0);
+        assert_eq!(value("-2 * -3"), 6);
+        assert_eq!(value("!0 + 1"), 2);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(value("0x10 + 1u + 2i"), 19);
+        assert_eq!(value("  256 % 16 == 0 "), 1);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(eval("1 / 0"), Some(Err("Division by zero".to_string())));
+        assert_eq!(
+            eval("1 % (2 - 2)"),
+            Some(Err("Division by zero".to_string()))
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(eval("(1 + 2"), Some(Err("Expected )".to_string())));
+        assert_eq!(
+            eval("1 +"),
+            Some(Err("Unexpected end of expression".to_string()))
+        );
+        assert_eq!(eval("1 2"), Some(Err("Unexpected 2".to_string())));
+        assert_eq!(eval("* 2"), Some(Err("Unexpected *".to_string())));
+    }
+
+    #[test]
+    fn not_constant() {
+        assert_eq!(eval("SIZE % 16 == 0"), None);
+        assert_eq!(eval("arrayLength(&buf) > 0"), None);
+        assert_eq!(eval("1.5 > 1"), None);
+    }
+}
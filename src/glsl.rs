@@ -0,0 +1,119 @@
+use crate::reflect;
+use crate::Diagnostic;
+use naga::front::glsl::{Frontend, Options};
+
+/// Shadertoy's inputs, declared as private globals so that naga keeps their names,
+/// and assigned from the toy's uniforms by `main_image` below.
+const SHADERTOY_HEADER: &str = r#"#version 450
+layout(local_size_x = 1) in;
+vec3 iResolution;
+float iTime;
+float iTimeDelta;
+int iFrame;
+vec4 iMouse;
+"#;
+
+// naga requires an entry point, which is removed after parsing
+const SHADERTOY_FOOTER: &str = "\nvoid main() {}\n";
+
+const SHADERTOY_ENTRY_POINT: &str = r#"
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: uint3) {
+    let screen_size = textureDimensions(screen);
+    if (id.x >= screen_size.x || id.y >= screen_size.y) { return; }
+    iResolution = float3(float2(screen_size), 1.);
+    iTime = time.elapsed;
+    iTimeDelta = time.delta;
+    iFrame = int(time.frame);
+    // Shadertoy's origin is the bottom left, and the click position is negative once released
+    let mouse_pos = float2(float(mouse.pos.x), float(screen_size.y) - float(mouse.pos.y));
+    iMouse = float4(mouse_pos, select(-1., 1., mouse.click > 0) * mouse_pos);
+    var colour: float4;
+    mainImage(&colour, float2(float(id.x) + .5, float(screen_size.y - id.y) - .5));
+    textureStore(screen, id.xy, float4(colour.rgb, 1.));
+}
+"#;
+
+/// Translate a Shadertoy shader, defining `mainImage(out vec4 fragColor, in vec2 fragCoord)`,
+/// into a full-screen compute shader to be given to `preprocess`. The `iResolution`, `iTime`,
+/// `iTimeDelta`, `iFrame` and `iMouse` inputs are supported, but not channels.
+pub fn shadertoy_to_wgsl(glsl: &str) -> Option<String> {
+    translate(glsl)
+        .map_err(|errors| errors.into_iter().for_each(Diagnostic::submit))
+        .ok()
+}
+
+/// The translation of a Shadertoy shader, or the errors in it, located in the shader as given.
+fn translate(glsl: &str) -> Result<String, Vec<Diagnostic>> {
+    let source = format!("{SHADERTOY_HEADER}{glsl}{SHADERTOY_FOOTER}");
+    let header_len = SHADERTOY_HEADER.lines().count();
+    let mut module = Frontend::default()
+        .parse(&Options::from(naga::ShaderStage::Compute), &source)
+        .map_err(|e| {
+            e.errors
+                .into_iter()
+                .map(|error| {
+                    let loc = error.meta.location(&source);
+                    let row = (loc.line_number as usize).saturating_sub(header_len);
+                    Diagnostic::error(&error.kind.to_string(), row, loc.line_position as usize)
+                })
+                .collect::<Vec<_>>()
+        })?;
+    if !module
+        .functions
+        .iter()
+        .any(|(_, f)| f.name.as_deref() == Some("mainImage"))
+    {
+        return Err(vec![Diagnostic::error(
+            "Shadertoy shaders must define mainImage",
+            0,
+            0,
+        )]);
+    }
+    module.entry_points.clear();
+    match reflect::to_wgsl(&module) {
+        Ok(wgsl) => Ok(wgsl + SHADERTOY_ENTRY_POINT),
+        Err(e) => Err(vec![Diagnostic::error(&e, 0, 0)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_main_image() {
+        let wgsl = translate(
+            "void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    fragColor = vec4(fragCoord / iResolution.xy, sin(iTime), 1.);
+}",
+        )
+        .unwrap();
+        assert!(wgsl.contains("fn mainImage("));
+        assert!(wgsl.contains("fn main_image(@builtin(global_invocation_id) id: uint3)"));
+        assert!(wgsl.contains("iTime = time.elapsed;"));
+    }
+
+    #[test]
+    fn locates_syntax_errors_in_the_shader() {
+        let errors = translate(
+            "void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    fragColor = vec4(uv 0., 1.);
+}",
+        )
+        .unwrap_err();
+        assert!(!errors.is_empty());
+        assert_eq!(errors[0].line(), 3);
+    }
+
+    #[test]
+    fn requires_main_image() {
+        let errors = translate("float brightness() { return 1.; }").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message(),
+            "Shadertoy shaders must define mainImage"
+        );
+    }
+}
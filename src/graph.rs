@@ -0,0 +1,281 @@
+use crate::preprocessor::strip_comments;
+use lazy_regex::*;
+
+static RE_IDENT: Lazy<Regex> = lazy_regex!("^[[:alpha:]_][[:word:]]*$");
+static RE_FN: Lazy<Regex> = lazy_regex!(r"\bfn\s+([[:word:]]+)");
+static RE_WORD: Lazy<Regex> = lazy_regex!(r"\b[[:word:]]+\b");
+static RE_DECLARATION: Lazy<Regex> = lazy_regex!(
+    r"\b(?:fn|const|override|struct|alias|var(?:\s*<[^>]*>)?)\s+([[:alpha:]_][[:word:]]*)"
+);
+static RE_ENTRY_POINT: Lazy<Regex> =
+    lazy_regex!(r"(?s)@compute.*?@workgroup_size\(.*?\).*?fn\s+(\w+)");
+static RE_STRUCT: Lazy<Regex> = lazy_regex!(r"\bstruct\s+[[:word:]]+\s*\{");
+static RE_COMMENT: Lazy<Regex> = lazy_regex!(r"(//.*|(?s:/\*.*?\*/))");
+
+/// A project made of several named passes, in the style of Shadertoy's Buffer A–D tabs.
+///
+/// Each pass is written in its own source and is assigned a layer of `pass_in`/`pass_out`,
+/// available to every pass as a constant of the same name, so `passLoad(BufferA, coord, 0)`
+/// reads the latest output of `BufferA`. Passes are dispatched in the order they were added,
+/// so a pass sees the current frame's output of earlier passes and the previous frame's
+/// output of itself and later passes. Each pass sees the declarations of the common code, but
+/// not those of the other passes.
+#[derive(Default)]
+pub struct PassGraph {
+    common: String,
+    passes: Vec<(String, String)>,
+}
+
+impl PassGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Code shared by all passes, like Shadertoy's Common tab.
+    pub fn set_common(&mut self, source: &str) {
+        self.common = source.to_string();
+    }
+
+    /// Add a pass, or replace the source of an existing one.
+    pub fn add_pass(&mut self, name: &str, source: &str) -> Result<(), String> {
+        if !RE_IDENT.is_match(name) {
+            return Err(format!("Pass name {name} is not a valid identifier"));
+        }
+        match self.passes.iter_mut().find(|(n, _)| n == name) {
+            Some(pass) => pass.1 = source.to_string(),
+            None => self.passes.push((name.to_string(), source.to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn remove_pass(&mut self, name: &str) {
+        self.passes.retain(|(n, _)| n != name);
+    }
+
+    /// The `pass_in`/`pass_out` layer written by a pass.
+    pub fn pass_index(&self, name: &str) -> Option<u32> {
+        self.passes
+            .iter()
+            .position(|(n, _)| n == name)
+            .map(|i| i as u32)
+    }
+
+    /// Combine the passes into a single shader, to be given to `preprocess`.
+    ///
+    /// The functions, constants, variables and types declared by a pass are prefixed with its
+    /// name, so `main_image` in `BufferA` becomes `BufferA_main_image`, and passes can declare
+    /// the same helpers and entry points without colliding. Use `locate` to find the pass an
+    /// error in the composed shader came from.
+    pub fn compose(&self) -> String {
+        self.compose_with_map().0
+    }
+
+    /// The pass (or `common`) and line within it that a line of the composed shader came from.
+    pub fn locate(&self, line: usize) -> Option<PassLocation> {
+        let (_, map) = self.compose_with_map();
+        let (pass, line) = map.get(line.checked_sub(1)?)?.clone()?;
+        Some(PassLocation { pass, line })
+    }
+}
+
+/// Where a line of a composed `PassGraph` was written.
+pub struct PassLocation {
+    /// The name of the pass, or `common`
+    pub pass: String,
+    pub line: usize,
+}
+
+impl PassGraph {
+    /// The composed shader, and the pass and line each of its lines came from, or `None` for
+    /// the lines generated here.
+    fn compose_with_map(&self) -> (String, Vec<Option<(String, usize)>>) {
+        let mut shader = String::new();
+        let mut map = vec![];
+        let mut push = |line: &str, from: Option<(&str, usize)>| {
+            shader.push_str(line);
+            shader.push('\n');
+            map.push(from.map(|(pass, n)| (pass.to_string(), n)));
+        };
+        push(&format!("#pass_count {}", self.passes.len().max(1)), None);
+        for (i, (name, _)) in self.passes.iter().enumerate() {
+            push(&format!("const {name}: int = {i};"), None);
+        }
+        for (line, n) in self.common.lines().zip(1..) {
+            push(line, Some(("common", n)));
+        }
+        for (name, source) in &self.passes {
+            let stripped = strip_comments(source);
+            let declared = top_level_names(&stripped);
+            let entry_points: Vec<&str> = RE_ENTRY_POINT
+                .captures_iter(&stripped)
+                .map(|cap| cap.get(1).unwrap().as_str())
+                .collect();
+            let in_struct = struct_bodies(source);
+            for (line, n) in source.lines().zip(1..) {
+                // label entry points with the pass name, see `WgpuToyRenderer::pass_name`
+                let function = RE_FN.captures(line).map(|cap| cap[1].to_string());
+                if let Some(entry_point) = function.filter(|f| entry_points.contains(&f.as_str())) {
+                    if entry_points.len() > 1 {
+                        push(&format!("/// name: {name}: {entry_point}"), None);
+                    } else {
+                        push(&format!("/// name: {name}"), None);
+                    }
+                }
+                let start = line.as_ptr() as usize - source.as_ptr() as usize;
+                let in_struct = &in_struct[start..start + line.len()];
+                push(
+                    &prefix_names(line, in_struct, &declared, name),
+                    Some((name, n)),
+                );
+            }
+        }
+        (shader, map)
+    }
+}
+
+/// The names declared outside of any braces in a shader without comments.
+fn top_level_names(source: &str) -> Vec<&str> {
+    let mut depth = 0;
+    let mut depths = Vec::with_capacity(source.len());
+    for b in source.bytes() {
+        match b {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            _ => (),
+        }
+        depths.push(depth);
+    }
+    RE_DECLARATION
+        .captures_iter(source)
+        .filter(|cap| depths[cap.get(0).unwrap().start()] == 0)
+        .map(|cap| cap.get(1).unwrap().as_str())
+        .collect()
+}
+
+/// Whether each byte of a shader is within the body of a struct, where `name: type` declares a
+/// member rather than using a name.
+fn struct_bodies(source: &str) -> Vec<bool> {
+    // blank out comments, keeping the positions of the code
+    let code = RE_COMMENT.replace_all(source, |cap: &regex::Captures| " ".repeat(cap[0].len()));
+    let mut in_struct = vec![false; source.len()];
+    for m in RE_STRUCT.find_iter(&code) {
+        // struct bodies can't contain braces
+        let end = code[m.end()..]
+            .find('}')
+            .map_or(code.len(), |i| m.end() + i);
+        in_struct[m.end()..end].fill(true);
+    }
+    in_struct
+}
+
+/// Prefix the uses of the given names in a line with `{prefix}_`, leaving member accesses and
+/// the declarations of struct members, marked by `in_struct` for each byte, alone.
+fn prefix_names(line: &str, in_struct: &[bool], names: &[&str], prefix: &str) -> String {
+    RE_WORD
+        .replace_all(line, |cap: &regex::Captures| {
+            let word = cap.get(0).unwrap();
+            let member = line[..word.start()].trim_end().ends_with('.')
+                || (in_struct[word.start()] && line[word.end()..].trim_start().starts_with(':'));
+            if !member && names.contains(&word.as_str()) {
+                format!("{prefix}_{}", word.as_str())
+            } else {
+                word.as_str().to_string()
+            }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASS: &str = "\
+fn helper(x: f32) -> f32 { return x * 2.; }
+const scale = 2.;
+
+@compute @workgroup_size(16, 16)
+fn main_image(@builtin(global_invocation_id) id: vec3u) {
+    let v = vec2f(helper(scale), 1.);
+    let w = v.x * 1. * scale;
+}
+";
+
+    fn graph() -> PassGraph {
+        let mut graph = PassGraph::new();
+        graph.set_common("fn common_value() -> f32 { return 1.; }");
+        graph.add_pass("BufferA", PASS).unwrap();
+        graph.add_pass("BufferB", PASS).unwrap();
+        graph
+    }
+
+    #[test]
+    fn prefixes_pass_declarations() {
+        let shader = graph().compose();
+        for pass in ["BufferA", "BufferB"] {
+            assert!(shader.contains(&format!("fn {pass}_helper(x: f32)")));
+            assert!(shader.contains(&format!("const {pass}_scale = 2.;")));
+            assert!(shader.contains(&format!("fn {pass}_main_image(")));
+            assert!(shader.contains(&format!("vec2f({pass}_helper({pass}_scale), 1.)")));
+            assert!(shader.contains(&format!("v.x * 1. * {pass}_scale")));
+            assert!(shader.contains(&format!("/// name: {pass}\n")));
+        }
+        assert!(shader.contains("fn common_value() -> f32"));
+        assert!(
+            shader.starts_with("#pass_count 2\nconst BufferA: int = 0;\nconst BufferB: int = 1;\n")
+        );
+    }
+
+    #[test]
+    fn leaves_struct_members_alone() {
+        let mut graph = PassGraph::new();
+        let pass = "\
+const scale = 2.;
+struct Particle {
+    scale: f32,
+    // scale: f32 {
+    @align(16) pos: vec2f,
+}
+struct Pair { scale: f32, other: Particle }
+fn grow(p: Particle, q: Pair) -> f32 { return p . scale * q.other.scale * scale; }
+";
+        graph.add_pass("BufferA", pass).unwrap();
+        let shader = graph.compose();
+        assert!(shader.contains("struct BufferA_Particle {\n    scale: f32,\n"));
+        assert!(shader.contains("@align(16) pos: vec2f,"));
+        assert!(shader.contains("struct BufferA_Pair { scale: f32, other: BufferA_Particle }"));
+        assert!(shader.contains("fn BufferA_grow(p: BufferA_Particle, q: BufferA_Pair)"));
+        assert!(shader.contains("return p . scale * q.other.scale * BufferA_scale;"));
+    }
+
+    #[test]
+    fn passes_with_the_same_names_compile_together() {
+        let wgsl: String = graph()
+            .compose()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        naga::front::wgsl::parse_str(&format!("alias int = i32;\n{wgsl}")).unwrap();
+    }
+
+    #[test]
+    fn locates_lines_in_their_pass() {
+        let graph = graph();
+        let shader = graph.compose();
+        let row = |needle: &str| shader.lines().position(|l| l.contains(needle)).unwrap() + 1;
+        assert!(graph.locate(1).is_none());
+        let common = graph.locate(row("fn common_value")).unwrap();
+        assert_eq!((common.pass.as_str(), common.line), ("common", 1));
+        let entry = graph.locate(row("fn BufferB_main_image")).unwrap();
+        assert_eq!((entry.pass.as_str(), entry.line), ("BufferB", 5));
+        // the label inserted before the entry point isn't in any pass
+        assert!(graph.locate(row("fn BufferB_main_image") - 1).is_none());
+        assert!(graph.locate(0).is_none());
+        assert!(graph.locate(shader.lines().count() + 1).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_pass_names() {
+        assert!(PassGraph::new().add_pass("Buffer A", PASS).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::context::WgpuContext;
+use std::mem::size_of;
+use wgpu::PipelineCompilationOptions;
+
+const TILE_SIZE: u32 = 16;
+/// Written to each result before checking, meaning that every value was finite
+pub const NONE_FOUND: u32 = u32::MAX;
+
+/// Finds the first NaN or infinite value in each of a set of textures, storing its index
+/// (row by row from the top left) or `NONE_FOUND` in consecutive `results`.
+pub struct NanCheck {
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    /// The distance between results in `results`, to satisfy the storage buffer offset alignment
+    stride: u64,
+    results: Option<wgpu::Buffer>,
+}
+
+impl Drop for NanCheck {
+    fn drop(&mut self) {
+        if let Some(results) = &self.results {
+            results.destroy();
+        }
+    }
+}
+
+impl NanCheck {
+    pub fn new(wgpu: &WgpuContext) -> Self {
+        let shader = wgpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("NaN check"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("nancheck.wgsl").into()),
+            });
+        let layout = wgpu
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(size_of::<u32>() as u64),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let pipeline_layout = wgpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+        NanCheck {
+            pipeline: wgpu
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("NaN check"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point: "check",
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+            layout,
+            stride: wgpu.device.limits().min_storage_buffer_offset_alignment as u64,
+            results: None,
+        }
+    }
+
+    /// Append the checks of the given texture layers to the encoder, then copy the results
+    /// into a new staging buffer of one `u32` per texture, which is returned.
+    pub fn encode(
+        &mut self,
+        wgpu: &WgpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        textures: &[(&wgpu::Texture, u32)],
+    ) -> wgpu::Buffer {
+        let size = textures.len() as u64 * self.stride;
+        if self.results.as_ref().map(wgpu::Buffer::size) != Some(size) {
+            if let Some(results) = &self.results {
+                results.destroy();
+            }
+            self.results = Some(wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let results = self.results.as_ref().unwrap();
+        let none_found = vec![NONE_FOUND; (size / size_of::<u32>() as u64) as usize];
+        wgpu.queue
+            .write_buffer(results, 0, bytemuck::cast_slice(&none_found));
+        let staging = wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: textures.len() as u64 * size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        for (i, (texture, layer)) in textures.iter().enumerate() {
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: *layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            let bind_group = wgpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: results,
+                            offset: i as u64 * self.stride,
+                            size: wgpu::BufferSize::new(size_of::<u32>() as u64),
+                        }),
+                    },
+                ],
+            });
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("NaN check"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.dispatch_workgroups(
+                texture.width().div_ceil(TILE_SIZE),
+                texture.height().div_ceil(TILE_SIZE),
+                1,
+            );
+            drop(compute_pass);
+            encoder.copy_buffer_to_buffer(
+                results,
+                i as u64 * self.stride,
+                &staging,
+                i as u64 * size_of::<u32>() as u64,
+                size_of::<u32>() as u64,
+            );
+        }
+        staging
+    }
+}
@@ -0,0 +1,16 @@
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> first: atomic<u32>;
+
+fn is_finite(value: vec4<f32>) -> bool {
+    // compare the exponent bits, as arithmetic may assume values are finite
+    let exponent = bitcast<vec4<u32>>(value) & vec4<u32>(0x7f800000u);
+    return all(exponent != vec4<u32>(0x7f800000u));
+}
+
+@compute @workgroup_size(16, 16)
+fn check(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(src);
+    if (all(id.xy < size) && !is_finite(textureLoad(src, id.xy, 0))) {
+        atomicMin(&first, id.y * size.x + id.x);
+    }
+}
@@ -0,0 +1,338 @@
+//! How the screen is presented: post-processing, tonemapping, the output format and
+//! resolution, and letterboxing.
+
+use crate::{blit, context, reflect, Gamut, PresentMode, Tonemap, WgpuToyRenderer};
+
+impl WgpuToyRenderer {
+    /// Run a WGSL snippet defining `fn post_process(uv: float2) -> float4` when drawing the
+    /// screen to the canvas, returning the linear colour at `uv`. It can read the screen with
+    /// `screen_sample(uv)` and `screen_size()`, and the `time` and `custom` uniforms.
+    pub fn set_post_process(&mut self, snippet: &str) -> Result<(), String> {
+        let (source, line) = self.post_process_source(snippet);
+        if let Err(e) = reflect::validate(&source) {
+            return Err(match e.location {
+                Some((row, col)) if row > line => {
+                    format!("{}:{col}: {}", row - line, e.message)
+                }
+                _ => e.message,
+            });
+        }
+        self.post_process = Some(snippet.to_string());
+        self.screen_blitter = self.create_screen_blitter();
+        Ok(())
+    }
+
+    pub fn clear_post_process(&mut self) {
+        self.post_process = None;
+        self.screen_blitter = self.create_screen_blitter();
+    }
+
+    /// Convert the output to the primaries of a wide gamut display, which must interpret the
+    /// surface in that colour space, rather than assuming it's sRGB. Colours outside the sRGB
+    /// gamut, with negative components in the shader, are then displayed where possible.
+    pub fn set_output_gamut(&mut self, gamut: Gamut) {
+        self.output_settings.gamut = gamut as u32;
+        self.write_output_settings();
+    }
+
+    /// Map the screen's linear colours, scaled by `exposure`, to the displayable range when
+    /// drawing them to the canvas, instead of clipping values above 1.
+    pub fn set_tonemap(&mut self, mode: Tonemap, exposure: f32) {
+        self.output_settings.tonemap = mode as u32;
+        self.output_settings.exposure = exposure;
+        self.write_output_settings();
+    }
+
+    /// Dither the output to reduce banding in smooth gradients, when drawing to an 8-bit surface.
+    pub fn set_dither(&mut self, dither: bool) {
+        self.dither = dither;
+        self.write_output_settings();
+    }
+
+    pub(crate) fn write_output_settings(&mut self) {
+        let eight_bit = matches!(
+            self.wgpu.surface_config.format.remove_srgb_suffix(),
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8Unorm
+        );
+        self.output_settings.dither = (self.dither && eight_bit) as u32;
+        self.output_settings.premultiply = self.premultiplied() as u32;
+        self.wgpu.queue.write_buffer(
+            &self.output_buffer,
+            0,
+            bytemuck::bytes_of(&self.output_settings),
+        );
+    }
+
+    /// Draw to a linear `rgba16float` surface if the display supports it, so values above 1
+    /// aren't clipped, or return to the default 8-bit sRGB surface.
+    pub fn set_output_hdr(&mut self, hdr: bool) -> Result<(), String> {
+        let formats = &self.wgpu.surface_capabilities.formats;
+        let format = if hdr {
+            if !formats.contains(&wgpu::TextureFormat::Rgba16Float) {
+                return Err("This display does not support HDR output".to_string());
+            }
+            wgpu::TextureFormat::Rgba16Float
+        } else {
+            context::preferred_framebuffer_format(formats)
+        };
+        let view_formats = if hdr {
+            vec![]
+        } else {
+            vec![format.add_srgb_suffix(), format.remove_srgb_suffix()]
+        };
+        self.configure_output_format(format, view_formats);
+        Ok(())
+    }
+
+    /// Draw frames in the given format, which must be supported by the surface. A headless
+    /// renderer can use any renderable format, e.g. to match the views given to
+    /// `render_to_texture`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_output_format(&mut self, format: wgpu::TextureFormat) -> Result<(), String> {
+        if matches!(self.wgpu.target, context::Target::Surface(_))
+            && !self.wgpu.surface_capabilities.formats.contains(&format)
+        {
+            return Err(format!("Output format {format:?} is not supported"));
+        }
+        self.configure_output_format(format, vec![]);
+        Ok(())
+    }
+
+    fn configure_output_format(
+        &mut self,
+        format: wgpu::TextureFormat,
+        view_formats: Vec<wgpu::TextureFormat>,
+    ) {
+        self.wgpu.surface_config.format = format;
+        self.wgpu.surface_config.view_formats = view_formats;
+        self.wgpu.configure_surface();
+        self.screen_blitter = self.create_screen_blitter();
+        self.write_output_settings();
+    }
+
+    /// Reconfigure the surface to present frames with vsync (`Fifo`), or without it if the
+    /// platform supports the mode, e.g. for benchmarking.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> Result<(), String> {
+        let present_mode = wgpu::PresentMode::from(mode);
+        if !self
+            .wgpu
+            .surface_capabilities
+            .present_modes
+            .contains(&present_mode)
+        {
+            return Err(format!("Present mode {mode:?} is not supported"));
+        }
+        self.wgpu.surface_config.present_mode = present_mode;
+        self.wgpu.configure_surface();
+        Ok(())
+    }
+
+    /// Resize the surface to `width` by `height` logical pixels, e.g. CSS pixels, at `scale`
+    /// physical pixels each, e.g. `window.devicePixelRatio`, so that it isn't blurry on
+    /// high-DPI displays.
+    pub fn resize(&mut self, width: u32, height: u32, scale: f32) {
+        let scale = if scale.is_finite() && scale > 0. {
+            scale
+        } else {
+            1.
+        };
+        self.resize_physical(
+            (width as f32 * scale).round() as u32,
+            (height as f32 * scale).round() as u32,
+            scale,
+        );
+    }
+
+    /// Resize the surface to a size in physical pixels, where each logical pixel is `scale`
+    /// of them, for hosts which know the exact physical size, e.g. from `winit`.
+    pub fn resize_physical(&mut self, width: u32, height: u32, scale: f32) {
+        self.wgpu.surface_config.width = width.max(1);
+        self.wgpu.surface_config.height = height.max(1);
+        if scale.is_finite() && scale > 0. {
+            self.pixel_ratio = scale;
+        }
+        self.wgpu.configure_surface();
+        self.update_resolution();
+    }
+
+    /// The pixel ratio last given to `resize`, or that of the display when created.
+    pub fn pixel_ratio(&self) -> f32 {
+        self.pixel_ratio
+    }
+
+    /// Limit the internal (compute) resolution, preserving aspect ratio.
+    /// Larger surfaces are upscaled by the blitter. Pass zero to remove the limit.
+    pub fn set_max_resolution(&mut self, width: u32, height: u32) {
+        self.max_resolution = if width == 0 || height == 0 {
+            None
+        } else {
+            Some((width, height))
+        };
+        self.update_resolution();
+    }
+
+    /// Scale the internal (compute) resolution relative to the surface size, e.g. 0.5 to
+    /// render at half resolution and let the blitter upscale, within any maximum resolution.
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<(), String> {
+        if !(scale.is_finite() && scale > 0.) {
+            return Err(format!("Render scale must be positive, not {scale}"));
+        }
+        self.render_scale = scale;
+        self.update_resolution();
+        Ok(())
+    }
+
+    /// Render at twice the resolution (within any maximum resolution), averaging each 2x2
+    /// block of pixels when drawing to the surface, to anti-alias the output.
+    pub fn set_supersample(&mut self, supersample: bool) {
+        self.supersample = supersample;
+        self.update_resolution();
+    }
+
+    /// Preserve the alpha written to the screen texture, so the surface can be composited over
+    /// the content behind it, e.g. HTML under the canvas, if the platform supports it.
+    /// Otherwise alpha is ignored and the output is opaque, the default.
+    pub fn set_transparent(&mut self, transparent: bool) -> Result<(), String> {
+        let alpha_mode = if transparent {
+            [
+                wgpu::CompositeAlphaMode::PreMultiplied,
+                wgpu::CompositeAlphaMode::PostMultiplied,
+            ]
+            .into_iter()
+            .find(|mode| self.wgpu.surface_capabilities.alpha_modes.contains(mode))
+            .ok_or("Transparent output is not supported")?
+        } else {
+            wgpu::CompositeAlphaMode::Opaque
+        };
+        self.wgpu.surface_config.alpha_mode = alpha_mode;
+        self.wgpu.configure_surface();
+        self.write_output_settings();
+        Ok(())
+    }
+
+    fn premultiplied(&self) -> bool {
+        self.wgpu.surface_config.alpha_mode == wgpu::CompositeAlphaMode::PreMultiplied
+    }
+
+    /// Set the colour drawn around the output when letterboxed, as sRGB components from 0 to 1.
+    pub fn set_background_color(&mut self, r: f64, g: f64, b: f64, a: f64) {
+        self.background = [r, g, b, a];
+    }
+
+    /// Letterbox the output to the given width / height ratio, rather than filling the surface,
+    /// e.g. for shaders designed for 16:9. Pass zero to fill the surface again.
+    pub fn set_aspect_ratio(&mut self, ratio: f32) -> Result<(), String> {
+        if !(ratio.is_finite() && ratio >= 0.) {
+            return Err(format!("Aspect ratio must be positive, not {ratio}"));
+        }
+        self.aspect_ratio = (ratio > 0.).then_some(ratio);
+        self.update_resolution();
+        Ok(())
+    }
+
+    /// The background colour in the space of the surface format, which is linear unless the
+    /// format is 8-bit without sRGB encoding.
+    pub(crate) fn background_color(&self) -> wgpu::Color {
+        let format = self.wgpu.surface_config.format;
+        let linear = |c: f64| {
+            if format.is_srgb() || format == wgpu::TextureFormat::Rgba16Float {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            } else {
+                c
+            }
+        };
+        let [r, g, b, a] = self.background;
+        let alpha = if self.premultiplied() { a } else { 1. };
+        wgpu::Color {
+            r: linear(r) * alpha,
+            g: linear(g) * alpha,
+            b: linear(b) * alpha,
+            a,
+        }
+    }
+
+    fn update_resolution(&mut self) {
+        let (surface_width, surface_height) = (
+            self.wgpu.surface_config.width as f32,
+            self.wgpu.surface_config.height as f32,
+        );
+        self.viewport = self.aspect_ratio.map(|ratio| {
+            let width = surface_width.min(surface_height * ratio).max(1.);
+            let height = surface_height.min(surface_width / ratio).max(1.);
+            [
+                (surface_width - width) / 2.,
+                (surface_height - height) / 2.,
+                width,
+                height,
+            ]
+        });
+        let (width, height) = match self.viewport {
+            Some([_, _, width, height]) => (width as u32, height as u32),
+            None => (surface_width as u32, surface_height as u32),
+        };
+        let render_scale = self.render_scale * if self.supersample { 2. } else { 1. };
+        let scale = match self.max_resolution {
+            Some((max_width, max_height)) => f32::min(
+                render_scale,
+                f32::min(
+                    max_width as f32 / width as f32,
+                    max_height as f32 / height as f32,
+                ),
+            ),
+            None => render_scale,
+        };
+        let max_size = self.wgpu.device.limits().max_texture_dimension_2d;
+        self.screen_width = ((width as f32 * scale) as u32).clamp(1, max_size);
+        self.screen_height = ((height as f32 * scale) as u32).clamp(1, max_size);
+        self.reset();
+    }
+
+    /// Release the GPU resources and the canvas or window, for apps which create and remove
+    /// renderers repeatedly, e.g. as an editor is mounted and unmounted. The device is
+    /// destroyed, so nothing is rendered afterwards, and the renderer should then be dropped,
+    /// or freed from JS with `free`.
+    pub fn destroy(&mut self) {
+        // errors from work already queued on the destroyed device are expected
+        self.wgpu.device.on_uncaptured_error(Box::new(|_| ()));
+        self.compute_pipelines.clear();
+        self.last_compute_pipelines = None;
+        self.wgpu.detach();
+        self.wgpu.device.destroy();
+    }
+
+    /// The source of the post-processing shader, with the line at which the snippet starts.
+    fn post_process_source(&self, snippet: &str) -> (String, usize) {
+        let prelude = format!(
+            "{}@group(0) @binding(3) var<uniform> time: Time;\n@group(0) @binding(4) var<uniform> custom: Custom;\n",
+            self.prelude_types()
+        );
+        blit::post_process_source(&prelude, snippet)
+    }
+
+    pub(crate) fn create_screen_blitter(&self) -> blit::Blitter {
+        self.create_screen_blitter_to(self.wgpu.surface_config.format, &self.output_buffer)
+    }
+
+    pub(crate) fn create_screen_blitter_to(
+        &self,
+        format: wgpu::TextureFormat,
+        settings: &wgpu::Buffer,
+    ) -> blit::Blitter {
+        let source = self
+            .post_process
+            .as_ref()
+            .map(|snippet| self.post_process_source(snippet).0);
+        let uniforms = [self.bindings.time.buffer(), self.bindings.custom.buffer()];
+        blit::Blitter::screen(
+            &self.wgpu,
+            self.bindings.tex_screen.view(),
+            format,
+            settings,
+            source.as_deref().map(|source| (source, &uniforms[..])),
+        )
+    }
+}
@@ -0,0 +1,171 @@
+use crate::bind::Bindings;
+use crate::context::WgpuContext;
+use std::collections::HashMap;
+use wgpu::PipelineCompilationOptions;
+
+/// A compiled entry point, with how to dispatch it and the bind groups it uses.
+pub struct ComputePipeline {
+    pub name: String,
+    pub label: String,
+    pub workgroup_size: [u32; 3],
+    pub workgroup_count: Option<[u32; 3]>,
+    pub dispatch_size: Option<[u32; 3]>,
+    /// Set by the host with `set_dispatch_size`, taking precedence over the shader's directives
+    pub dispatch_size_override: Option<[u32; 3]>,
+    pub dispatch_indirect: Option<u32>,
+    /// Only dispatch on frame 0, which `reset` returns to, see `dispatches_once`
+    pub dispatch_once: bool,
+    pub dispatch_count: u32,
+    /// Only dispatch on frames that are a multiple of this
+    pub dispatch_every: u32,
+    /// The bindings this entry point uses, determined by reflection
+    pub bindings: Vec<u32>,
+    pub dynamic_offset: bool,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    /// The bind group, followed by the one with `pass_in` and `pass_out` swapped for `#pass_ping_pong` shaders,
+    /// then the same again with the `#storage_double` buffers swapped if there are any
+    pub bind_groups: Vec<wgpu::BindGroup>,
+    pub pipeline: wgpu::ComputePipeline,
+}
+
+impl ComputePipeline {
+    /// Number of workgroups to dispatch, covering the screen unless the shader specified otherwise.
+    pub fn workgroup_count(&self, width: u32, height: u32) -> [u32; 3] {
+        let size = match (self.dispatch_size_override, self.workgroup_count) {
+            (Some(size), _) => size,
+            (None, Some(count)) => return count,
+            (None, None) => self.dispatch_size.unwrap_or([width, height, 1]),
+        };
+        workgroups_covering(size, self.workgroup_size)
+    }
+
+    /// The same entry point created with other override values, without its bind groups.
+    pub fn specialise(
+        &self,
+        wgpu: &WgpuContext,
+        bindings: &Bindings,
+        module: &wgpu::ShaderModule,
+        constants: &HashMap<String, f64>,
+    ) -> ComputePipeline {
+        let bind_group_layout = bindings.create_bind_group_layout(wgpu, &self.bindings);
+        let pipeline_layout = bindings.create_pipeline_layout(wgpu, &bind_group_layout);
+        ComputePipeline {
+            name: self.name.clone(),
+            label: self.label.clone(),
+            workgroup_size: self.workgroup_size,
+            workgroup_count: self.workgroup_count,
+            dispatch_size: self.dispatch_size,
+            dispatch_size_override: self.dispatch_size_override,
+            dispatch_indirect: self.dispatch_indirect,
+            dispatch_once: self.dispatch_once,
+            dispatch_count: self.dispatch_count,
+            dispatch_every: self.dispatch_every,
+            bindings: self.bindings.clone(),
+            dynamic_offset: self.dynamic_offset,
+            pipeline: create_compute_pipeline(
+                wgpu,
+                &self.label,
+                &pipeline_layout,
+                module,
+                &self.name,
+                constants,
+            ),
+            bind_group_layout,
+            bind_groups: vec![],
+        }
+    }
+}
+
+/// Whether an entry point only runs on frame 0: those named by `#dispatch_once` or `#init`, and
+/// those whose names start with `init`.
+pub fn dispatches_once(name: &str, dispatch_once: &HashMap<String, bool>) -> bool {
+    dispatch_once
+        .get(name)
+        .copied()
+        .unwrap_or_else(|| name.starts_with("init"))
+}
+
+/// Number of workgroups of `workgroup_size` needed to cover a domain of `size` invocations.
+pub fn workgroups_covering(size: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    std::array::from_fn(|i| size[i].div_ceil(workgroup_size[i]))
+}
+
+/// For each bind group of a pipeline, in the order of `ComputePipeline::bind_groups`, whether the
+/// pass textures and the storage buffers need swapping from how they are currently bound to build
+/// it. This leaves the current bindings at `storage_swapped * pass_variants + pass_swapped`.
+pub fn bind_group_swaps(
+    pass_swapped: bool,
+    storage_swapped: bool,
+    pass_variants: usize,
+    storage_variants: usize,
+) -> Vec<(bool, bool)> {
+    (0..storage_variants)
+        .flat_map(|s| {
+            (0..pass_variants).map(move |p| ((p == 1) != pass_swapped, (s == 1) != storage_swapped))
+        })
+        .collect()
+}
+
+pub fn create_compute_pipeline(
+    wgpu: &WgpuContext,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    constants: &HashMap<String, f64>,
+) -> wgpu::ComputePipeline {
+    wgpu.device
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            module,
+            entry_point,
+            compilation_options: PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_entry_points_dispatch_once() {
+        let directives = HashMap::from([("seed".to_string(), true)]);
+        assert!(dispatches_once("seed", &directives));
+        assert!(dispatches_once("init", &directives));
+        assert!(dispatches_once("init_particles", &directives));
+        assert!(!dispatches_once("main_image", &directives));
+        assert!(!dispatches_once("interior", &directives));
+    }
+
+    #[test]
+    fn bind_group_swaps_keep_parity() {
+        for pass_swapped in [false, true] {
+            for storage_swapped in [false, true] {
+                let swaps = bind_group_swaps(pass_swapped, storage_swapped, 2, 2);
+                assert_eq!(swaps.len(), 4);
+                // the bind group used next is built from the bindings as they are
+                let current = storage_swapped as usize * 2 + pass_swapped as usize;
+                assert_eq!(swaps[current], (false, false));
+                // and the first from the bindings as they were before any swaps
+                assert_eq!(swaps[0], (pass_swapped, storage_swapped));
+            }
+        }
+    }
+
+    #[test]
+    fn bind_group_swaps_without_ping_pong() {
+        assert_eq!(bind_group_swaps(false, false, 1, 1), [(false, false)]);
+        assert_eq!(
+            bind_group_swaps(false, true, 1, 2),
+            [(false, true), (false, false)]
+        );
+        assert_eq!(
+            bind_group_swaps(true, false, 2, 1),
+            [(true, false), (false, false)]
+        );
+    }
+}
@@ -0,0 +1,849 @@
+use crate::{
+    bind::{
+        parse_storage_format, MAX_CUSTOM_PARAMS, MAX_DISPATCHES, MAX_INDIRECT_DISPATCHES,
+        NUM_ASSERT_COUNTERS,
+    },
+    diagnostic::Diagnostic,
+    expr,
+    utils::{bundled_include, fetch_bytes, fetch_include, parse_u32},
+};
+use async_recursion::async_recursion;
+use itertools::Itertools;
+use lazy_regex::*;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+pub struct WGSLError {
+    summary: String,
+    line: usize,
+    include: Option<(String, usize)>,
+}
+
+impl WGSLError {
+    pub fn new(summary: String, line: usize) -> Self {
+        Self {
+            summary,
+            line,
+            include: None,
+        }
+    }
+    /// Record the include file and line the error occurred at, unless a nested include already has.
+    fn in_include(mut self, name: &str, line: usize) -> Self {
+        self.include.get_or_insert((name.to_string(), line));
+        self
+    }
+    pub fn handler(summary: &str, row: usize, col: usize) {
+        Diagnostic::error(summary, row, col).submit();
+    }
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&self.summary, self.line, 0);
+        match &self.include {
+            None => diagnostic,
+            Some((name, line)) => diagnostic.in_file(name, *line, 0),
+        }
+    }
+    pub fn submit(&self) {
+        self.diagnostic().submit();
+    }
+}
+
+#[derive(Clone)]
+pub struct SourceMap {
+    pub extensions: String,
+    pub source: String,
+    pub map: Vec<usize>,
+    /// The include file and line within it of each line of `source`, if it came from an include
+    pub include_map: Vec<Option<(String, usize)>>,
+    /// Storage buffers declared with `#storage`, and their element counts
+    pub storage: Vec<(String, Option<u32>)>,
+    /// Views declared with `#storage_view`, which are also in `storage`, and the buffers they view
+    pub storage_views: HashMap<String, String>,
+    /// Buffers declared with `#storage_double`, bound as `{name}_read` and `{name}_write`
+    pub storage_double: Vec<String>,
+    pub workgroup_count: HashMap<String, [u32; 3]>,
+    pub dispatch_size: HashMap<String, [u32; 3]>,
+    /// The line of each `#workgroup_count` and `#dispatch_size`, by directive and entry point
+    pub directive_lines: HashMap<(&'static str, String), usize>,
+    pub dispatch_indirect: HashMap<String, u32>,
+    pub dispatch_once: HashMap<String, bool>,
+    pub dispatch_count: HashMap<String, u32>,
+    pub dispatch_every: HashMap<String, u32>,
+    pub assert_map: Vec<usize>,
+    pub user_data: indexmap::IndexMap<String, Vec<u32>>,
+    /// Custom uniforms declared with `#param`
+    pub params: Vec<Param>,
+    /// Images downloaded for `#texture`, to be loaded into channels
+    pub textures: Vec<ChannelTexture>,
+    pub pass_names: HashMap<String, String>,
+    pub pass_count: Option<u32>,
+    pub pass_formats: BTreeMap<u32, wgpu::TextureFormat>,
+    pub pass_ping_pong: bool,
+    /// Whether the prelude is prepended, which shaders translated from SPIR-V go without
+    pub prelude: bool,
+    /// Time taken to preprocess the shader, including fetching includes and textures
+    pub preprocess_ms: f32,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self {
+            extensions: String::new(),
+            source: String::new(),
+            map: vec![0],
+            include_map: vec![None],
+            storage: vec![],
+            storage_views: HashMap::new(),
+            storage_double: vec![],
+            workgroup_count: HashMap::new(),
+            dispatch_size: HashMap::new(),
+            directive_lines: HashMap::new(),
+            dispatch_indirect: HashMap::new(),
+            dispatch_once: HashMap::new(),
+            dispatch_count: HashMap::new(),
+            dispatch_every: HashMap::new(),
+            assert_map: vec![],
+            user_data: indexmap::IndexMap::from([("_dummy".into(), vec![0])]),
+            params: vec![],
+            textures: vec![],
+            pass_names: HashMap::new(),
+            pass_count: None,
+            pass_formats: BTreeMap::new(),
+            pass_ping_pong: false,
+            prelude: true,
+            preprocess_ms: 0.,
+        }
+    }
+
+    /// A shader that declares its own bindings, rather than using those of the prelude.
+    pub fn without_prelude(wgsl: &str) -> Self {
+        let mut source = Self::new();
+        for (line, n) in wgsl.lines().zip(1..) {
+            source.push_line(line, n, None);
+        }
+        source.prelude = false;
+        source
+    }
+    fn push_line(&mut self, s: &str, n: usize, include: Option<(String, usize)>) {
+        self.source.push_str(s);
+        self.source.push('\n');
+        self.map.push(n);
+        self.include_map.push(include);
+    }
+
+    /// The line of the original file that produced the first line of `source` matching a pattern,
+    /// or 0 if there is none.
+    pub fn find_line(&self, re: &Regex) -> usize {
+        self.source
+            .lines()
+            .position(|line| re.is_match(line))
+            .and_then(|i| self.map.get(i + 1).copied())
+            .unwrap_or(0)
+    }
+
+    /// Report an error located in the shader composed of `prelude_len` lines of prelude
+    /// followed by `source`, at the location the user wrote it.
+    pub fn report_error(&self, diagnostic: Diagnostic, prelude_len: usize) {
+        self.locate(diagnostic, prelude_len).submit();
+    }
+
+    /// Move a diagnostic located in the shader composed of `prelude_len` lines of prelude
+    /// followed by `source` to the location the user wrote it.
+    pub fn locate(&self, diagnostic: Diagnostic, prelude_len: usize) -> Diagnostic {
+        let Diagnostic {
+            line: row,
+            column: col,
+            ..
+        } = diagnostic;
+        let unlocated = Diagnostic {
+            line: 0,
+            column: 0,
+            end_line: 0,
+            end_column: 0,
+            ..diagnostic.clone()
+        };
+        if row <= prelude_len {
+            return unlocated.in_file("prelude", row, col);
+        }
+        let n = row - prelude_len;
+        match (self.map.get(n), self.include_map.get(n)) {
+            (Some(&line), Some(Some((name, include_line)))) => Diagnostic {
+                line,
+                end_line: line,
+                ..unlocated
+            }
+            .in_file(name, *include_line, col),
+            (Some(&line), _) => Diagnostic {
+                line,
+                end_line: diagnostic
+                    .end_line
+                    .checked_sub(prelude_len)
+                    .and_then(|n| self.map.get(n).copied())
+                    .unwrap_or(line),
+                ..diagnostic
+            },
+            (None, _) => unlocated,
+        }
+    }
+}
+
+/// A custom uniform declared in the shader, e.g. `#param speed 0.5 range(0, 10) step(0.1)`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Param {
+    pub name: String,
+    pub default: f32,
+    pub range: Option<(f32, f32)>,
+    pub step: Option<f32>,
+}
+
+/// An image to load into a channel, e.g. `#texture channel0 "https://example.com/env.hdr" rgbe`.
+#[derive(Clone)]
+pub struct ChannelTexture {
+    pub index: usize,
+    pub url: String,
+    /// Whether the image is Radiance HDR, rather than sRGB
+    pub hdr: bool,
+    pub bytes: Vec<u8>,
+}
+
+impl Default for SourceMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves `#include "name"` directives before falling back to the default include locations.
+/// On the web this calls a JS function returning the source, or a promise of it.
+type ResolveFn = dyn Fn(&str) -> Pin<Box<dyn Future<Output = Option<String>>>>;
+
+#[derive(Clone)]
+pub struct IncludeResolver(pub Rc<ResolveFn>);
+
+impl IncludeResolver {
+    async fn resolve(&self, name: &str) -> Option<String> {
+        (self.0)(name).await
+    }
+}
+
+/// An `#ifdef`/`#ifndef` block being processed.
+struct Conditional {
+    line: usize,
+    parent_active: bool,
+    taken: bool,
+    seen_else: bool,
+}
+
+pub struct Preprocessor {
+    defines: HashMap<String, String>,
+    conditionals: Vec<Conditional>,
+    include_resolver: Option<IncludeResolver>,
+    include_stack: Vec<(String, usize)>,
+    source: SourceMap,
+    assert_count: usize,
+    special_strings: bool,
+    pending_pass_name: Option<String>,
+}
+
+static RE_COMMENT: Lazy<Regex> = lazy_regex!(r"(//.*|(?s:/\*.*?\*/))");
+static RE_QUOTES: Lazy<Regex> = lazy_regex!(r#""((?:[^\\"]|\\.)*)""#);
+static RE_CHEVRONS: Lazy<Regex> = lazy_regex!("<(.*)>");
+static RE_WORD: Lazy<Regex> = lazy_regex!("[[:word:]]+");
+static RE_PASS_NAME: Lazy<Regex> = lazy_regex!(r"^\s*///\s*name:\s*(.*?)\s*$");
+static RE_FN: Lazy<Regex> = lazy_regex!(r"\bfn\s+([[:word:]]+)");
+
+const STRING_MAX_LEN: usize = 20;
+
+/// The type of a storage buffer directive and its optional trailing element count,
+/// e.g. `array<Particle> N*N`.
+fn storage_type(types: &[&str], n: usize) -> Result<(String, Option<u32>), WGSLError> {
+    match types {
+        [ty @ .., count] if !ty.is_empty() && !count.contains('>') => {
+            let count = count.split('*').try_fold(1u32, |product, x| {
+                product
+                    .checked_mul(parse_u32(x, n)?)
+                    .ok_or_else(|| WGSLError::new(format!("Element count {count} is too large"), n))
+            })?;
+            Ok((ty.join(" "), Some(count)))
+        }
+        _ => Ok((types.join(" "), None)),
+    }
+}
+
+pub fn strip_comments(s: &str) -> String {
+    RE_COMMENT.replace_all(s, "").to_string()
+}
+
+impl Preprocessor {
+    pub fn new(mut defines: HashMap<String, String>) -> Self {
+        defines.insert("STRING_MAX_LEN".to_string(), STRING_MAX_LEN.to_string());
+        Self {
+            defines,
+            conditionals: vec![],
+            include_resolver: None,
+            include_stack: vec![],
+            source: SourceMap::new(),
+            assert_count: 0,
+            special_strings: false,
+            pending_pass_name: None,
+        }
+    }
+
+    pub fn with_include_resolver(mut self, resolver: Option<IncludeResolver>) -> Self {
+        self.include_resolver = resolver;
+        self
+    }
+
+    async fn resolve_include(&self, name: &str) -> Option<String> {
+        if let Some(resolver) = &self.include_resolver {
+            if let Some(code) = resolver.resolve(name).await {
+                return Some(code);
+            }
+        }
+        if let Some(code) = bundled_include(name) {
+            return Some(code.to_string());
+        }
+        fetch_include(name.to_string()).await
+    }
+
+    fn subst_defines(&self, source: &str) -> String {
+        RE_WORD
+            .replace_all(source, |caps: &regex::Captures| {
+                let name = &caps[0];
+                self.defines
+                    .get(name)
+                    .unwrap_or(&name.to_string())
+                    .to_owned()
+            })
+            .to_string()
+    }
+
+    async fn preprocess(&mut self, shader: &str) -> Result<(), WGSLError> {
+        for (line, n) in shader.lines().zip(1..) {
+            self.process_line(line, n).await?
+        }
+        match self.conditionals.last() {
+            Some(cond) => Err(WGSLError::new("Unterminated #ifdef".to_string(), cond.line)),
+            None => Ok(()),
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.conditionals
+            .last()
+            .is_none_or(|cond| cond.parent_active && cond.taken)
+    }
+
+    /// Handle `#ifdef`, `#ifndef`, `#else` and `#endif`, returning whether the line was one of them.
+    fn process_conditional(&mut self, line_orig: &str, n: usize) -> Result<bool, WGSLError> {
+        let line = RE_COMMENT.replace(line_orig, "");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            ["#ifdef" | "#ifndef", name] => {
+                let defined = self.defines.contains_key(name);
+                self.conditionals.push(Conditional {
+                    line: n,
+                    parent_active: self.active(),
+                    taken: defined == (tokens[0] == "#ifdef"),
+                    seen_else: false,
+                });
+            }
+            ["#else"] => match self.conditionals.last_mut() {
+                Some(cond) if !cond.seen_else => {
+                    cond.taken = !cond.taken;
+                    cond.seen_else = true;
+                }
+                _ => return Err(WGSLError::new("Unexpected #else".to_string(), n)),
+            },
+            ["#endif"] => {
+                if self.conditionals.pop().is_none() {
+                    return Err(WGSLError::new("Unexpected #endif".to_string(), n));
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    #[async_recursion(?Send)]
+    async fn process_line(&mut self, line_orig: &str, n: usize) -> Result<(), WGSLError> {
+        if self.process_conditional(line_orig, n)? || !self.active() {
+            return Ok(());
+        }
+        let mut line = self.subst_defines(line_orig);
+        if line.trim_start().starts_with("enable") {
+            line = RE_COMMENT.replace(&line, "").to_string();
+            self.source.extensions.push_str(&line);
+            self.source.extensions.push('\n');
+        } else if line.trim_start().starts_with('#') {
+            line = RE_COMMENT.replace(&line, "").to_string();
+            let tokens: Vec<&str> = line.trim().split(' ').collect();
+            match tokens[..] {
+                ["#include", name] => {
+                    if self.include_stack.iter().any(|(n, _)| n == name) {
+                        return Err(WGSLError::new(format!("Recursive include {name}"), n));
+                    }
+                    let include = match RE_QUOTES.captures(name) {
+                        None => match RE_CHEVRONS.captures(name) {
+                            None => {
+                                return Err(WGSLError::new(
+                                    "Path must be enclosed in quotes".to_string(),
+                                    n,
+                                ))
+                            }
+                            Some(cap) => {
+                                let path = &cap[1];
+                                if path == "string" {
+                                    self.special_strings = true;
+                                }
+                                self.resolve_include(&format!("std/{path}")).await
+                            }
+                        },
+                        Some(cap) => self.resolve_include(&cap[1]).await,
+                    };
+                    if let Some(code) = include {
+                        self.include_stack.push((name.to_string(), 0));
+                        for (line, i) in code.lines().zip(1..) {
+                            self.include_stack.last_mut().unwrap().1 = i;
+                            self.process_line(line, n)
+                                .await
+                                .map_err(|e| e.in_include(name, i))?
+                        }
+                        self.include_stack.pop();
+                    } else {
+                        return Err(WGSLError::new(format!("Cannot find include {name}"), n));
+                    }
+                }
+                ["#workgroup_count", name, x, y, z] => {
+                    self.source.workgroup_count.insert(
+                        name.to_string(),
+                        [parse_u32(x, n)?, parse_u32(y, n)?, parse_u32(z, n)?],
+                    );
+                    self.source
+                        .directive_lines
+                        .insert(("#workgroup_count", name.to_string()), n);
+                }
+                ["#dispatch_size", name, x, y, z] => {
+                    self.source.dispatch_size.insert(
+                        name.to_string(),
+                        [parse_u32(x, n)?, parse_u32(y, n)?, parse_u32(z, n)?],
+                    );
+                    self.source
+                        .directive_lines
+                        .insert(("#dispatch_size", name.to_string()), n);
+                }
+                ["#dispatch_indirect", name] => {
+                    let slot = self.source.dispatch_indirect.len();
+                    if slot >= MAX_INDIRECT_DISPATCHES {
+                        return Err(WGSLError::new(
+                            format!("A maximum of {MAX_INDIRECT_DISPATCHES} indirect dispatches are currently supported"),
+                            n,
+                        ));
+                    }
+                    self.source
+                        .dispatch_indirect
+                        .insert(name.to_string(), slot as u32);
+                }
+                ["#dispatch_once" | "#init", name] => {
+                    self.source.dispatch_once.insert(name.to_string(), true);
+                }
+                ["#dispatch_count", name, x] => {
+                    let count = parse_u32(x, n)?;
+                    if count == 0 || count as usize > MAX_DISPATCHES {
+                        return Err(WGSLError::new(
+                            format!("Dispatch count must be between 1 and {MAX_DISPATCHES}"),
+                            n,
+                        ));
+                    }
+                    self.source.dispatch_count.insert(name.to_string(), count);
+                }
+                ["#dispatch_every", name, x] => {
+                    let every = parse_u32(x, n)?;
+                    if every == 0 {
+                        return Err(WGSLError::new(
+                            "Dispatch interval must be at least 1".to_string(),
+                            n,
+                        ));
+                    }
+                    self.source.dispatch_every.insert(name.to_string(), every);
+                }
+                ["#pass_count", x] => {
+                    self.source.pass_count = Some(parse_u32(x, n)?);
+                }
+                ["#pass_format", x, format] => {
+                    let format = parse_storage_format(format).ok_or(WGSLError::new(
+                        format!("Unsupported pass format {format}"),
+                        n,
+                    ))?;
+                    self.source.pass_formats.insert(parse_u32(x, n)?, format);
+                }
+                ["#pass_ping_pong"] => {
+                    self.source.pass_ping_pong = true;
+                }
+                ["#texture", channel, url, ref format @ ..] => {
+                    let index = channel
+                        .strip_prefix("channel")
+                        .and_then(|i| i.parse().ok())
+                        .ok_or(WGSLError::new(format!("Unknown channel {channel}"), n))?;
+                    let url = RE_QUOTES.captures(url).ok_or(WGSLError::new(
+                        "URL must be enclosed in quotes".to_string(),
+                        n,
+                    ))?[1]
+                        .to_string();
+                    let hdr = match format {
+                        [] => url.ends_with(".hdr"),
+                        ["rgbe"] => true,
+                        ["srgb"] => false,
+                        _ => {
+                            return Err(WGSLError::new(
+                                format!("Unsupported texture format {}", format.join(" ")),
+                                n,
+                            ))
+                        }
+                    };
+                    let bytes = fetch_bytes(url.clone())
+                        .await
+                        .map_err(|e| WGSLError::new(format!("Cannot load texture {e}"), n))?;
+                    self.source.textures.retain(|t| t.index != index);
+                    self.source.textures.push(ChannelTexture {
+                        index,
+                        url,
+                        hdr,
+                        bytes,
+                    });
+                }
+                ["#param", name, default, ref options @ ..] => {
+                    if self.source.params.len() >= MAX_CUSTOM_PARAMS {
+                        return Err(WGSLError::new(
+                            format!("A maximum of {MAX_CUSTOM_PARAMS} parameters are supported"),
+                            n,
+                        ));
+                    }
+                    if self.source.params.iter().any(|p| p.name == name) {
+                        return Err(WGSLError::new(format!("Cannot redefine {name}"), n));
+                    }
+                    let parse_f32 = |s: &str| {
+                        s.trim().parse::<f32>().map_err(|_| {
+                            WGSLError::new(format!("Cannot parse '{}' as f32", s.trim()), n)
+                        })
+                    };
+                    let options = options.join(" ");
+                    let range = match regex!(r"range\(([^,]*),([^)]*)\)").captures(&options) {
+                        Some(cap) => Some((parse_f32(&cap[1])?, parse_f32(&cap[2])?)),
+                        None => None,
+                    };
+                    let step = match regex!(r"step\(([^)]*)\)").captures(&options) {
+                        Some(cap) => Some(parse_f32(&cap[1])?),
+                        None => None,
+                    };
+                    self.source.params.push(Param {
+                        name: name.to_string(),
+                        default: parse_f32(default)?,
+                        range,
+                        step,
+                    });
+                }
+                ["#define", ..] => {
+                    let l = line_orig
+                        .trim()
+                        .split(' ')
+                        .nth(1)
+                        .ok_or(WGSLError::new("Parse error".to_string(), n))?;
+                    let r = tokens[2..].join(" ");
+                    if self.defines.contains_key(l) {
+                        return Err(WGSLError::new(format!("Cannot redefine {l}"), n));
+                    }
+                    self.defines.insert(l.to_string(), r);
+                }
+                ["#storage", name, ref types @ ..] => {
+                    // an optional trailing element count, e.g. `#storage particles array<Particle> N*N`
+                    let (ty, count) = storage_type(types, n)?;
+                    self.push_line(
+                        &format!(
+                            "@group(0) @binding({}) var<storage,read_write> {name}: {ty};",
+                            self.source.storage.len()
+                        ),
+                        n,
+                    );
+                    self.source.storage.push((name.to_string(), count));
+                }
+                ["#storage_view", name, buffer, ref types @ ..] => {
+                    // the memory of an earlier buffer declared with another type,
+                    // e.g. `#storage_view particle_counts particles array<atomic<u32>>`
+                    if !self.source.storage.iter().any(|(n, _)| n == buffer) {
+                        return Err(WGSLError::new(
+                            format!("Storage buffer {buffer} must be declared before a view of it"),
+                            n,
+                        ));
+                    }
+                    let double = self.source.storage_double.iter().any(|d| {
+                        [format!("{d}_read"), format!("{d}_write")].contains(&buffer.to_string())
+                    });
+                    if double {
+                        return Err(WGSLError::new(
+                            format!("Storage buffer {buffer} is swapped every frame, so cannot be viewed"),
+                            n,
+                        ));
+                    }
+                    let ty = types.join(" ");
+                    self.push_line(
+                        &format!(
+                            "@group(0) @binding({}) var<storage,read_write> {name}: {ty};",
+                            self.source.storage.len()
+                        ),
+                        n,
+                    );
+                    self.source.storage.push((name.to_string(), None));
+                    self.source
+                        .storage_views
+                        .insert(name.to_string(), buffer.to_string());
+                }
+                ["#storage_double", name, ref types @ ..] => {
+                    // last frame's contents are read from `{name}_read` while writing `{name}_write`
+                    let (ty, count) = storage_type(types, n)?;
+                    for (suffix, access) in [("read", "read"), ("write", "read_write")] {
+                        self.push_line(
+                            &format!(
+                                "@group(0) @binding({}) var<storage,{access}> {name}_{suffix}: {ty};",
+                                self.source.storage.len()
+                            ),
+                            n,
+                        );
+                        self.source
+                            .storage
+                            .push((format!("{name}_{suffix}"), count));
+                    }
+                    self.source.storage_double.push(name.to_string());
+                }
+                ["#assert", ref counters @ ..] => {
+                    let pred = counters.join(" ");
+                    // constant expressions are checked now, anything else on the GPU
+                    match expr::eval(&pred) {
+                        Some(Ok(0)) => {
+                            let orig = line_orig.trim().trim_start_matches("#assert").trim();
+                            return Err(WGSLError::new(format!("Assertion failed: {orig}"), n));
+                        }
+                        Some(Ok(_)) => return Ok(()),
+                        Some(Err(e)) => {
+                            return Err(WGSLError::new(
+                                format!("Cannot evaluate assertion: {e}"),
+                                n,
+                            ))
+                        }
+                        None => (),
+                    }
+                    if self.assert_count >= NUM_ASSERT_COUNTERS {
+                        return Err(WGSLError::new(
+                            format!("A maximum of {NUM_ASSERT_COUNTERS} assertions are currently supported"),
+                            n,
+                        ));
+                    }
+                    self.push_line(&format!("assert({}, {pred});", self.assert_count), n);
+                    self.source.assert_map.push(n);
+                    self.assert_count += 1;
+                }
+                ["#data", name, "u32", ref data @ ..] => {
+                    match data.join("").split(',').map(|s| parse_u32(s, n)).collect() {
+                        Ok::<Vec<u32>, _>(mut data) => {
+                            if self.source.user_data.contains_key("_dummy") {
+                                self.source.user_data.clear();
+                            }
+                            let name = name.to_string();
+                            if let Some(arr) = self.source.user_data.get_mut(&name) {
+                                arr.append(&mut data);
+                            } else {
+                                self.source.user_data.insert(name, data);
+                            }
+                        }
+                        Err(e) => {
+                            return Err(e);
+                        }
+                    }
+                }
+                _ => {
+                    return Err(WGSLError::new(
+                        "Unrecognised preprocessor directive".to_string(),
+                        n,
+                    ))
+                }
+            }
+        } else {
+            if let Some(cap) = RE_PASS_NAME.captures(&line) {
+                self.pending_pass_name = Some(cap[1].to_string());
+            } else if let Some(cap) = RE_FN.captures(&line) {
+                if let Some(name) = self.pending_pass_name.take() {
+                    self.source.pass_names.insert(cap[1].to_string(), name);
+                }
+            }
+            if self.special_strings {
+                let mut err = None;
+                line = RE_QUOTES.replace(&line, |caps: &Captures| {
+                    if let Ok(s) = snailquote::unescape(&caps[0]) {
+                        let mut chars: Vec<u32> = s.chars().map(|c| c as u32).collect();
+                        let len = chars.len();
+                        if len > STRING_MAX_LEN {
+                            err = Some(WGSLError::new(
+                                format!(
+                                    "String literals cannot be longer than {STRING_MAX_LEN} characters"
+                                ),
+                                n,
+                            ));
+                        }
+                        chars.resize(STRING_MAX_LEN, 0);
+                        format!(
+                            "String({len}, array<uint,{STRING_MAX_LEN}>({}))",
+                            chars.iter().map(|c| format!("{c:#04x}")).join(", ")
+                        )
+                    } else {
+                        caps[0].to_string()
+                    }
+                }).to_string();
+                if let Some(e) = err {
+                    return Err(e);
+                }
+            }
+            self.push_line(&line, n);
+        }
+        Ok(())
+    }
+
+    fn push_line(&mut self, s: &str, n: usize) {
+        let include = self.include_stack.last().cloned();
+        self.source.push_line(s, n, include);
+    }
+
+    pub async fn run(&mut self, shader: &str) -> Option<SourceMap> {
+        match self.check(shader).await {
+            Ok(source) => Some(source),
+            Err(e) => {
+                e.submit();
+                None
+            }
+        }
+    }
+
+    /// Preprocess a shader, returning the first error rather than reporting it.
+    pub async fn check(&mut self, shader: &str) -> Result<SourceMap, WGSLError> {
+        let now = instant::Instant::now();
+        self.preprocess(shader).await?;
+        self.source.preprocess_ms = now.elapsed().as_secs_f32() * 1e3;
+        Ok(std::mem::take(&mut self.source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(shader: &str, defines: &[(&str, &str)]) -> Result<SourceMap, (String, usize)> {
+        let defines = defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut preprocessor = Preprocessor::new(defines);
+        pollster::block_on(preprocessor.preprocess(shader)).map_err(|e| (e.summary, e.line))?;
+        Ok(preprocessor.source)
+    }
+
+    fn lines(shader: &str, defines: &[(&str, &str)]) -> Vec<String> {
+        let source = preprocess(shader, defines).unwrap();
+        source.source.lines().map(str::to_string).collect()
+    }
+
+    const NESTED: &str = "\
+#ifdef HIGH
+high
+#ifdef SHADOWS
+shadows
+#else
+no_shadows
+#endif
+#else
+low
+#endif
+always";
+
+    #[test]
+    fn nested_conditionals() {
+        assert_eq!(lines(NESTED, &[]), ["low", "always"]);
+        assert_eq!(
+            lines(NESTED, &[("HIGH", "")]),
+            ["high", "no_shadows", "always"]
+        );
+        assert_eq!(
+            lines(NESTED, &[("HIGH", ""), ("SHADOWS", "")]),
+            ["high", "shadows", "always"]
+        );
+        // the inner block of an inactive one is skipped, whichever way it goes
+        assert_eq!(lines(NESTED, &[("SHADOWS", "")]), ["low", "always"]);
+    }
+
+    #[test]
+    fn ifndef_and_define() {
+        let shader = "#define LOW 1\n#ifndef LOW\nhigh\n#else\nlow LOW\n#endif";
+        assert_eq!(lines(shader, &[]), ["low 1"]);
+    }
+
+    #[test]
+    fn keeps_line_numbers() {
+        let source = preprocess("#ifdef A\na\n#endif\nb", &[]).unwrap();
+        assert_eq!(source.source, "b\n");
+        assert_eq!(source.map, [0, 4]);
+    }
+
+    #[test]
+    fn storage_counts() {
+        let source = preprocess(
+            "#storage a array<f32>\n#storage b array<vec4<f32>> 64*64\n#storage c array<u32> 0x10",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            source.storage,
+            [
+                ("a".to_string(), None),
+                ("b".to_string(), Some(4096)),
+                ("c".to_string(), Some(16)),
+            ]
+        );
+        assert!(source
+            .source
+            .contains("@group(0) @binding(1) var<storage,read_write> b: array<vec4<f32>>;"));
+    }
+
+    #[test]
+    fn malformed_storage_counts() {
+        assert_eq!(
+            preprocess("#storage a array<f32> N", &[]).err(),
+            Some(("Cannot parse 'N' as u32".to_string(), 1))
+        );
+        assert_eq!(
+            preprocess("\n#storage a array<f32> 4*", &[]).err(),
+            Some(("Cannot parse '' as u32".to_string(), 2))
+        );
+        assert_eq!(
+            preprocess("#storage a array<f32> 65536*65536", &[]).err(),
+            Some(("Element count 65536*65536 is too large".to_string(), 1))
+        );
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        assert_eq!(
+            preprocess("a\n#endif", &[]).err(),
+            Some(("Unexpected #endif".to_string(), 2))
+        );
+        assert_eq!(
+            preprocess("#ifdef A\n#else\n#else\n#endif", &[]).err(),
+            Some(("Unexpected #else".to_string(), 3))
+        );
+        assert_eq!(
+            preprocess("#else", &[]).err(),
+            Some(("Unexpected #else".to_string(), 1))
+        );
+        assert_eq!(
+            preprocess("#ifdef A\n#ifdef B\n#endif", &[]).err(),
+            Some(("Unterminated #ifdef".to_string(), 1))
+        );
+    }
+}
@@ -0,0 +1,650 @@
+//! Reading textures and buffers back to the host, as screenshots, recordings, pixel values
+//! and state snapshots, and writing state back.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::record;
+use crate::state::{FrameDump, StateBlob};
+use crate::{bind, blit, context, utils, Gamut, WgpuToyRenderer};
+use std::mem::take;
+
+/// The format screenshots are drawn in, before being encoded as PNG.
+const SCREENSHOT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// The screen blitter of screenshots, see `WgpuToyRenderer::screenshot_blitter`.
+pub(crate) struct ScreenshotBlitter {
+    blitter: blit::Blitter,
+    /// The output settings the blitter reads, kept for as long as it is used
+    _settings: wgpu::Buffer,
+}
+
+/// A copy of a texture layer in a staging buffer, whose rows are padded to
+/// `COPY_BYTES_PER_ROW_ALIGNMENT`.
+pub(crate) struct TextureReadback {
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    row_bytes: usize,
+    padded_row_bytes: usize,
+}
+
+impl TextureReadback {
+    pub(crate) async fn read_bytes(self) -> Result<Vec<u8>, String> {
+        let bytes = WgpuToyRenderer::read_staging_buffer(self.buffer).await?;
+        Ok(bytes
+            .chunks(self.padded_row_bytes)
+            .flat_map(|row| &row[..self.row_bytes])
+            .copied()
+            .collect())
+    }
+
+    pub(crate) async fn read(self) -> Result<Vec<f32>, String> {
+        let format = self.format;
+        Ok(bind::texels_to_f32(format, &self.read_bytes().await?))
+    }
+
+    /// Encode a float texture as an OpenEXR image.
+    pub(crate) async fn read_exr(self) -> Result<Vec<u8>, String> {
+        let width = self.row_bytes / self.format.block_copy_size(None).unwrap_or(4) as usize;
+        utils::encode_exr(width, &self.read().await?)
+    }
+
+    /// Encode an `Rgba8Unorm` texture as PNG, ignoring its alpha if `opaque`.
+    pub(crate) async fn read_png(self, opaque: bool) -> Result<Vec<u8>, String> {
+        let width = (self.row_bytes / 4) as u32;
+        let height = (self.buffer.size() / self.padded_row_bytes as u64) as u32;
+        let mut bytes = self.read_bytes().await?;
+        if opaque {
+            for texel in bytes.chunks_mut(4) {
+                texel[3] = u8::MAX;
+            }
+        }
+        let image = image::RgbaImage::from_raw(width, height, bytes)
+            .ok_or("Screenshot has the wrong size")?;
+        let mut png = std::io::Cursor::new(vec![]);
+        image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .map_err(|e| e.to_string())?;
+        Ok(png.into_inner())
+    }
+}
+
+/// Copies of the storage buffers and pass layers in staging buffers, for `snapshot_state`.
+pub(crate) struct StateReadback {
+    frame: u32,
+    storage: Vec<(String, wgpu::Buffer)>,
+    passes: Vec<TextureReadback>,
+}
+
+impl StateReadback {
+    pub(crate) async fn read(self) -> Result<StateBlob, String> {
+        let mut storage = vec![];
+        for (name, buffer) in self.storage {
+            storage.push((name, WgpuToyRenderer::read_staging_buffer(buffer).await?));
+        }
+        let mut passes = vec![];
+        for pass in self.passes {
+            passes.push(pass.read_bytes().await?);
+        }
+        Ok(StateBlob {
+            frame: self.frame,
+            storage,
+            passes,
+        })
+    }
+}
+
+/// Copies of everything `dump_frame_debug` returns in staging buffers.
+pub(crate) struct FrameDumpReadback {
+    frame: u32,
+    width: u32,
+    height: u32,
+    screen: TextureReadback,
+    passes: Vec<TextureReadback>,
+    storage: Vec<(String, wgpu::Buffer)>,
+}
+
+impl FrameDumpReadback {
+    pub(crate) async fn read(self) -> Result<FrameDump, String> {
+        let mut passes = vec![];
+        for pass in self.passes {
+            passes.push(pass.read().await?);
+        }
+        let mut storage = vec![];
+        for (name, buffer) in self.storage {
+            storage.push((name, WgpuToyRenderer::read_staging_buffer(buffer).await?));
+        }
+        Ok(FrameDump {
+            frame: self.frame,
+            width: self.width,
+            height: self.height,
+            screen: self.screen.read().await?,
+            passes,
+            storage,
+        })
+    }
+}
+
+impl WgpuToyRenderer {
+    /// Copy `len` bytes of a storage buffer, starting at `offset`, into a staging buffer to be
+    /// mapped by `read_staging_buffer`.
+    pub(crate) fn copy_storage_buffer(
+        &self,
+        name: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<wgpu::Buffer, String> {
+        let buffer = self.storage_buffer_range(name, offset, len)?;
+        let staging = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("storage staging"),
+            size: len,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.wgpu.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(buffer, offset, &staging, 0, len);
+        self.wgpu.queue.submit(Some(encoder.finish()));
+        Ok(staging)
+    }
+
+    pub(crate) async fn read_staging_buffer(buf: wgpu::Buffer) -> Result<Vec<u8>, String> {
+        let buffer_slice = buf.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| match sender.send(v) {
+            Ok(()) => {}
+            Err(_) => log::error!("Channel closed unexpectedly"),
+        });
+        let result = match receiver.receive().await {
+            None => Err("Channel closed unexpectedly".to_string()),
+            Some(Err(e)) => Err(e.to_string()),
+            Some(Ok(())) => Ok(buffer_slice.get_mapped_range().to_vec()),
+        };
+        buf.unmap();
+        result
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn read_storage_buffer_async(
+        &self,
+        name: &str,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, String> {
+        Self::read_staging_buffer(self.copy_storage_buffer(name, offset, len)?).await
+    }
+
+    /// Upload data into a storage buffer at the given byte offset, e.g. to seed a simulation
+    /// with initial positions. The offset and length must be multiples of 4. The contents are
+    /// lost when the buffer is recreated, on a resize or when its declaration changes.
+    pub fn write_storage_buffer(&self, name: &str, offset: u64, data: &[u8]) -> Result<(), String> {
+        let buffer = self.storage_buffer_range(name, offset, data.len() as u64)?;
+        self.wgpu.queue.write_buffer(buffer, offset, data);
+        Ok(())
+    }
+
+    /// Copy the latest output of a pass layer into a staging buffer.
+    pub(crate) fn copy_pass_texture(&self, layer: u32) -> Result<TextureReadback, String> {
+        let pass_count = self.source.pass_count.unwrap_or(self.pass_count);
+        if layer >= pass_count {
+            return Err(format!(
+                "Pass layer {layer} does not exist, as there are {pass_count}"
+            ));
+        }
+        let (texture, array_layer) = self.bindings.pass_texture(layer);
+        Ok(self.copy_texture(texture, array_layer))
+    }
+
+    /// Copy a layer of a texture into a staging buffer.
+    pub(crate) fn copy_texture(
+        &self,
+        texture: &wgpu::Texture,
+        array_layer: u32,
+    ) -> TextureReadback {
+        self.copy_texture_region(
+            texture,
+            array_layer,
+            [0, 0],
+            [texture.width(), texture.height()],
+        )
+    }
+
+    /// Copy a rectangle of a texture layer, from `origin` of the given `size`, into a staging buffer.
+    fn copy_texture_region(
+        &self,
+        texture: &wgpu::Texture,
+        array_layer: u32,
+        origin: [u32; 2],
+        size: [u32; 2],
+    ) -> TextureReadback {
+        let format = texture.format();
+        let row_bytes = size[0] * format.block_copy_size(None).unwrap_or(4);
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pass staging"),
+            size: (padded_row_bytes * size[1]) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.wgpu.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin[0],
+                    y: origin[1],
+                    z: array_layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+        );
+        self.wgpu.queue.submit(Some(encoder.finish()));
+        TextureReadback {
+            buffer,
+            format,
+            row_bytes: row_bytes as usize,
+            padded_row_bytes: padded_row_bytes as usize,
+        }
+    }
+
+    /// A blitter drawing the screen as it appears on the surface but in sRGB, to reuse for
+    /// each frame of a capture rather than creating its pipeline every frame.
+    pub(crate) fn screenshot_blitter(&self) -> ScreenshotBlitter {
+        let mut settings = self.output_settings;
+        settings.dither = 0;
+        settings.premultiply = 0;
+        settings.gamut = Gamut::Srgb as u32;
+        let settings_buffer = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screenshot settings"),
+            size: size_of::<blit::OutputSettings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        self.wgpu
+            .queue
+            .write_buffer(&settings_buffer, 0, bytemuck::bytes_of(&settings));
+        ScreenshotBlitter {
+            blitter: self.create_screen_blitter_to(SCREENSHOT_FORMAT, &settings_buffer),
+            _settings: settings_buffer,
+        }
+    }
+
+    /// Draw the screen, as it appears on the surface but in sRGB, into a texture of the given
+    /// size and copy it into a staging buffer.
+    pub(crate) fn copy_screenshot(&self, width: u32, height: u32) -> TextureReadback {
+        self.copy_screenshot_with(&self.screenshot_blitter(), width, height)
+    }
+
+    /// Like `copy_screenshot`, drawing with a blitter from `screenshot_blitter`.
+    pub(crate) fn copy_screenshot_with(
+        &self,
+        blitter: &ScreenshotBlitter,
+        width: u32,
+        height: u32,
+    ) -> TextureReadback {
+        let texture = self.wgpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("screenshot"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SCREENSHOT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let mut encoder = self.wgpu.device.create_command_encoder(&Default::default());
+        blitter
+            .blitter
+            .blit(&mut encoder, &texture.create_view(&Default::default()));
+        self.wgpu.queue.submit(Some(encoder.finish()));
+        let readback = self.copy_texture(&texture, 0);
+        texture.destroy();
+        readback
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn screenshot_async(&self) -> Result<Vec<u8>, String> {
+        let opaque = self.wgpu.surface_config.alpha_mode == wgpu::CompositeAlphaMode::Opaque;
+        self.copy_screenshot(self.screen_width, self.screen_height)
+            .read_png(opaque)
+            .await
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn screenshot_hdr_async(&self) -> Result<Vec<f32>, String> {
+        self.copy_texture(self.bindings.tex_screen.texture(), 0)
+            .read()
+            .await
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn screenshot_exr_async(&self) -> Result<Vec<u8>, String> {
+        self.copy_texture(self.bindings.tex_screen.texture(), 0)
+            .read_exr()
+            .await
+    }
+
+    /// Start writing a screenshot into `dir` for every `1 / fps` seconds of shader time, from
+    /// `frame_00000.png` onwards, e.g. to be encoded as a video by ffmpeg.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_recording(&mut self, fps: f32, dir: &str) -> Result<(), String> {
+        if self.recording.is_some() {
+            return Err("Already recording".to_string());
+        }
+        if !(fps.is_finite() && fps > 0.) {
+            return Err(format!("Frame rate must be positive, not {fps}"));
+        }
+        let elapsed = self.bindings.time.host.elapsed;
+        self.recording = Some(record::Recording::start(dir, fps, elapsed)?);
+        Ok(())
+    }
+
+    /// Stop recording, returning the number of frames written.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_recording(&mut self) -> Result<u32, String> {
+        Ok(self.recording.take().ok_or("Not recording")?.frames)
+    }
+
+    /// Render `frames` frames without presenting them, advancing the time by `dt` for each,
+    /// and copy each into a staging buffer at the given size.
+    pub(crate) fn capture_frames(
+        &mut self,
+        frames: u32,
+        dt: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<TextureReadback>, String> {
+        if !(dt.is_finite() && dt > 0.) {
+            return Err(format!("Frame interval must be positive, not {dt}"));
+        }
+        let max_size = self.wgpu.device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(format!("Cannot capture frames of {width}x{height}"));
+        }
+        let target = self.unpresented_target();
+        let view = target.create_view(&Default::default());
+        // frames rendered offline run even while paused
+        let paused = take(&mut self.paused);
+        self.bindings.time.host.delta = dt;
+        let start = self.bindings.time.host.elapsed;
+        let blitter = self.screenshot_blitter();
+        let readbacks = (0..frames)
+            .map(|i| {
+                self.bindings.time.host.elapsed = start + i as f32 * dt;
+                self.render_to(&view);
+                self.copy_screenshot_with(&blitter, width, height)
+            })
+            .collect();
+        self.bindings.time.host.elapsed = start + frames as f32 * dt;
+        self.paused = paused;
+        target.destroy();
+        Ok(readbacks)
+    }
+
+    /// A texture like the surface's for frames that are rendered but not presented.
+    pub(crate) fn unpresented_target(&self) -> wgpu::Texture {
+        self.wgpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("capture"),
+            size: wgpu::Extent3d {
+                width: self.wgpu.surface_config.width,
+                height: self.wgpu.surface_config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.wgpu.surface_config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+    }
+
+    pub(crate) fn capture_gif(
+        &mut self,
+        frames: u32,
+        fps: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<impl std::future::Future<Output = Result<Vec<u8>, String>>, String> {
+        if !(fps.is_finite() && fps > 0.) {
+            return Err(format!("Frame rate must be positive, not {fps}"));
+        }
+        if frames == 0 {
+            return Err("GIFs need at least one frame".to_string());
+        }
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(format!("GIFs cannot be {width}x{height}"));
+        }
+        let readbacks = self.capture_frames(frames, 1. / fps, width, height)?;
+        let delay = (100. / fps).round().max(1.) as u32;
+        Ok(async move {
+            let mut frames = vec![];
+            for readback in readbacks {
+                frames.push(readback.read_bytes().await?);
+            }
+            utils::encode_gif(width, height, frames, delay)
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn record_gif_async(
+        &mut self,
+        frames: u32,
+        fps: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, String> {
+        self.capture_gif(frames, fps, width, height)?.await
+    }
+
+    /// Render frames without presenting them, at the time `frame * dt` of each frame, for
+    /// frame-perfect animations independent of the real frame rate. Call `reset` first to
+    /// start from frame 0. Calls `on_frame(frame, pixels)` with each frame's sRGB RGBA8
+    /// pixels at the internal resolution, as tonemapped and post-processed for display.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn render_offline_async(
+        &mut self,
+        frame_count: u32,
+        dt: f32,
+        mut on_frame: impl FnMut(u32, Vec<u8>),
+    ) -> Result<(), String> {
+        for _ in 0..frame_count {
+            let frame = self.bindings.time.host.frame;
+            self.bindings.time.host.elapsed = frame as f32 * dt;
+            let (width, height) = (self.screen_width, self.screen_height);
+            for readback in self.capture_frames(1, dt, width, height)? {
+                on_frame(frame, readback.read_bytes().await?);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn copy_output(&self) -> Result<TextureReadback, String> {
+        match &self.wgpu.target {
+            context::Target::Offscreen(texture) => Ok(self.copy_texture(texture, 0)),
+            context::Target::Surface(_) | context::Target::Detached => {
+                Err("Only headless renderers can read back their output".to_string())
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn read_output_async(&self) -> Result<Vec<u8>, String> {
+        self.copy_output()?.read_bytes().await
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn read_pass_texture_async(&self, layer: u32) -> Result<Vec<f32>, String> {
+        self.copy_pass_texture(layer)?.read().await
+    }
+
+    /// Copy the texel at `(x, y)` of the screen, followed by each pass layer if `include_passes`.
+    pub(crate) fn copy_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        include_passes: bool,
+    ) -> Result<Vec<TextureReadback>, String> {
+        let mut textures = vec![(self.bindings.tex_screen.texture(), 0)];
+        if include_passes {
+            let pass_count = self.source.pass_count.unwrap_or(self.pass_count);
+            textures.extend((0..pass_count).map(|layer| self.bindings.pass_texture(layer)));
+        }
+        textures
+            .into_iter()
+            .map(|(texture, array_layer)| {
+                if x >= texture.width() || y >= texture.height() {
+                    return Err(format!(
+                        "({x}, {y}) is outside the {}x{} texture",
+                        texture.width(),
+                        texture.height()
+                    ));
+                }
+                Ok(self.copy_texture_region(texture, array_layer, [x, y], [1, 1]))
+            })
+            .collect()
+    }
+
+    /// Read the value of the screen at `(x, y)`, in pixels from the top left, before
+    /// tonemapping, followed by the value of each pass layer if `include_passes`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn inspect_pixel_async(
+        &self,
+        x: u32,
+        y: u32,
+        include_passes: bool,
+    ) -> Result<Vec<Vec<f32>>, String> {
+        let mut values = vec![];
+        for readback in self.copy_pixel(x, y, include_passes)? {
+            values.push(readback.read().await?);
+        }
+        Ok(values)
+    }
+
+    pub(crate) fn copy_frame_dump(&self) -> Result<FrameDumpReadback, String> {
+        let state = self.copy_state()?;
+        Ok(FrameDumpReadback {
+            frame: state.frame,
+            width: self.screen_width,
+            height: self.screen_height,
+            screen: self.copy_texture(self.bindings.tex_screen.texture(), 0),
+            passes: state.passes,
+            storage: state.storage,
+        })
+    }
+
+    /// Read back every pass layer, the screen and each storage buffer as of the last frame,
+    /// e.g. to write them to files with `FrameDump::write_files`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn dump_frame_debug_async(&self) -> Result<FrameDump, String> {
+        self.copy_frame_dump()?.read().await
+    }
+
+    pub(crate) fn copy_state(&self) -> Result<StateReadback, String> {
+        let mut storage = vec![];
+        for (buffer, binding) in self.storage_buffers.iter().zip(&self.bindings.storage) {
+            if buffer.view_of.is_none() {
+                let size = binding.buffer().size();
+                storage.push((
+                    buffer.name.clone(),
+                    self.copy_storage_buffer(&buffer.name, 0, size)?,
+                ));
+            }
+        }
+        let pass_count = self.source.pass_count.unwrap_or(self.pass_count);
+        Ok(StateReadback {
+            frame: self.bindings.time.host.frame,
+            storage,
+            passes: (0..pass_count)
+                .map(|layer| self.copy_pass_texture(layer))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn snapshot_state_async(&self) -> Result<StateBlob, String> {
+        self.copy_state()?.read().await
+    }
+
+    /// Reload a state captured by `snapshot_state`, which must come from a shader declaring
+    /// the same storage buffers and pass layers at the same resolution.
+    pub fn restore_state(&mut self, state: &StateBlob) -> Result<(), String> {
+        for (name, data) in &state.storage {
+            let index = self
+                .storage_buffers
+                .iter()
+                .position(|buffer| &buffer.name == name)
+                .ok_or(format!("No storage buffer named {name}"))?;
+            let size = self.bindings.storage[index].buffer().size();
+            if data.len() as u64 != size {
+                return Err(format!(
+                    "Storage buffer {name} is {size} bytes, but the state has {}",
+                    data.len()
+                ));
+            }
+        }
+        let pass_count = self.source.pass_count.unwrap_or(self.pass_count);
+        if state.passes.len() != pass_count as usize {
+            return Err(format!(
+                "The state has {} pass layers, but there are {pass_count}",
+                state.passes.len()
+            ));
+        }
+        for (layer, data) in state.passes.iter().enumerate() {
+            let (texture, _) = self.bindings.pass_texture(layer as u32);
+            let row_bytes = texture.width() * texture.format().block_copy_size(None).unwrap_or(4);
+            if data.len() != (row_bytes * texture.height()) as usize {
+                return Err(format!(
+                    "Pass layer {layer} is a different size or format to the state"
+                ));
+            }
+        }
+        for (name, data) in &state.storage {
+            self.write_storage_buffer(name, 0, data)?;
+        }
+        for (layer, data) in state.passes.iter().enumerate() {
+            let (texture, array_layer) = self.bindings.pass_texture(layer as u32);
+            let row_bytes = texture.width() * texture.format().block_copy_size(None).unwrap_or(4);
+            self.wgpu.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: array_layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row_bytes),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: texture.width(),
+                    height: texture.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        self.bindings.time.host.frame = state.frame;
+        Ok(())
+    }
+}
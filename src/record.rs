@@ -0,0 +1,32 @@
+//! Recording the output at a fixed frame rate as a sequence of PNG screenshots. On the web
+//! the canvas is recorded as a video instead, see `wasm`.
+
+pub struct Recording {
+    dir: std::path::PathBuf,
+    interval: f32,
+    next_time: f32,
+    pub frames: u32,
+}
+
+impl Recording {
+    /// Start writing a frame into `dir` every `1 / fps` seconds of shader time from `elapsed`.
+    pub fn start(dir: &str, fps: f32, elapsed: f32) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        Ok(Recording {
+            dir: dir.into(),
+            interval: 1. / fps,
+            next_time: elapsed,
+            frames: 0,
+        })
+    }
+
+    /// The path to write the frame rendered at `elapsed` to, if one is due.
+    pub fn next_frame(&mut self, elapsed: f32) -> Option<std::path::PathBuf> {
+        if elapsed < self.next_time {
+            return None;
+        }
+        self.next_time += self.interval * ((elapsed - self.next_time) / self.interval + 1.).floor();
+        self.frames += 1;
+        Some(self.dir.join(format!("frame_{:05}.png", self.frames - 1)))
+    }
+}
@@ -0,0 +1,199 @@
+use crate::context::WgpuContext;
+use wgpu::PipelineCompilationOptions;
+
+const TILE_SIZE: u32 = 16;
+pub const RESULT_SIZE: u64 = 4 * std::mem::size_of::<f32>() as u64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReduceOp {
+    Min,
+    Max,
+    Sum,
+    Average,
+}
+
+impl ReduceOp {
+    fn identity(self) -> &'static str {
+        match self {
+            ReduceOp::Min => "vec4<f32>(3.40282347e38)",
+            ReduceOp::Max => "vec4<f32>(-3.40282347e38)",
+            ReduceOp::Sum | ReduceOp::Average => "vec4<f32>(0.)",
+        }
+    }
+    fn combine(self) -> &'static str {
+        match self {
+            ReduceOp::Min => "min(a, b)",
+            ReduceOp::Max => "max(a, b)",
+            ReduceOp::Sum | ReduceOp::Average => "a + b",
+        }
+    }
+    fn finalise(self) -> &'static str {
+        match self {
+            ReduceOp::Average => "value / f32(size.x * size.y)",
+            _ => "value",
+        }
+    }
+}
+
+/// Reduces a texture to a single value in two passes: each workgroup reduces a tile into
+/// `partials`, then a single workgroup reduces the partials into `result`.
+pub struct Reduction {
+    pub op: ReduceOp,
+    /// The pass layer to reduce, or the screen if `None`
+    pub pass_index: Option<u32>,
+    layout: wgpu::BindGroupLayout,
+    tiles_pipeline: wgpu::ComputePipeline,
+    partials_pipeline: wgpu::ComputePipeline,
+    partials: Option<wgpu::Buffer>,
+    pub result: wgpu::Buffer,
+}
+
+impl Drop for Reduction {
+    fn drop(&mut self) {
+        self.result.destroy();
+        if let Some(partials) = &self.partials {
+            partials.destroy();
+        }
+    }
+}
+
+impl Reduction {
+    pub fn new(wgpu: &WgpuContext, op: ReduceOp, pass_index: Option<u32>) -> Self {
+        let shader = wgpu
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("reduction"),
+                source: wgpu::ShaderSource::Wgsl(
+                    include_str!("reduce.wgsl")
+                        .replace("IDENTITY", op.identity())
+                        .replace("COMBINE", op.combine())
+                        .replace("FINALISE", op.finalise())
+                        .into(),
+                ),
+            });
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = wgpu
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    storage(1),
+                    storage(2),
+                ],
+            });
+        let pipeline_layout = wgpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = |entry_point| {
+            wgpu.device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("reduction"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                    compilation_options: PipelineCompilationOptions::default(),
+                })
+        };
+        Reduction {
+            op,
+            pass_index,
+            tiles_pipeline: pipeline("reduce_tiles"),
+            partials_pipeline: pipeline("reduce_partials"),
+            layout,
+            partials: None,
+            result: wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: RESULT_SIZE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    /// The size of the buffers holding the partial and final results.
+    pub fn buffer_bytes(&self) -> u64 {
+        self.result.size() + self.partials.as_ref().map_or(0, wgpu::Buffer::size)
+    }
+
+    /// Append the reduction passes of the given texture layer to the encoder.
+    pub fn encode(
+        &mut self,
+        wgpu: &WgpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        layer: u32,
+    ) {
+        let tiles = [
+            texture.width().div_ceil(TILE_SIZE),
+            texture.height().div_ceil(TILE_SIZE),
+        ];
+        let partials_size = (tiles[0] * tiles[1]) as u64 * RESULT_SIZE;
+        if self.partials.as_ref().map(wgpu::Buffer::size) != Some(partials_size) {
+            if let Some(partials) = &self.partials {
+                partials.destroy();
+            }
+            self.partials = Some(wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: partials_size,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }));
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        let bind_group = wgpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.partials.as_ref().unwrap().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.result.as_entire_binding(),
+                },
+            ],
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("reduction"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.set_pipeline(&self.tiles_pipeline);
+        compute_pass.dispatch_workgroups(tiles[0], tiles[1], 1);
+        compute_pass.set_pipeline(&self.partials_pipeline);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+}
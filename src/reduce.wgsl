@@ -0,0 +1,52 @@
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> partials: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> result: vec4<f32>;
+
+var<workgroup> values: array<vec4<f32>, 256>;
+
+fn combine(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    return COMBINE;
+}
+
+fn reduce_workgroup(local: u32) -> vec4<f32> {
+    for (var stride = 128u; stride > 0u; stride >>= 1u) {
+        workgroupBarrier();
+        if (local < stride) {
+            values[local] = combine(values[local], values[local + stride]);
+        }
+    }
+    return values[0];
+}
+
+@compute @workgroup_size(16, 16)
+fn reduce_tiles(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let size = textureDimensions(src);
+    var value = IDENTITY;
+    if (all(id.xy < size)) {
+        value = textureLoad(src, id.xy, 0);
+    }
+    values[local] = value;
+    let reduced = reduce_workgroup(local);
+    if (local == 0u) {
+        partials[group.y * groups.x + group.x] = reduced;
+    }
+}
+
+@compute @workgroup_size(256)
+fn reduce_partials(@builtin(local_invocation_index) local: u32) {
+    let size = textureDimensions(src);
+    var value = IDENTITY;
+    for (var i = local; i < arrayLength(&partials); i += 256u) {
+        value = combine(value, partials[i]);
+    }
+    values[local] = value;
+    value = reduce_workgroup(local);
+    if (local == 0u) {
+        result = FINALISE;
+    }
+}
@@ -0,0 +1,470 @@
+use lazy_regex::*;
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+use serde::Serialize;
+use std::collections::HashMap;
+
+static RE_BINDING: Lazy<Regex> =
+    lazy_regex!(r"@group\(0\) @binding\((\d+)\) var(?:<([^>]*)>)? (\w+): ([^;]+);");
+
+/// A shader that naga has parsed and validated.
+pub struct Reflection {
+    module: naga::Module,
+    info: ModuleInfo,
+}
+
+/// Why a shader failed to parse or validate, the 1-based rows and columns of the start and
+/// end of the code at fault, and how to fix it if naga suggests how.
+pub struct ShaderError {
+    pub message: String,
+    pub location: Option<(usize, usize)>,
+    pub end: Option<(usize, usize)>,
+    pub fix: Option<String>,
+}
+
+/// The 1-based row and column of the end of a span.
+fn span_end(wgsl: &str, span: naga::Span) -> Option<(usize, usize)> {
+    let end = span.to_range()?.end.min(wgsl.len());
+    let line_start = wgsl[..end].rfind('\n').map_or(0, |i| i + 1);
+    Some((wgsl[..end].matches('\n').count() + 1, end - line_start + 1))
+}
+
+/// Parse and validate a shader, so that errors can be reported before wgpu creates the pipelines.
+pub fn validate(wgsl: &str) -> Result<Reflection, ShaderError> {
+    let module = naga::front::wgsl::parse_str(wgsl).map_err(|e| parse_error(wgsl, &e))?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| validation_error(wgsl, &e))?;
+    Ok(Reflection { module, info })
+}
+
+fn location(loc: naga::SourceLocation) -> (usize, usize) {
+    (loc.line_number as usize, loc.line_position as usize)
+}
+
+fn parse_error(wgsl: &str, e: &naga::front::wgsl::ParseError) -> ShaderError {
+    ShaderError {
+        message: e.message().to_string(),
+        location: e.location(wgsl).map(location),
+        end: e.labels().next().and_then(|(span, _)| span_end(wgsl, span)),
+        // the notes aren't public, so take them from the rendered error
+        fix: e
+            .emit_to_string(wgsl)
+            .lines()
+            .find_map(|line| line.trim_start().strip_prefix("= note: "))
+            .map(str::to_string),
+    }
+}
+
+fn validation_error(wgsl: &str, e: &naga::WithSpan<naga::valid::ValidationError>) -> ShaderError {
+    // the outer error only names the function, so include the errors it was caused by
+    let mut message = e.as_inner().to_string();
+    let mut source = std::error::Error::source(e.as_inner());
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    ShaderError {
+        message,
+        location: e.location(wgsl).map(location),
+        end: e.spans().next().and_then(|(span, _)| span_end(wgsl, *span)),
+        fix: None,
+    }
+}
+
+/// The error behind a shader module that wgpu failed to create from `wgsl`, located from naga's
+/// spans natively, or from the `:row:col message` that browsers report.
+pub fn shader_module_error(e: &wgpu::Error, wgsl: &str) -> Option<ShaderError> {
+    let wgpu::Error::Validation { description, .. } = e else {
+        return None;
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(e) = naga_module_error(e, wgsl) {
+        return Some(e);
+    }
+    let cap = regex!(r"(?s):(\d+):(\d+) (.*)").captures(description)?;
+    Some(ShaderError {
+        message: cap[3].to_string(),
+        location: Some((cap[1].parse().unwrap_or(0), cap[2].parse().unwrap_or(0))),
+        end: None,
+        fix: None,
+    })
+}
+
+/// The naga error behind a shader module that wgpu failed to create, which is only available
+/// natively. Modules given as naga IR have no source of their own, so are located in `wgsl`.
+#[cfg(not(target_arch = "wasm32"))]
+fn naga_module_error(e: &wgpu::Error, wgsl: &str) -> Option<ShaderError> {
+    use wgpu::core::pipeline::CreateShaderModuleError;
+    let wgpu::Error::Validation { source, .. } = e else {
+        return None;
+    };
+    let mut cause: Option<&(dyn std::error::Error + 'static)> = Some(source.as_ref());
+    while let Some(e) = cause {
+        match e.downcast_ref::<CreateShaderModuleError>() {
+            Some(CreateShaderModuleError::Parsing(e)) => {
+                return Some(parse_error(&e.source, &e.inner))
+            }
+            Some(CreateShaderModuleError::Validation(e)) if e.source.is_empty() => {
+                return Some(validation_error(wgsl, &e.inner))
+            }
+            Some(CreateShaderModuleError::Validation(e)) => {
+                return Some(validation_error(&e.source, &e.inner))
+            }
+            Some(_) => return None,
+            None => cause = e.source(),
+        }
+    }
+    None
+}
+
+impl Reflection {
+    /// The keys pipeline-overridable constants can be set by, their names and their ids if
+    /// given, with their types.
+    pub fn overrides(&self) -> HashMap<String, naga::Scalar> {
+        self.module
+            .overrides
+            .iter()
+            .filter_map(|(_, o)| Some((o, self.module.types[o.ty].inner.scalar()?)))
+            .flat_map(|(o, scalar)| {
+                o.name
+                    .clone()
+                    .into_iter()
+                    .chain(o.id.map(|id| id.to_string()))
+                    .map(move |key| (key, scalar))
+            })
+            .collect()
+    }
+
+    /// Bindings in group 0 that each entry point actually uses.
+    pub fn used_bindings(&self) -> HashMap<String, Vec<u32>> {
+        self.module
+            .entry_points
+            .iter()
+            .enumerate()
+            .map(|(i, entry_point)| {
+                let function_info = self.info.get_entry_point(i);
+                let mut bindings: Vec<u32> = self
+                    .module
+                    .global_variables
+                    .iter()
+                    .filter(|(handle, _)| !function_info[*handle].is_empty())
+                    .filter_map(|(_, var)| var.binding.as_ref())
+                    .filter(|binding| binding.group == 0)
+                    .map(|binding| binding.binding)
+                    .collect();
+                bindings.sort_unstable();
+                (entry_point.name.clone(), bindings)
+            })
+            .collect()
+    }
+
+    /// Size in bytes of the buffer backing each named storage variable. Runtime-sized arrays hold
+    /// `count` elements if given, otherwise the buffer gets `default_size` bytes.
+    pub fn storage_sizes(
+        &self,
+        storage: &[(String, Option<u32>)],
+        default_size: u64,
+    ) -> Result<Vec<u64>, String> {
+        let module = &self.module;
+        let mut layouter = naga::proc::Layouter::default();
+        layouter
+            .update(module.to_ctx())
+            .map_err(|e| e.to_string())?;
+        storage
+            .iter()
+            .map(|(name, count)| {
+                let var = module
+                    .global_variables
+                    .iter()
+                    .map(|(_, var)| var)
+                    .find(|var| var.name.as_deref() == Some(name))
+                    .ok_or(format!("Storage buffer {name} not found"))?;
+                // the fixed part of the type, and the stride of its runtime-sized array if any
+                let (fixed, stride) = match &module.types[var.ty].inner {
+                    naga::TypeInner::Array {
+                        size: naga::ArraySize::Dynamic,
+                        stride,
+                        ..
+                    } => (0, Some(*stride)),
+                    naga::TypeInner::Struct { members, .. } => match members.last() {
+                        Some(last) => match module.types[last.ty].inner {
+                            naga::TypeInner::Array {
+                                size: naga::ArraySize::Dynamic,
+                                stride,
+                                ..
+                            } => (last.offset, Some(stride)),
+                            _ => (layouter[var.ty].size, None),
+                        },
+                        None => (layouter[var.ty].size, None),
+                    },
+                    _ => (layouter[var.ty].size, None),
+                };
+                match (stride, count) {
+                    (Some(stride), Some(count)) => Ok(fixed as u64 + stride as u64 * *count as u64),
+                    (Some(_), None) => Ok(default_size),
+                    (None, Some(_)) => Err(format!(
+                        "Storage buffer {name} has a fixed size, so cannot be given an element count"
+                    )),
+                    (None, None) => Ok(fixed as u64),
+                }
+            })
+            .collect()
+    }
+
+    /// The module, to create the shader from without parsing it again.
+    pub fn into_module(self) -> naga::Module {
+        self.module
+    }
+}
+
+/// Check that a pipeline-overridable constant of the given type can take a value, as the
+/// constants are all given as `f64`.
+pub fn check_override(name: &str, scalar: naga::Scalar, value: f64) -> Result<(), String> {
+    use naga::ScalarKind;
+    let (ty, valid) = match (scalar.kind, scalar.width) {
+        (ScalarKind::Bool, _) => ("bool", !value.is_nan()),
+        (ScalarKind::Sint, _) => (
+            "i32",
+            value.fract() == 0. && (i32::MIN as f64..=i32::MAX as f64).contains(&value),
+        ),
+        (ScalarKind::Uint, _) => (
+            "u32",
+            value.fract() == 0. && (0. ..=u32::MAX as f64).contains(&value),
+        ),
+        (ScalarKind::Float, 2) => ("f16", value.abs() <= 65504.),
+        (ScalarKind::Float, _) => ("f32", value.abs() <= f32::MAX as f64),
+        _ => return Ok(()),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{value} is not a valid value of {name}, which is {ty}"
+        ))
+    }
+}
+
+/// Write a module translated from another shading language as WGSL.
+#[cfg(any(feature = "glsl", feature = "spirv"))]
+pub fn to_wgsl(module: &naga::Module) -> Result<String, String> {
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(module)
+        .map_err(|e| e.into_inner().to_string())?;
+    naga::back::wgsl::write_string(module, &info, naga::back::wgsl::WriterFlags::empty())
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Serialize)]
+struct PreludeAlias {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[derive(Serialize)]
+struct PreludeMember {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[derive(Serialize)]
+struct PreludeStruct {
+    name: String,
+    members: Vec<PreludeMember>,
+}
+
+#[derive(Serialize)]
+struct PreludeBinding {
+    binding: u32,
+    name: String,
+    /// The address space and access mode, e.g. `storage,read_write`, if a buffer
+    space: Option<String>,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[derive(Serialize)]
+struct PreludeFunction {
+    name: String,
+    params: Vec<PreludeMember>,
+    returns: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PreludeDescription {
+    aliases: Vec<PreludeAlias>,
+    structs: Vec<PreludeStruct>,
+    bindings: Vec<PreludeBinding>,
+    functions: Vec<PreludeFunction>,
+}
+
+/// Split a list at the commas that aren't within angle brackets, e.g. in `array<u32,4>`.
+fn split_list(list: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in list.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(&list[start..]);
+    items
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn parse_members(list: &str) -> Vec<PreludeMember> {
+    split_list(list)
+        .into_iter()
+        .filter_map(|member| member.split_once(':'))
+        .map(|(name, ty)| PreludeMember {
+            name: name.trim().to_string(),
+            ty: ty.trim().to_string(),
+        })
+        .collect()
+}
+
+/// Describe the declarations of the prelude as JSON, for editors to offer completions and
+/// documentation of them. Internal declarations, whose names start with `_`, are left out.
+pub fn describe_prelude(prelude: &str) -> String {
+    let public = |name: &str| !name.starts_with('_');
+    let description = PreludeDescription {
+        aliases: regex!(r"(?m)^alias (\w+) = (.+);$")
+            .captures_iter(prelude)
+            .map(|cap| PreludeAlias {
+                name: cap[1].to_string(),
+                ty: cap[2].to_string(),
+            })
+            .collect(),
+        structs: regex!(r"(?s)struct (\w+) \{(.*?)\}")
+            .captures_iter(prelude)
+            .filter(|cap| public(&cap[1]))
+            .map(|cap| PreludeStruct {
+                name: cap[1].to_string(),
+                members: parse_members(&cap[2]),
+            })
+            .collect(),
+        bindings: RE_BINDING
+            .captures_iter(prelude)
+            .filter(|cap| public(&cap[3]))
+            .map(|cap| PreludeBinding {
+                binding: cap[1].parse().unwrap_or_default(),
+                space: cap.get(2).map(|m| m.as_str().to_string()),
+                name: cap[3].to_string(),
+                ty: cap[4].to_string(),
+            })
+            .collect(),
+        functions: regex!(r"fn (\w+)\(([^)]*)\)(?:\s*->\s*([^{]+?))?\s*\{")
+            .captures_iter(prelude)
+            .filter(|cap| public(&cap[1]))
+            .map(|cap| PreludeFunction {
+                name: cap[1].to_string(),
+                params: parse_members(&cap[2]),
+                returns: cap.get(3).map(|m| m.as_str().to_string()),
+            })
+            .collect(),
+    };
+    serde_json::to_string(&description).unwrap()
+}
+
+#[derive(Serialize)]
+pub struct BindingReflection {
+    pub binding: u32,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct EntryPointReflection {
+    pub name: String,
+    /// The pass name given by a `/// name:` comment, or the entry point name
+    pub label: String,
+    pub enabled: bool,
+    pub workgroup_size: [u32; 3],
+    pub workgroup_count: [u32; 3],
+    pub dispatch_count: u32,
+    pub dispatch_once: bool,
+    pub dispatch_every: u32,
+    pub bindings: Vec<BindingReflection>,
+}
+
+#[derive(Serialize)]
+pub struct UniformReflection {
+    pub name: String,
+    pub value: f32,
+    /// The default, range and step declared by `#param`, if any
+    pub default: Option<f32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub step: Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct StorageReflection {
+    pub name: String,
+    pub size: u64,
+}
+
+/// What a compiled shader consists of, for hosts to build their UI from.
+#[derive(Serialize)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPointReflection>,
+    pub custom_uniforms: Vec<UniformReflection>,
+    pub storage: Vec<StorageReflection>,
+}
+
+/// The names of the variables declared in group 0, by binding.
+pub fn binding_names(wgsl: &str) -> HashMap<u32, String> {
+    RE_BINDING
+        .captures_iter(wgsl)
+        .filter_map(|cap| Some((cap[1].parse().ok()?, cap[3].to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_values() {
+        let scalar = |kind, width| naga::Scalar { kind, width };
+        let int = scalar(naga::ScalarKind::Sint, 4);
+        let uint = scalar(naga::ScalarKind::Uint, 4);
+        let float = scalar(naga::ScalarKind::Float, 4);
+        assert!(check_override("n", int, -3.).is_ok());
+        assert_eq!(
+            check_override("n", int, 1.5),
+            Err("1.5 is not a valid value of n, which is i32".to_string())
+        );
+        assert!(check_override("n", uint, -1.).is_err());
+        assert!(check_override("n", uint, 4294967296.).is_err());
+        assert!(check_override("x", float, 0.25).is_ok());
+        assert!(check_override("x", float, f64::INFINITY).is_err());
+        assert!(check_override("x", float, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn shader_module_error_from_message() {
+        // as browsers report it, without naga's error behind it
+        let e = wgpu::Error::Validation {
+            source: Box::new(std::fmt::Error),
+            description: "Error while parsing WGSL: :12:5 unknown identifier: 'x'".to_string(),
+        };
+        let e = shader_module_error(&e, "").unwrap();
+        assert_eq!(e.message, "unknown identifier: 'x'");
+        assert_eq!(e.location, Some((12, 5)));
+        let e = wgpu::Error::Validation {
+            source: Box::new(std::fmt::Error),
+            description: "Buffer is invalid".to_string(),
+        };
+        assert!(shader_module_error(&e, "").is_none());
+    }
+}
@@ -0,0 +1,915 @@
+//! Rendering frames: dispatching the compute passes, blitting the screen to the output, and
+//! reading back the results the host is told about afterwards, along with the inputs and
+//! clock the passes see.
+
+use crate::callback::{DebugPrintCallback, NanCallback};
+use crate::channel::zero_texture;
+use crate::context;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::context::WgpuContext;
+use crate::preprocessor::WGSLError;
+use crate::stats::{CompileStats, FrameStats, MemoryUsage, PassTimings};
+use crate::{
+    bind, create_output_buffer, error_message, nancheck, on_scope_error, reduce, watch_device,
+    ReduceOp, WgpuToyRenderer, ASSERTS_SIZE, SHADER_ERROR, STATS_PERIOD,
+};
+use std::collections::HashMap;
+use std::mem::take;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of frames `seek` runs before waiting for the GPU to finish them.
+const SEEK_BATCH: u32 = 32;
+
+/// Format the value of a `debug_print_*` record according to its kind.
+fn format_debug_print(kind: u32, value: &[u32]) -> String {
+    match kind {
+        0 => f32::from_bits(value[0]).to_string(),
+        1 => value[0].to_string(),
+        2 => (value[0] as i32).to_string(),
+        _ => {
+            let [x, y, z, w] = [0, 1, 2, 3].map(|i| f32::from_bits(value[i]));
+            format!("({x}, {y}, {z}, {w})")
+        }
+    }
+}
+
+impl WgpuToyRenderer {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn render_async(&mut self) {
+        self.render_overlaid_async(|_, _| ()).await
+    }
+
+    /// Render a frame like `render_async`, letting `overlay` draw over it before it is
+    /// presented, e.g. a user interface.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn render_overlaid_async(
+        &mut self,
+        overlay: impl FnOnce(&WgpuContext, &wgpu::TextureView),
+    ) {
+        if self.device_lost.load(Ordering::SeqCst) {
+            log::warn!("Requesting a new device");
+            match self.wgpu.request_new_device().await {
+                Ok(device) => self.restore_device(device),
+                Err(e) => {
+                    log::error!("Unable to replace the lost device: {e}");
+                    return;
+                }
+            }
+        }
+        if let Some((view, frame)) = self.acquire_frame() {
+            let (staging_buffer, _) = self.render_to(&view);
+            overlay(&self.wgpu, &view);
+            if let Some(frame) = frame {
+                frame.present();
+            }
+            Self::postrender(
+                staging_buffer,
+                self.screen_width * self.screen_height,
+                self.source.assert_map.clone(),
+                self.on_debug_print_cb.clone(),
+            )
+            .await;
+            if let Some(buf) = self.finished_staging.take() {
+                Self::check_finished(buf, self.finished.clone()).await
+            }
+            if let Some(buf) = self.reduction_staging.take() {
+                Self::read_reductions(buf, self.reduction_results.clone()).await
+            }
+            if let Some((buf, textures)) = self.nan_check_staging.take() {
+                Self::read_nan_check(
+                    buf,
+                    textures,
+                    self.nan_reported.clone(),
+                    self.on_nan_cb.clone(),
+                )
+                .await
+            }
+            if let Some((buf, names)) = self.timestamp_staging.take() {
+                Self::read_timestamps(
+                    buf,
+                    names,
+                    self.wgpu.queue.get_timestamp_period(),
+                    self.pass_timings.clone(),
+                )
+                .await
+            }
+            let elapsed = self.bindings.time.host.elapsed;
+            if let Some(path) = self.recording.as_mut().and_then(|r| r.next_frame(elapsed)) {
+                let opaque =
+                    self.wgpu.surface_config.alpha_mode == wgpu::CompositeAlphaMode::Opaque;
+                let png = self
+                    .copy_screenshot(self.screen_width, self.screen_height)
+                    .read_png(opaque)
+                    .await;
+                if let Err(e) =
+                    png.and_then(|png| std::fs::write(path, png).map_err(|e| e.to_string()))
+                {
+                    log::error!("Unable to record frame: {e}");
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_surface(
+        &mut self,
+        frame: &wgpu::SurfaceTexture,
+    ) -> (Option<wgpu::Buffer>, wgpu::SubmissionIndex) {
+        self.render_to(&frame.texture.create_view(&Default::default()))
+    }
+
+    /// Render a frame into the given view rather than the surface, to composite the output
+    /// into an application's own render graph. The view must belong to `wgpu.device` and have
+    /// the format of `wgpu.surface_config`, see `set_output_format`, and should have the size
+    /// given to `resize`, as the frame is stretched to fill it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_texture(
+        &mut self,
+        view: &wgpu::TextureView,
+    ) -> (Option<wgpu::Buffer>, wgpu::SubmissionIndex) {
+        self.render_to(view)
+    }
+
+    /// CPU timings and the frame rate of the recent frames, e.g. for an FPS overlay.
+    pub fn get_frame_stats(&self) -> FrameStats {
+        self.frame_timer.stats()
+    }
+
+    /// The GPU time of each entry point, measured every 100 frames, e.g. to find the slowest.
+    pub fn get_pass_timings(&self) -> PassTimings {
+        self.pass_timings.lock().unwrap().clone()
+    }
+
+    /// Measure the GPU time of each entry point every frame rather than every 100 frames,
+    /// e.g. to benchmark a shader, at the cost of reading the timings back each frame.
+    pub fn set_profile_every_frame(&mut self, enabled: bool) {
+        self.profile_every_frame = enabled;
+    }
+
+    /// How long the last successful `compile` took, by stage, to tell whether stutters
+    /// come from compiling a large shader.
+    pub fn get_compile_stats(&self) -> CompileStats {
+        self.compile_stats
+    }
+
+    /// Switch to a new device, recreating the bindings, blitters, reductions and channels on
+    /// it and recompiling the current shader. The contents of the pass textures and storage
+    /// buffers are lost, so the shader restarts from frame 0.
+    pub(crate) fn restore_device(&mut self, device: context::NewDevice) {
+        self.wgpu.replace_device(device);
+        self.device_lost = watch_device(&self.wgpu);
+        self.output_buffer = create_output_buffer(&self.wgpu);
+        self.write_output_settings();
+        self.finished_staging = None;
+        self.reduction_staging = None;
+        self.nan_check_staging = None;
+        self.query_set = None;
+        self.timestamp_staging = None;
+        self.reset_bindings();
+        for index in 0..self.bindings.channels.len() {
+            let binding = match self.channel_sources.get(&index) {
+                Some(source) => self.channel_binding(index, source),
+                None => Ok(bind::TextureBinding::channel(
+                    index,
+                    bind::blank_texture(&self.wgpu),
+                )),
+            };
+            match binding {
+                Ok(binding) => {
+                    self.bindings.set_channel(index, binding);
+                }
+                Err(e) => log::error!("Unable to reload channel {index}: {e}"),
+            }
+        }
+        self.reductions = self
+            .reductions
+            .iter()
+            .map(|r| reduce::Reduction::new(&self.wgpu, r.op, r.pass_index))
+            .collect();
+        if self.nan_check.is_some() {
+            self.nan_check = Some(nancheck::NanCheck::new(&self.wgpu));
+        }
+        let compiled = !self.compute_pipelines.is_empty();
+        self.last_compute_pipelines = None;
+        self.compute_pipelines.clear();
+        self.compute_shader = None;
+        if compiled {
+            self.compile(self.source.clone());
+        }
+        self.on_device_restored_cb.call();
+    }
+
+    /// The GPU memory allocated by the renderer, e.g. to see what a higher resolution or
+    /// `set_pass_f32` costs.
+    pub fn get_memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.bindings.memory_usage();
+        if let context::Target::Offscreen(texture) = &self.wgpu.target {
+            usage.screen += bind::texture_bytes(texture) as f64;
+        }
+        usage.other += (self.output_buffer.size()
+            + self
+                .reductions
+                .iter()
+                .map(reduce::Reduction::buffer_bytes)
+                .sum::<u64>()) as f64;
+        usage.totalled()
+    }
+
+    /// The view to draw the next frame to, with the surface texture to present afterwards,
+    /// or `None` if the surface is unavailable.
+    pub(crate) fn acquire_frame(
+        &mut self,
+    ) -> Option<(wgpu::TextureView, Option<wgpu::SurfaceTexture>)> {
+        use wgpu::SurfaceError;
+
+        let surface = match &self.wgpu.target {
+            context::Target::Surface(surface) => surface,
+            context::Target::Offscreen(texture) => {
+                return Some((texture.create_view(&Default::default()), None));
+            }
+            context::Target::Detached => return None,
+        };
+        match surface.get_current_texture() {
+            Err(err) => {
+                match err {
+                    SurfaceError::Lost | SurfaceError::Outdated => {
+                        // e.g. after a resize, or once the device is lost, when the surface is
+                        // configured for the new device as soon as it is ready
+                        log::warn!("Unable to get framebuffer: {err}");
+                        if !self.device_lost.load(Ordering::SeqCst) {
+                            self.wgpu.configure_surface();
+                        }
+                        #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
+                        if let Some(window) = &self.wgpu.window {
+                            window.request_redraw();
+                        }
+                    }
+                    SurfaceError::OutOfMemory => log::error!("Out of GPU Memory!"),
+                    SurfaceError::Timeout => log::warn!("Surface Timeout"),
+                }
+                None
+            }
+            Ok(frame) => Some((frame.texture.create_view(&Default::default()), Some(frame))),
+        }
+    }
+
+    /// Downsample the screen into each level of `screen_mips`.
+    fn blit_screen_mips(&self, encoder: &mut wgpu::CommandEncoder) {
+        for (blitter, view) in &self.screen_mip_blitters {
+            blitter.blit(encoder, view);
+        }
+    }
+
+    pub(crate) fn render_to(
+        &mut self,
+        view: &wgpu::TextureView,
+    ) -> (Option<wgpu::Buffer>, wgpu::SubmissionIndex) {
+        let start = instant::Instant::now();
+        let mut encoder = self.wgpu.device.create_command_encoder(&Default::default());
+        if self.paused && !take(&mut self.step) {
+            self.screen_blitter.blit_viewport(
+                &mut encoder,
+                view,
+                self.background_color(),
+                self.viewport,
+            );
+            return (None, self.wgpu.queue.submit(Some(encoder.finish())));
+        }
+        self.wgpu
+            .device
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        self.bindings.stage(&self.wgpu.queue);
+        if self.bindings.time.host.frame.is_multiple_of(STATS_PERIOD) {
+            //encoder.clear_buffer(&self.uniforms.debug_buffer, 0, None); // not yet implemented in web backend
+            self.wgpu.queue.write_buffer(
+                self.bindings.debug_buffer.buffer(),
+                0,
+                // the assertion counters and the print count
+                bytemuck::bytes_of(&[0u32; bind::NUM_ASSERT_COUNTERS + 1]),
+            );
+
+            if self.bindings.time.host.frame > 0 {
+                let mean = self.last_stats.elapsed().as_secs_f32() / STATS_PERIOD as f32;
+                self.last_stats = instant::Instant::now();
+                log::debug!("{} fps ({} ms)", 1. / mean, 1e3 * mean);
+            }
+        }
+        if SHADER_ERROR.swap(false, Ordering::SeqCst) {
+            match take(&mut self.last_compute_pipelines) {
+                None => log::warn!("unable to rollback shader after error"),
+                Some(vec) => {
+                    self.compute_pipelines = vec;
+                    // the query set was sized for the pipelines that failed
+                    self.query_set = None;
+                    self.rebuild_bind_groups();
+                }
+            }
+        }
+        let mut dispatch_counter = 0;
+        let profiled = self.query_set.is_some()
+            && (self.profile_every_frame
+                || self.bindings.time.host.frame % STATS_PERIOD == STATS_PERIOD - 1);
+        // the labels of the entry points timed, whose timestamps are in this order
+        let mut timed: Vec<String> = vec![];
+        let pass_bind_groups = 1 + self.source.pass_ping_pong as usize;
+        // the mip chain is generated once the last entry point that may write the screen has
+        // run, so that the entry points after it sample this frame, and those up to it the last
+        let screen_binding = self.bindings.screen_binding();
+        let mips_after = self
+            .compute_pipelines
+            .iter()
+            .rposition(|p| {
+                !self.disabled_entry_points.contains(&p.name)
+                    && p.bindings.contains(&screen_binding)
+            })
+            .filter(|_| self.generate_screen_mips);
+        for (index, p) in self.compute_pipelines.iter().enumerate() {
+            if self.disabled_entry_points.contains(&p.name) {
+                continue;
+            }
+            let frame = self.bindings.time.host.frame;
+            if (!p.dispatch_once || frame == 0) && frame.is_multiple_of(p.dispatch_every) {
+                for i in 0..p.dispatch_count {
+                    if let Some(slot) = p.dispatch_indirect {
+                        let offset = slot as u64 * bind::INDIRECT_ARGS_SIZE;
+                        encoder.copy_buffer_to_buffer(
+                            self.bindings.dispatch_indirect.buffer(),
+                            offset,
+                            &self.bindings.indirect_args,
+                            offset,
+                            bind::INDIRECT_ARGS_SIZE,
+                        );
+                    }
+                    if profiled && i == 0 {
+                        timed.push(p.label.clone());
+                    }
+                    // from the start of the first dispatch to the end of the last
+                    let query = 2 * timed.len() as u32;
+                    let timestamp_writes = self
+                        .query_set
+                        .as_ref()
+                        .filter(|_| profiled && (i == 0 || i + 1 == p.dispatch_count))
+                        .map(|query_set| wgpu::ComputePassTimestampWrites {
+                            query_set,
+                            beginning_of_pass_write_index: (i == 0).then_some(query - 2),
+                            end_of_pass_write_index: (i + 1 == p.dispatch_count)
+                                .then_some(query - 1),
+                        });
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some(&p.label),
+                            timestamp_writes,
+                        });
+                    let workgroup_count = p.workgroup_count(self.screen_width, self.screen_height);
+                    compute_pass.set_pipeline(&p.pipeline);
+                    self.wgpu.queue.write_buffer(
+                        self.bindings.dispatch_info.buffer(),
+                        bind::OFFSET_ALIGNMENT as u64 * dispatch_counter,
+                        bytemuck::bytes_of(&i),
+                    );
+                    let offsets = [bind::OFFSET_ALIGNMENT as u32 * dispatch_counter as u32];
+                    compute_pass.set_bind_group(
+                        0,
+                        &p.bind_groups[self.storage_swapped as usize * pass_bind_groups
+                            + self.pass_swapped as usize],
+                        if p.dynamic_offset { &offsets } else { &[] },
+                    );
+                    dispatch_counter += 1;
+                    match p.dispatch_indirect {
+                        Some(slot) => compute_pass.dispatch_workgroups_indirect(
+                            &self.bindings.indirect_args,
+                            slot as u64 * bind::INDIRECT_ARGS_SIZE,
+                        ),
+                        None => compute_pass.dispatch_workgroups(
+                            workgroup_count[0],
+                            workgroup_count[1],
+                            workgroup_count[2],
+                        ),
+                    }
+                    drop(compute_pass);
+                    if self.source.pass_ping_pong {
+                        // the output of this dispatch becomes the input of the next
+                        self.pass_swapped = !self.pass_swapped;
+                        self.bindings.swap_pass_textures();
+                        continue;
+                    }
+                    encoder.copy_texture_to_texture(
+                        wgpu::ImageCopyTexture {
+                            texture: self.bindings.tex_write.texture(),
+                            mip_level: 0,
+                            origin: wgpu::Origin3d::ZERO,
+                            aspect: wgpu::TextureAspect::All,
+                        },
+                        wgpu::ImageCopyTexture {
+                            texture: self.bindings.tex_read.texture(),
+                            mip_level: 0,
+                            origin: wgpu::Origin3d::ZERO,
+                            aspect: wgpu::TextureAspect::All,
+                        },
+                        wgpu::Extent3d {
+                            width: self.screen_width,
+                            height: self.screen_height,
+                            depth_or_array_layers: self
+                                .bindings
+                                .tex_read
+                                .texture()
+                                .depth_or_array_layers(),
+                        },
+                    );
+                    for layer in &self.bindings.pass_layers {
+                        encoder.copy_texture_to_texture(
+                            layer.write.texture().as_image_copy(),
+                            layer.read.texture().as_image_copy(),
+                            layer.read.texture().size(),
+                        );
+                    }
+                }
+            }
+            if mips_after == Some(index) {
+                self.blit_screen_mips(&mut encoder);
+            }
+        }
+        if self.bindings.has_storage_swaps() {
+            // this frame's output is read by the next
+            self.bindings.swap_storage_buffers();
+            self.storage_swapped = !self.storage_swapped;
+        }
+        let mut staging_buffer = None;
+        if self.bindings.time.host.frame % STATS_PERIOD == STATS_PERIOD - 1 {
+            let buf = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: bind::DEBUG_BUFFER_SIZE as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            encoder.copy_buffer_to_buffer(
+                self.bindings.debug_buffer.buffer(),
+                0,
+                &buf,
+                0,
+                bind::DEBUG_BUFFER_SIZE as wgpu::BufferAddress,
+            );
+            staging_buffer = Some(buf);
+        }
+        if let Some(query_set) = self.query_set.as_ref().filter(|_| !timed.is_empty()) {
+            let count = 2 * timed.len() as u32;
+            let size = count as u64 * size_of::<u64>() as u64;
+            // query results can't be resolved into a mappable buffer
+            let resolved = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let buf = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            encoder.resolve_query_set(query_set, 0..count, &resolved, 0);
+            encoder.copy_buffer_to_buffer(&resolved, 0, &buf, 0, size);
+            self.timestamp_staging = Some((buf, timed));
+        }
+        if self.poll_finished && !self.finished.load(Ordering::SeqCst) {
+            let buf = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: size_of::<u32>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            encoder.copy_buffer_to_buffer(
+                self.bindings.finished.buffer(),
+                0,
+                &buf,
+                0,
+                size_of::<u32>() as wgpu::BufferAddress,
+            );
+            self.finished_staging = Some(buf);
+        }
+        if !self.reductions.is_empty() {
+            let buf = self.wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: self.reductions.len() as u64 * reduce::RESULT_SIZE,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            for (i, reduction) in self.reductions.iter_mut().enumerate() {
+                let (texture, layer) = match reduction.pass_index {
+                    Some(index) => self.bindings.pass_texture(index),
+                    None => (self.bindings.tex_screen.texture(), 0),
+                };
+                reduction.encode(&self.wgpu, &mut encoder, texture, layer);
+                encoder.copy_buffer_to_buffer(
+                    &reduction.result,
+                    0,
+                    &buf,
+                    i as u64 * reduce::RESULT_SIZE,
+                    reduce::RESULT_SIZE,
+                );
+            }
+            self.reduction_staging = Some(buf);
+        }
+        if let Some(nan_check) = &mut self.nan_check {
+            let screen = self.bindings.tex_screen.texture();
+            let mut textures = vec![(screen, 0)];
+            let mut names = vec![("screen".to_string(), screen.width())];
+            for index in 0..self.source.pass_count.unwrap_or(self.pass_count) {
+                let (texture, layer) = self.bindings.pass_texture(index);
+                if bind::sample_scalar(texture.format()) == "f32" {
+                    textures.push((texture, layer));
+                    names.push((format!("pass {index}"), texture.width()));
+                }
+            }
+            let buf = nan_check.encode(&self.wgpu, &mut encoder, &textures);
+            self.nan_check_staging = Some((buf, names));
+        }
+        if self.generate_screen_mips && mips_after.is_none() {
+            self.blit_screen_mips(&mut encoder);
+        }
+        self.bindings.time.host.frame = self.bindings.time.host.frame.wrapping_add(1);
+        self.screen_blitter.blit_viewport(
+            &mut encoder,
+            view,
+            self.background_color(),
+            self.viewport,
+        );
+
+        let commands = encoder.finish();
+        let encoded = instant::Instant::now();
+        let i = self.wgpu.queue.submit(Some(commands));
+        on_scope_error(self.wgpu.device.pop_error_scope(), |e| {
+            log::error!("{e}");
+            // dispatching pipelines which failed to compile has already been reported
+            if !SHADER_ERROR.swap(true, Ordering::SeqCst) {
+                WGSLError::handler(&error_message(&e), 0, 0);
+            }
+        });
+        self.frame_timer
+            .record(start, encoded, instant::Instant::now());
+        (staging_buffer, i)
+    }
+
+    pub(crate) async fn postrender(
+        staging_buffer: Option<wgpu::Buffer>,
+        numthreads: u32,
+        assert_map: Vec<usize>,
+        on_debug_print: DebugPrintCallback,
+    ) {
+        if let Some(buf) = staging_buffer {
+            let buffer_slice = buf.slice(..);
+            let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+            buffer_slice.map_async(wgpu::MapMode::Read, move |v| match sender.send(v) {
+                Ok(()) => {}
+                Err(_) => log::error!("Channel closed unexpectedly"),
+            });
+            match receiver.receive().await {
+                None => log::error!("Channel closed unexpectedly"),
+                Some(Err(e)) => log::error!("{e}"),
+                Some(Ok(())) => {
+                    let data = buffer_slice.get_mapped_range();
+                    let assertions: &[u32] = bytemuck::cast_slice(&data[0..ASSERTS_SIZE]);
+                    let prints: &[u32] =
+                        bytemuck::cast_slice(&data[ASSERTS_SIZE..bind::DEBUG_BUFFER_SIZE]);
+                    for (i, count) in assertions.iter().enumerate() {
+                        if count > &0 {
+                            let percent =
+                                *count as f32 / (numthreads * STATS_PERIOD) as f32 * 100.0;
+                            log::warn!("Assertion {i} failed in {percent}% of threads");
+                            if i < assert_map.len() {
+                                WGSLError::handler(
+                                    &format!("Assertion failed in {percent}% of threads"),
+                                    assert_map[i],
+                                    0,
+                                );
+                            }
+                        }
+                    }
+                    let count = prints[0] as usize;
+                    for record in prints[1..]
+                        .chunks_exact(bind::DEBUG_PRINT_SIZE / size_of::<u32>())
+                        .take(count)
+                    {
+                        on_debug_print
+                            .call(record[0], &format_debug_print(record[1], &record[2..]));
+                    }
+                    if count > bind::MAX_DEBUG_PRINTS {
+                        log::warn!(
+                            "Dropped {} debug prints, the maximum is {} per {STATS_PERIOD} frames",
+                            count - bind::MAX_DEBUG_PRINTS,
+                            bind::MAX_DEBUG_PRINTS
+                        );
+                    }
+                }
+            }
+            buf.unmap();
+        }
+    }
+
+    pub(crate) async fn check_finished(buf: wgpu::Buffer, finished: Arc<AtomicBool>) {
+        let buffer_slice = buf.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| match sender.send(v) {
+            Ok(()) => {}
+            Err(_) => log::error!("Channel closed unexpectedly"),
+        });
+        match receiver.receive().await {
+            None => log::error!("Channel closed unexpectedly"),
+            Some(Err(e)) => log::error!("{e}"),
+            Some(Ok(())) => {
+                let data = buffer_slice.get_mapped_range();
+                if bytemuck::cast_slice::<u8, u32>(&data)[0] != 0 {
+                    log::info!("Shader signalled that it has finished");
+                    finished.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+        buf.unmap();
+    }
+
+    pub(crate) async fn read_reductions(
+        buf: wgpu::Buffer,
+        results: Arc<Mutex<Vec<Option<[f32; 4]>>>>,
+    ) {
+        let buffer_slice = buf.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| match sender.send(v) {
+            Ok(()) => {}
+            Err(_) => log::error!("Channel closed unexpectedly"),
+        });
+        match receiver.receive().await {
+            None => log::error!("Channel closed unexpectedly"),
+            Some(Err(e)) => log::error!("{e}"),
+            Some(Ok(())) => {
+                let data = buffer_slice.get_mapped_range();
+                let values: &[[f32; 4]] = bytemuck::cast_slice(&data);
+                let mut results = results.lock().unwrap();
+                // reductions may have been added or cleared since this frame was rendered
+                for (result, value) in results.iter_mut().zip(values) {
+                    *result = Some(*value);
+                }
+            }
+        }
+        buf.unmap();
+    }
+
+    /// Read the start and end timestamps of each entry point, where `period` is the number of
+    /// nanoseconds per tick.
+    pub(crate) async fn read_timestamps(
+        buf: wgpu::Buffer,
+        names: Vec<String>,
+        period: f32,
+        timings: Arc<Mutex<PassTimings>>,
+    ) {
+        let buffer_slice = buf.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| match sender.send(v) {
+            Ok(()) => {}
+            Err(_) => log::error!("Channel closed unexpectedly"),
+        });
+        match receiver.receive().await {
+            None => log::error!("Channel closed unexpectedly"),
+            Some(Err(e)) => log::error!("{e}"),
+            Some(Ok(())) => {
+                let data = buffer_slice.get_mapped_range();
+                let ticks: &[[u64; 2]] = bytemuck::cast_slice(&data);
+                let ms = ticks
+                    .iter()
+                    .map(|[start, end]| end.saturating_sub(*start) as f32 * period * 1e-6)
+                    .collect();
+                *timings.lock().unwrap() = PassTimings { names, ms };
+            }
+        }
+        buf.unmap();
+    }
+
+    pub(crate) async fn read_nan_check(
+        buf: wgpu::Buffer,
+        textures: Vec<(String, u32)>,
+        reported: Arc<Mutex<HashMap<String, [u32; 2]>>>,
+        on_nan: NanCallback,
+    ) {
+        let buffer_slice = buf.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| match sender.send(v) {
+            Ok(()) => {}
+            Err(_) => log::error!("Channel closed unexpectedly"),
+        });
+        match receiver.receive().await {
+            None => log::error!("Channel closed unexpectedly"),
+            Some(Err(e)) => log::error!("{e}"),
+            Some(Ok(())) => {
+                let data = buffer_slice.get_mapped_range();
+                let results: &[u32] = bytemuck::cast_slice(&data);
+                let mut reported = reported.lock().unwrap();
+                for ((name, width), &index) in textures.into_iter().zip(results) {
+                    if index == nancheck::NONE_FOUND {
+                        reported.remove(&name);
+                        continue;
+                    }
+                    let pos = [index % width, index / width];
+                    if reported.get(&name) != Some(&pos) {
+                        on_nan.call(&name, pos[0], pos[1]);
+                        reported.insert(name, pos);
+                    }
+                }
+            }
+        }
+        buf.unmap();
+    }
+
+    /// Whether the current shader has called `signalFinished()` since the last reset.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Reduce the screen (or a pass, if `pass_index` is given) to a single value after every frame,
+    /// returning an id for `reduction_result`.
+    pub fn request_reduction(
+        &mut self,
+        op: ReduceOp,
+        pass_index: Option<u32>,
+    ) -> Result<usize, String> {
+        if let Some(index) = pass_index {
+            let pass_count = self.source.pass_count.unwrap_or(self.pass_count);
+            if index >= pass_count {
+                return Err(format!("Pass {index} does not exist"));
+            }
+            let (texture, _) = self.bindings.pass_texture(index);
+            if bind::sample_scalar(texture.format()) != "f32" {
+                return Err(format!("Pass {index} does not have a float format"));
+            }
+        }
+        self.reductions
+            .push(reduce::Reduction::new(&self.wgpu, op, pass_index));
+        self.reduction_results.lock().unwrap().push(None);
+        Ok(self.reductions.len() - 1)
+    }
+
+    /// The latest value of a reduction, available a few frames after it was requested.
+    pub fn reduction_result(&self, id: usize) -> Option<Vec<f32>> {
+        self.reduction_results
+            .lock()
+            .unwrap()
+            .get(id)
+            .copied()
+            .flatten()
+            .map(Vec::from)
+    }
+
+    pub fn clear_reductions(&mut self) {
+        self.reductions.clear();
+        self.reduction_results.lock().unwrap().clear();
+    }
+
+    /// Scan the screen and the float pass layers for NaN or infinite values after every frame,
+    /// reporting the first offending pixel of each, row by row from the top left, to the
+    /// `on_nan` callback (or the log). A pixel is reported again only once it has moved or the
+    /// texture has been clean for a frame.
+    pub fn set_nan_check(&mut self, enabled: bool) {
+        self.nan_check = enabled.then(|| nancheck::NanCheck::new(&self.wgpu));
+        self.nan_reported.lock().unwrap().clear();
+    }
+
+    pub fn set_time_elapsed(&mut self, t: f32) {
+        self.bindings.time.host.elapsed = t;
+    }
+
+    pub fn set_time_delta(&mut self, t: f32) {
+        self.bindings.time.host.delta = t;
+    }
+
+    /// The `time.frame` of the next frame, the number run since the last reset.
+    pub fn frame(&self) -> u32 {
+        self.bindings.time.host.frame
+    }
+
+    /// Jump to a frame without running the frames in between, for shaders that only depend
+    /// on the time. The host sets `time.elapsed` to match, or `seek` runs the frames between.
+    pub fn set_frame(&mut self, frame: u32) {
+        self.bindings.time.host.frame = frame;
+    }
+
+    /// Run the shader up to `frame` without presenting, for a timeline scrubber of
+    /// deterministic shaders: from the current frame if `frame` is ahead of it, or from frame 0
+    /// with cleared state if it is behind. Each frame is at the time `frame * dt`, as in
+    /// `render_offline`, and the next frame rendered is `frame`, at the time `frame * dt`.
+    pub fn seek(&mut self, frame: u32, dt: f32) -> Result<(), String> {
+        if !(dt.is_finite() && dt > 0.) {
+            return Err(format!("Frame interval must be positive, not {dt}"));
+        }
+        if frame < self.bindings.time.host.frame {
+            self.clear_state();
+        }
+        let target = self.unpresented_target();
+        let view = target.create_view(&Default::default());
+        let paused = take(&mut self.paused);
+        self.bindings.time.host.delta = dt;
+        while self.bindings.time.host.frame < frame {
+            self.bindings.time.host.elapsed = self.bindings.time.host.frame as f32 * dt;
+            let (_, submission) = self.render_to(&view);
+            // bound the work queued, rather than submitting thousands of frames at once.
+            // Browsers schedule the work themselves, so this only waits natively
+            if (frame - self.bindings.time.host.frame).is_multiple_of(SEEK_BATCH) {
+                self.wgpu
+                    .device
+                    .poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+            }
+        }
+        self.bindings.time.host.elapsed = frame as f32 * dt;
+        self.paused = paused;
+        target.destroy();
+        Ok(())
+    }
+
+    pub fn set_mouse_pos(&mut self, x: f32, y: f32) {
+        let (x, y) = match self.viewport {
+            Some([left, top, width, height]) => (
+                ((x * self.wgpu.surface_config.width as f32 - left) / width).clamp(0., 1.),
+                ((y * self.wgpu.surface_config.height as f32 - top) / height).clamp(0., 1.),
+            ),
+            None => (x, y),
+        };
+        if self.bindings.mouse.host.click == 1 {
+            self.bindings.mouse.host.pos = [
+                (x * self.screen_width as f32) as u32,
+                (y * self.screen_height as f32) as u32,
+            ];
+        }
+    }
+
+    /// Set the mouse position in logical pixels from the top left of the canvas or window,
+    /// e.g. a `MouseEvent`'s `offsetX` and `offsetY`, as the fraction of the surface that
+    /// `set_mouse_pos` takes. The shader's `mouse.pos` is in pixels of the screen texture
+    /// either way, so is the same on displays of any pixel ratio.
+    pub fn set_mouse_pos_logical(&mut self, x: f32, y: f32) {
+        self.set_mouse_pos(
+            x * self.pixel_ratio / self.wgpu.surface_config.width as f32,
+            y * self.pixel_ratio / self.wgpu.surface_config.height as f32,
+        );
+    }
+
+    pub fn set_mouse_click(&mut self, click: bool) {
+        self.bindings.mouse.host.click = if click { 1 } else { 0 };
+    }
+
+    pub fn set_keydown(&mut self, keycode: usize, keydown: bool) {
+        self.bindings.keys.host.set(keycode, keydown);
+    }
+
+    /// Stop running the shader: until `resume`, rendering presents the last frame again without
+    /// dispatching any entry points or advancing `time.frame`. Hosts should also stop
+    /// advancing `time.elapsed`.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// While paused, run the next frame rendered in full, dispatching the entry points and
+    /// advancing `time.frame` by one, then stay paused, to watch a simulation evolve frame by
+    /// frame. The host sets the frame's time beforehand as usual.
+    pub fn step_frame(&mut self) {
+        self.step = self.paused;
+    }
+
+    /// Recreate the pass textures and storage buffers and restart from frame 0,
+    /// so `#init`/`#dispatch_once` and `init*` entry points run again.
+    pub fn reset(&mut self) {
+        if self.reset_bindings() {
+            self.recompile();
+        } else {
+            self.rebuild_bind_groups();
+        }
+    }
+
+    /// Zero the pass textures and storage buffers and restart from frame 0, like `reset`
+    /// but without recreating them.
+    pub fn clear_state(&mut self) {
+        let mut encoder = self.wgpu.device.create_command_encoder(&Default::default());
+        for storage in &self.bindings.storage {
+            encoder.clear_buffer(storage.buffer(), 0, None);
+        }
+        self.wgpu.queue.submit(Some(encoder.finish()));
+        let pass_textures = [&self.bindings.tex_read, &self.bindings.tex_write]
+            .into_iter()
+            .chain(
+                self.bindings
+                    .pass_layers
+                    .iter()
+                    .flat_map(|layer| [&layer.read, &layer.write]),
+            );
+        for binding in pass_textures {
+            zero_texture(&self.wgpu, binding.texture());
+        }
+        self.bindings.time.host.frame = 0;
+        self.finished.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,8 @@
+use crate::reflect;
+
+/// Translate a SPIR-V module to WGSL, so that it can be compiled like any other shader.
+pub fn to_wgsl(spirv: &[u8]) -> Result<String, String> {
+    let module = naga::front::spv::parse_u8_slice(spirv, &naga::front::spv::Options::default())
+        .map_err(|e| format!("Invalid SPIR-V: {e}"))?;
+    reflect::to_wgsl(&module)
+}
@@ -0,0 +1,162 @@
+const MAGIC: &[u8; 4] = b"WTS1";
+
+/// The contents of a shader's storage buffers and pass layers at a given frame, captured by
+/// `snapshot_state` and reloaded by `restore_state` to resume a simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateBlob {
+    pub(crate) frame: u32,
+    /// The bytes of each storage buffer, by name
+    pub(crate) storage: Vec<(String, Vec<u8>)>,
+    /// The unpadded texels of each pass layer, in order
+    pub(crate) passes: Vec<Vec<u8>>,
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<&[u8], String> {
+        if len > self.0.len() {
+            return Err("State is truncated".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn data(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        Ok(self.bytes(len)?.to_vec())
+    }
+}
+
+fn push_data(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+impl StateBlob {
+    /// The frame the state was captured after.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Serialise the state, e.g. to save it to a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&self.frame.to_le_bytes());
+        out.extend_from_slice(&(self.storage.len() as u32).to_le_bytes());
+        for (name, data) in &self.storage {
+            push_data(&mut out, name.as_bytes());
+            push_data(&mut out, data);
+        }
+        out.extend_from_slice(&(self.passes.len() as u32).to_le_bytes());
+        for data in &self.passes {
+            push_data(&mut out, data);
+        }
+        out
+    }
+
+    /// Deserialise a state written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<StateBlob, String> {
+        let mut reader = Reader(bytes);
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err("Not a saved state".to_string());
+        }
+        let frame = reader.u32()?;
+        let storage = (0..reader.u32()?)
+            .map(|_| {
+                let name = String::from_utf8(reader.data()?).map_err(|e| e.to_string())?;
+                Ok((name, reader.data()?))
+            })
+            .collect::<Result<_, String>>()?;
+        let passes = (0..reader.u32()?)
+            .map(|_| reader.data())
+            .collect::<Result<_, String>>()?;
+        Ok(StateBlob {
+            frame,
+            storage,
+            passes,
+        })
+    }
+}
+
+/// Every pass layer, the screen and each storage buffer as of one frame, from
+/// `dump_frame_debug`. Textures are RGBA values, row by row from the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameDump {
+    pub(crate) frame: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) screen: Vec<f32>,
+    pub(crate) passes: Vec<Vec<f32>>,
+    pub(crate) storage: Vec<(String, Vec<u8>)>,
+}
+
+impl FrameDump {
+    /// The number of frames rendered before the dump.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The screen before tonemapping and post-processing.
+    pub fn screen(&self) -> Vec<f32> {
+        self.screen.clone()
+    }
+
+    pub fn pass_count(&self) -> u32 {
+        self.passes.len() as u32
+    }
+
+    pub fn pass(&self, layer: u32) -> Option<Vec<f32>> {
+        self.passes.get(layer as usize).cloned()
+    }
+
+    pub fn storage_names(&self) -> Vec<String> {
+        self.storage.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    pub fn storage(&self, name: &str) -> Option<Vec<u8>> {
+        self.storage
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, data)| data.clone())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FrameDump {
+    /// Write the screen and pass layers into `dir` as OpenEXR images of their exact values,
+    /// `screen.exr` and `pass0.exr` onwards, and the storage buffers as `{name}.bin`.
+    pub fn write_files(&self, dir: &str) -> Result<(), String> {
+        let dir = std::path::Path::new(dir);
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let write = |name: String, data: &[u8]| {
+            std::fs::write(dir.join(&name), data).map_err(|e| format!("{name}: {e}"))
+        };
+        let width = self.width as usize;
+        write(
+            "screen.exr".to_string(),
+            &crate::utils::encode_exr(width, &self.screen)?,
+        )?;
+        for (layer, pass) in self.passes.iter().enumerate() {
+            write(
+                format!("pass{layer}.exr"),
+                &crate::utils::encode_exr(width, pass)?,
+            )?;
+        }
+        for (name, data) in &self.storage {
+            write(format!("{name}.bin"), data)?;
+        }
+        Ok(())
+    }
+}
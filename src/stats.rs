@@ -0,0 +1,140 @@
+use instant::Instant;
+use std::collections::VecDeque;
+
+/// The number of frames the statistics are averaged over.
+const WINDOW: usize = 60;
+
+/// Timings of the recent frames rendered, averaged over the last 60, in milliseconds.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    /// Time spent on the CPU recording each frame's commands
+    pub encode_ms: f32,
+    /// Time spent submitting each frame's commands to the queue
+    pub submit_ms: f32,
+    /// Time between the starts of consecutive frames
+    pub interval_ms: f32,
+    /// The longest time between consecutive frames
+    pub max_interval_ms: f32,
+    /// Frames per second, from the mean interval
+    pub fps: f32,
+}
+
+/// Where the time went in the last shader compilation, in milliseconds.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CompileStats {
+    /// Preprocessing, including fetching includes and textures
+    pub preprocess_ms: f32,
+    /// Parsing and validating the shader with naga, skipped for shaders using extensions
+    pub parse_ms: f32,
+    /// Creating the shader module and the pipeline of each entry point, which browsers may
+    /// partly defer until the first dispatch
+    pub pipeline_ms: f32,
+    /// The sum of the above along with everything else done by `compile`
+    pub total_ms: f32,
+}
+
+/// The GPU memory allocated by the renderer, in bytes. Drivers may pad or compress
+/// allocations, so the memory actually used may differ.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MemoryUsage {
+    /// The `pass_in`/`pass_out` textures and the layers declared with `#pass_format`
+    pub passes: f64,
+    /// The screen, its mipmaps and the output texture when headless
+    pub screen: f64,
+    pub channels: f64,
+    pub storage: f64,
+    /// Uniforms, debug and indirect dispatch buffers, and the buffers of reductions
+    pub other: f64,
+    pub total: f64,
+}
+
+impl MemoryUsage {
+    /// Set `total` to the sum of the other fields.
+    pub(crate) fn totalled(self) -> Self {
+        MemoryUsage {
+            total: self.passes + self.screen + self.channels + self.storage + self.other,
+            ..self
+        }
+    }
+}
+
+/// The GPU time taken by each entry point in a recent frame, in milliseconds, measured with
+/// timestamp queries. Empty if the device doesn't support them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PassTimings {
+    pub(crate) names: Vec<String>,
+    pub(crate) ms: Vec<f32>,
+}
+
+impl PassTimings {
+    /// The `#pass_name` of each entry point dispatched, or its name, in the order dispatched.
+    pub fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+
+    /// The time from the start of each entry point's first dispatch to the end of its last.
+    pub fn ms(&self) -> Vec<f32> {
+        self.ms.clone()
+    }
+
+    pub fn total_ms(&self) -> f32 {
+        self.ms.iter().sum()
+    }
+}
+
+struct Sample {
+    encode: f32,
+    submit: f32,
+    interval: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct FrameTimer {
+    samples: VecDeque<Sample>,
+    last_start: Option<Instant>,
+}
+
+fn ms_between(start: Instant, end: Instant) -> f32 {
+    (end - start).as_secs_f32() * 1e3
+}
+
+impl FrameTimer {
+    /// Record the timings of a frame which started at `start`, finished encoding at `encoded`
+    /// and was submitted at `submitted`.
+    pub fn record(&mut self, start: Instant, encoded: Instant, submitted: Instant) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            encode: ms_between(start, encoded),
+            submit: ms_between(encoded, submitted),
+            interval: self.last_start.map(|last| ms_between(last, start)),
+        });
+        self.last_start = Some(start);
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        if self.samples.is_empty() {
+            return FrameStats::default();
+        }
+        let frames = self.samples.len() as f32;
+        let intervals = self.samples.iter().filter_map(|s| s.interval);
+        let interval_count = intervals.clone().count();
+        let interval_ms = if interval_count > 0 {
+            intervals.clone().sum::<f32>() / interval_count as f32
+        } else {
+            0.
+        };
+        FrameStats {
+            encode_ms: self.samples.iter().map(|s| s.encode).sum::<f32>() / frames,
+            submit_ms: self.samples.iter().map(|s| s.submit).sum::<f32>() / frames,
+            interval_ms,
+            max_interval_ms: intervals.fold(0., f32::max),
+            fps: if interval_ms > 0. {
+                1e3 / interval_ms
+            } else {
+                0.
+            },
+        }
+    }
+}
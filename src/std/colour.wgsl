@@ -0,0 +1,39 @@
+// Colour space conversions and palettes
+
+fn srgbToLinear(c: float3) -> float3 {
+    return select(pow((c + .055) / 1.055, float3(2.4)), c / 12.92, c <= float3(.04045));
+}
+
+fn linearToSrgb(c: float3) -> float3 {
+    return select(1.055 * pow(c, float3(1. / 2.4)) - .055, 12.92 * c, c <= float3(.0031308));
+}
+
+fn luminance(c: float3) -> float {
+    return dot(c, float3(.2126, .7152, .0722));
+}
+
+// https://www.shadertoy.com/view/MsS3Wc
+fn hsvToRgb(c: float3) -> float3 {
+    let rgb = clamp(abs((c.x * 6. + float3(0., 4., 2.)) % 6. - 3.) - 1., float3(0.), float3(1.));
+    return c.z * mix(float3(1.), rgb, c.y);
+}
+
+// https://stackoverflow.com/a/17897228
+fn rgbToHsv(c: float3) -> float3 {
+    let K = float4(0., -1. / 3., 2. / 3., -1.);
+    let p = mix(float4(c.bg, K.wz), float4(c.gb, K.xy), step(c.b, c.g));
+    let q = mix(float4(p.xyw, c.r), float4(c.r, p.yzx), step(p.x, c.r));
+    let d = q.x - min(q.w, q.y);
+    let e = 1e-10;
+    return float3(abs(q.z + (q.w - q.y) / (6. * d + e)), d / (q.x + e), q.x);
+}
+
+// https://iquilezles.org/articles/palettes/
+fn palette(t: float, a: float3, b: float3, c: float3, d: float3) -> float3 {
+    return a + b * cos(6.28318530718 * (c * t + d));
+}
+
+// ACES filmic tonemapping curve, https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn acesFilm(x: float3) -> float3 {
+    return clamp((x * (2.51 * x + .03)) / (x * (2.43 * x + .59) + .14), float3(0.), float3(1.));
+}
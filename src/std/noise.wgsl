@@ -0,0 +1,108 @@
+// Hashes and noise functions
+// Hash without Sine by Dave Hoskins, MIT License: https://www.shadertoy.com/view/4djSRW
+
+fn hash11(p: float) -> float {
+    var x = fract(p * .1031);
+    x *= x + 33.33;
+    x *= x + x;
+    return fract(x);
+}
+
+fn hash12(p: float2) -> float {
+    var p3 = fract(float3(p.xyx) * .1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn hash13(p: float3) -> float {
+    var p3 = fract(p * .1031);
+    p3 += dot(p3, p3.zyx + 31.32);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn hash22(p: float2) -> float2 {
+    var p3 = fract(float3(p.xyx) * float3(.1031, .1030, .0973));
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.xx + p3.yz) * p3.zy);
+}
+
+fn hash33(p: float3) -> float3 {
+    var p3 = fract(p * float3(.1031, .1030, .0973));
+    p3 += dot(p3, p3.yxz + 33.33);
+    return fract((p3.xxy + p3.yxx) * p3.zyx);
+}
+
+// Value noise in [0, 1]
+fn valueNoise2(p: float2) -> float {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3. - 2. * f);
+    return mix(mix(hash12(i), hash12(i + float2(1., 0.)), u.x),
+               mix(hash12(i + float2(0., 1.)), hash12(i + float2(1., 1.)), u.x), u.y);
+}
+
+fn valueNoise3(p: float3) -> float {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3. - 2. * f);
+    return mix(mix(mix(hash13(i), hash13(i + float3(1., 0., 0.)), u.x),
+                   mix(hash13(i + float3(0., 1., 0.)), hash13(i + float3(1., 1., 0.)), u.x), u.y),
+               mix(mix(hash13(i + float3(0., 0., 1.)), hash13(i + float3(1., 0., 1.)), u.x),
+                   mix(hash13(i + float3(0., 1., 1.)), hash13(i + float3(1., 1., 1.)), u.x), u.y), u.z);
+}
+
+// Gradient noise in [-1, 1]
+fn gradientNoise2(p: float2) -> float {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3. - 2. * f);
+    let g00 = 2. * hash22(i) - 1.;
+    let g10 = 2. * hash22(i + float2(1., 0.)) - 1.;
+    let g01 = 2. * hash22(i + float2(0., 1.)) - 1.;
+    let g11 = 2. * hash22(i + float2(1., 1.)) - 1.;
+    return mix(mix(dot(g00, f), dot(g10, f - float2(1., 0.)), u.x),
+               mix(dot(g01, f - float2(0., 1.)), dot(g11, f - float2(1., 1.)), u.x), u.y);
+}
+
+fn gradientNoise3(p: float3) -> float {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3. - 2. * f);
+    var n = 0.;
+    for (var z = 0; z < 2; z++) {
+        for (var y = 0; y < 2; y++) {
+            for (var x = 0; x < 2; x++) {
+                let o = float3(float(x), float(y), float(z));
+                let g = 2. * hash33(i + o) - 1.;
+                let w = mix(1. - u, u, o);
+                n += w.x * w.y * w.z * dot(g, f - o);
+            }
+        }
+    }
+    return n;
+}
+
+// Fractal Brownian motion of gradient noise
+fn fbm2(p: float2, octaves: int) -> float {
+    var q = p;
+    var a = .5;
+    var n = 0.;
+    for (var i = 0; i < octaves; i++) {
+        n += a * gradientNoise2(q);
+        q = 2. * q + 17.;
+        a *= .5;
+    }
+    return n;
+}
+
+fn fbm3(p: float3, octaves: int) -> float {
+    var q = p;
+    var a = .5;
+    var n = 0.;
+    for (var i = 0; i < octaves; i++) {
+        n += a * gradientNoise3(q);
+        q = 2. * q + 17.;
+        a *= .5;
+    }
+    return n;
+}
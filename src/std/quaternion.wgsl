@@ -0,0 +1,36 @@
+// Quaternions, stored as float4(x, y, z, w) with w the real part
+
+fn qMul(a: float4, b: float4) -> float4 {
+    return float4(a.w * b.xyz + b.w * a.xyz + cross(a.xyz, b.xyz), a.w * b.w - dot(a.xyz, b.xyz));
+}
+
+fn qConj(q: float4) -> float4 {
+    return float4(-q.xyz, q.w);
+}
+
+fn qAxisAngle(axis: float3, angle: float) -> float4 {
+    return float4(normalize(axis) * sin(.5 * angle), cos(.5 * angle));
+}
+
+fn qRotate(q: float4, v: float3) -> float3 {
+    let t = 2. * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+fn qToMat3(q: float4) -> float3x3 {
+    return float3x3(qRotate(q, float3(1., 0., 0.)), qRotate(q, float3(0., 1., 0.)), qRotate(q, float3(0., 0., 1.)));
+}
+
+fn qSlerp(a: float4, b: float4, t: float) -> float4 {
+    var c = dot(a, b);
+    var d = b;
+    if (c < 0.) {
+        c = -c;
+        d = -b;
+    }
+    if (c > .9995) {
+        return normalize(mix(a, d, t));
+    }
+    let theta = acos(c);
+    return (sin((1. - t) * theta) * a + sin(t * theta) * d) / sin(theta);
+}
@@ -0,0 +1,77 @@
+// Signed distance functions and operators
+// After Inigo Quilez, MIT License: https://iquilezles.org/articles/distfunctions/
+
+fn sdSphere(p: float3, r: float) -> float {
+    return length(p) - r;
+}
+
+fn sdBox(p: float3, b: float3) -> float {
+    let q = abs(p) - b;
+    return length(max(q, float3(0.))) + min(max(q.x, max(q.y, q.z)), 0.);
+}
+
+fn sdRoundBox(p: float3, b: float3, r: float) -> float {
+    return sdBox(p, b - r) - r;
+}
+
+fn sdTorus(p: float3, t: float2) -> float {
+    let q = float2(length(p.xz) - t.x, p.y);
+    return length(q) - t.y;
+}
+
+fn sdCapsule(p: float3, a: float3, b: float3, r: float) -> float {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0., 1.);
+    return length(pa - ba * h) - r;
+}
+
+fn sdCylinder(p: float3, h: float, r: float) -> float {
+    let d = abs(float2(length(p.xz), p.y)) - float2(r, h);
+    return min(max(d.x, d.y), 0.) + length(max(d, float2(0.)));
+}
+
+fn sdPlane(p: float3, n: float3, h: float) -> float {
+    return dot(p, n) + h;
+}
+
+fn sdCircle(p: float2, r: float) -> float {
+    return length(p) - r;
+}
+
+fn sdBox2(p: float2, b: float2) -> float {
+    let d = abs(p) - b;
+    return length(max(d, float2(0.))) + min(max(d.x, d.y), 0.);
+}
+
+fn opUnion(d1: float, d2: float) -> float {
+    return min(d1, d2);
+}
+
+fn opSubtraction(d1: float, d2: float) -> float {
+    return max(-d1, d2);
+}
+
+fn opIntersection(d1: float, d2: float) -> float {
+    return max(d1, d2);
+}
+
+fn opSmoothUnion(d1: float, d2: float, k: float) -> float {
+    let h = clamp(.5 + .5 * (d2 - d1) / k, 0., 1.);
+    return mix(d2, d1, h) - k * h * (1. - h);
+}
+
+fn opSmoothSubtraction(d1: float, d2: float, k: float) -> float {
+    let h = clamp(.5 - .5 * (d2 + d1) / k, 0., 1.);
+    return mix(d2, -d1, h) + k * h * (1. - h);
+}
+
+fn opSmoothIntersection(d1: float, d2: float, k: float) -> float {
+    let h = clamp(.5 - .5 * (d2 - d1) / k, 0., 1.);
+    return mix(d2, d1, h) + k * h * (1. - h);
+}
+
+// Repeat space with the given period, returning the position within the cell
+fn opRepeat(p: float3, period: float3) -> float3 {
+    return p - period * round(p / period);
+}
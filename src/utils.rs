@@ -1,8 +1,9 @@
 use crate::WGSLError;
 
 #[cfg(target_arch = "wasm32")]
-use {cached::proc_macro::cached, std::future::Future, wasm_bindgen::prelude::*};
+use cached::proc_macro::cached;
 
+#[cfg(target_arch = "wasm32")]
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -27,10 +28,25 @@ pub fn parse_u32(value: &str, line: usize) -> Result<u32, WGSLError> {
     )))
 }
 
+/// Modules of the standard library bundled with the crate, available without network access.
+pub fn bundled_include(name: &str) -> Option<&'static str> {
+    match name {
+        "std/noise" => Some(include_str!("std/noise.wgsl")),
+        "std/sdf" => Some(include_str!("std/sdf.wgsl")),
+        "std/colour" => Some(include_str!("std/colour.wgsl")),
+        "std/quaternion" => Some(include_str!("std/quaternion.wgsl")),
+        _ => None,
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 #[cached]
 pub async fn fetch_include(name: String) -> Option<String> {
-    let url = format!("https://compute-toys.github.io/include/{name}.wgsl");
+    let url = if name.starts_with("https://") || name.starts_with("http://") {
+        name
+    } else {
+        format!("https://compute-toys.github.io/include/{name}.wgsl")
+    };
 
     #[cfg(target_arch = "wasm32")]
     let resp = gloo_net::http::Request::get(&url).send().await.ok()?;
@@ -46,28 +62,158 @@ pub async fn fetch_include(name: String) -> Option<String> {
 
 #[cfg(not(target_arch = "wasm32"))]
 pub async fn fetch_include(name: String) -> Option<String> {
+    if name.starts_with("https://") || name.starts_with("http://") {
+        let bytes = fetch_bytes(name).await.ok()?;
+        return String::from_utf8(bytes).ok();
+    }
     let filename = format!("./include/{name}.wgsl");
     std::fs::read_to_string(filename).ok()
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn promise<F, T>(future: F) -> js_sys::Promise
-where
-    F: Future<Output = Option<T>> + 'static,
-    JsValue: From<T>,
-{
-    let mut future = Some(future);
-
-    js_sys::Promise::new(&mut |resolve, _reject| {
-        let future = future.take().unwrap_throw();
-
-        wasm_bindgen_futures::spawn_local(async move {
-            let val = if let Some(val) = future.await {
-                JsValue::from(val)
-            } else {
-                JsValue::undefined()
-            };
-            resolve.call1(&JsValue::undefined(), &val).unwrap_throw();
-        });
-    })
+#[cached(size = 16, result = true)]
+pub async fn fetch_bytes(url: String) -> Result<Vec<u8>, String> {
+    let resp = gloo_net::http::Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if resp.status() != 200 {
+        return Err(format!("{url}: HTTP {}", resp.status()));
+    }
+    resp.binary().await.map_err(|e| e.to_string())
+}
+
+/// The directory set by `set_cache_dir`
+#[cfg(all(not(target_arch = "wasm32"), feature = "fetch"))]
+static CACHE_DIR: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+
+/// Cache textures fetched from URLs in this directory, unless `WGPUTOY_CACHE_DIR` is set. Only
+/// the first call takes effect, and only if made before anything has been fetched.
+#[cfg(all(not(target_arch = "wasm32"), feature = "fetch"))]
+pub fn set_cache_dir(dir: std::path::PathBuf) {
+    let _ = CACHE_DIR.set(dir);
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "fetch"))]
+lazy_static::lazy_static! {
+    static ref HTTP_CLIENT: reqwest_middleware::ClientWithMiddleware = {
+        use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, HttpCacheOptions};
+        reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: match std::env::var_os("WGPUTOY_CACHE_DIR")
+                    .map(Into::into)
+                    .or_else(|| CACHE_DIR.get().cloned())
+                {
+                    Some(path) => CACacheManager { path },
+                    None => CACacheManager::default(),
+                },
+                options: HttpCacheOptions::default(),
+            }))
+            .build()
+    };
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "fetch"))]
+pub async fn fetch_bytes(url: String) -> Result<Vec<u8>, String> {
+    let resp = HTTP_CLIENT
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("{url}: HTTP {}", resp.status()));
+    }
+    Ok(resp.bytes().await.map_err(|e| e.to_string())?.to_vec())
+}
+
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "fetch")))]
+pub async fn fetch_bytes(url: String) -> Result<Vec<u8>, String> {
+    Err(format!("{url}: built without the fetch feature"))
+}
+
+/// Encode RGBA values, row by row, losslessly as an OpenEXR image of 32-bit floats.
+pub fn encode_exr(width: usize, rgba: &[f32]) -> Result<Vec<u8>, String> {
+    let height = rgba.len() / 4 / width;
+    let image = image::Rgba32FImage::from_raw(width as u32, height as u32, rgba.to_vec())
+        .ok_or("Image has the wrong size")?;
+    let mut exr = std::io::Cursor::new(vec![]);
+    image
+        .write_to(&mut exr, image::ImageOutputFormat::OpenExr)
+        .map_err(|e| e.to_string())?;
+    Ok(exr.into_inner())
+}
+
+/// Encode frames of RGBA pixels as a looping GIF, ignoring alpha, showing each frame for
+/// `delay` hundredths of a second.
+pub fn encode_gif(
+    width: u32,
+    height: u32,
+    frames: Vec<Vec<u8>>,
+    delay: u32,
+) -> Result<Vec<u8>, String> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    let mut gif = vec![];
+    {
+        // a palette quantised for each frame, sampling every 10th pixel
+        let mut encoder = GifEncoder::new_with_speed(&mut gif, 10);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        for mut rgba in frames {
+            for texel in rgba.chunks_mut(4) {
+                texel[3] = u8::MAX;
+            }
+            let image = image::RgbaImage::from_raw(width, height, rgba)
+                .ok_or("GIF frame has the wrong size")?;
+            encoder
+                .encode_frame(image::Frame::from_parts(
+                    image,
+                    0,
+                    0,
+                    image::Delay::from_numer_denom_ms(delay * 10, 1),
+                ))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(gif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::AnimationDecoder;
+
+    #[test]
+    fn exr_round_trip() {
+        // values a Radiance image would lose, above 1, negative, tiny or with alpha
+        let rgba = [
+            1e6, -2.5, 1e-9, 0.5, 0.1, 0.2, 0.3, 0.25, 3.75, 0., 1., 0., 65504.5, 7., 0.125, 1.,
+            -0., 2., 4., 8., 0.3, 0.6, 0.9, 0.75,
+        ];
+        let exr = encode_exr(3, &rgba).unwrap();
+        let image = image::load_from_memory_with_format(&exr, image::ImageFormat::OpenExr)
+            .unwrap()
+            .into_rgba32f();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.into_raw(), rgba);
+    }
+
+    #[test]
+    fn gif_round_trip() {
+        let (width, height) = (7, 3);
+        let frames = [[255, 0, 0, 0], [0, 0, 255, 128]]
+            .iter()
+            .map(|texel| texel.repeat(width * height))
+            .collect();
+        let gif = encode_gif(width as u32, height as u32, frames, 4).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(gif)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        for (frame, colour) in frames.iter().zip([[255, 0, 0, 255], [0, 0, 255, 255]]) {
+            assert_eq!(frame.delay().numer_denom_ms(), (40, 1));
+            assert_eq!(frame.buffer().dimensions(), (7, 3));
+            assert!(frame.buffer().pixels().all(|p| p.0 == colour));
+        }
+    }
 }
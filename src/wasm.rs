@@ -0,0 +1,838 @@
+//! The JS API: the renderer and the types it takes and returns, wrapped for `wasm_bindgen`, so
+//! that the rest of the crate is plain Rust. Most methods forward to `WgpuToyRenderer`; those
+//! reading back from the GPU return promises, and callbacks are JS functions.
+
+mod record;
+mod types;
+
+#[cfg(feature = "winit")]
+use crate::context::init_wgpu;
+use crate::context::{self, init_wgpu_headless, AdapterOptions};
+use crate::preprocessor::{self, IncludeResolver};
+use crate::{utils, Diagnostic, WgpuToyRenderer};
+use std::cell::RefCell;
+use std::future::Future;
+use std::mem::take;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use types::*;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    fn wgsl_error_handler(diagnostic: JsDiagnostic);
+}
+
+/// Report an error to the page's `wgsl_error_handler`.
+pub(crate) fn report_error(diagnostic: Diagnostic) {
+    wgsl_error_handler(JsDiagnostic(diagnostic));
+}
+
+/// A promise resolving to the output of `future`, or `undefined` if it is `None`.
+fn promise<F, T>(future: F) -> js_sys::Promise
+where
+    F: Future<Output = Option<T>> + 'static,
+    JsValue: From<T>,
+{
+    let mut future = Some(future);
+
+    js_sys::Promise::new(&mut |resolve, _reject| {
+        let future = future.take().unwrap_throw();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let val = if let Some(val) = future.await {
+                JsValue::from(val)
+            } else {
+                JsValue::undefined()
+            };
+            resolve.call1(&JsValue::undefined(), &val).unwrap_throw();
+        });
+    })
+}
+
+/// Log the error of a method returning a promise, which resolves to `undefined` instead.
+fn log_error<T>(method: &str, result: Result<T, String>) -> Option<T> {
+    result.map_err(|e| log::error!("{method}: {e}")).ok()
+}
+
+/// Call a JS callback, logging what it throws.
+fn call(name: &str, result: Result<JsValue, JsValue>) {
+    if let Err(error) = result {
+        log::error!("Error calling registered {name} callback: {error:?}");
+    }
+}
+
+/// Channel images that have finished downloading, waiting to be uploaded at the start of the next frame.
+type PendingChannels = Rc<RefCell<Vec<(usize, String, Vec<u8>)>>>;
+
+/// A device requested to replace a lost one, filled in once it is ready.
+type DeviceRequest = Rc<RefCell<Option<Result<context::NewDevice, String>>>>;
+
+#[wasm_bindgen(js_name = WgpuToyRenderer)]
+pub struct JsRenderer {
+    renderer: WgpuToyRenderer,
+    pending_channels: PendingChannels,
+    device_request: Option<DeviceRequest>,
+    recording: Option<record::Recording>,
+}
+
+impl From<WgpuToyRenderer> for JsRenderer {
+    fn from(renderer: WgpuToyRenderer) -> Self {
+        JsRenderer {
+            renderer,
+            pending_channels: Default::default(),
+            device_request: None,
+            recording: None,
+        }
+    }
+}
+
+// FIXME: async fn(&str) doesn't currently work with wasm_bindgen: https://stackoverflow.com/a/63655324/78204
+#[cfg(feature = "winit")]
+/// Create a renderer drawing to the canvas with the given id. A `profile` other than
+/// `DeviceProfile.Full` requests fewer features and lower limits than the GPU supports, to
+/// check that shaders work on weaker devices.
+#[wasm_bindgen]
+pub async fn create_renderer(
+    width: u32,
+    height: u32,
+    bind_id: String,
+    profile: Option<JsDeviceProfile>,
+) -> Result<JsRenderer, String> {
+    let profile = profile.map_or(crate::DeviceProfile::Full, Into::into);
+    let wgpu = init_wgpu(width, height, &bind_id, profile, AdapterOptions::default()).await?;
+    Ok(WgpuToyRenderer::new(wgpu).into())
+}
+
+/// Create a renderer drawing to an offscreen texture rather than a canvas, read back with
+/// `read_output`.
+#[wasm_bindgen]
+pub async fn create_renderer_headless(
+    width: u32,
+    height: u32,
+    profile: Option<JsDeviceProfile>,
+) -> Result<JsRenderer, String> {
+    let profile = profile.map_or(crate::DeviceProfile::Full, Into::into);
+    let wgpu = init_wgpu_headless(width, height, profile, AdapterOptions::default()).await?;
+    Ok(WgpuToyRenderer::new(wgpu).into())
+}
+
+/// Translate a Shadertoy GLSL shader to WGSL, see `shadertoy_to_wgsl` in the Rust API.
+#[cfg(feature = "glsl")]
+#[wasm_bindgen(js_name = shadertoy_to_wgsl)]
+pub fn js_shadertoy_to_wgsl(glsl: &str) -> Option<String> {
+    crate::shadertoy_to_wgsl(glsl)
+}
+
+#[wasm_bindgen(js_class = WgpuToyRenderer)]
+impl JsRenderer {
+    pub fn render(&mut self) {
+        if !self.device_ready() {
+            return;
+        }
+        let pending = take(&mut *self.pending_channels.borrow_mut());
+        let r = &mut self.renderer;
+        for (index, url, bytes) in pending {
+            if let Err(e) = r.load_channel_bytes(index, &url, &bytes) {
+                log::error!("load_channel_url: {e}");
+            }
+        }
+
+        if let Some((view, frame)) = r.acquire_frame() {
+            let (staging_buffer, _) = r.render_to(&view);
+            if let Some(frame) = frame {
+                frame.present();
+            }
+            wasm_bindgen_futures::spawn_local(WgpuToyRenderer::postrender(
+                staging_buffer,
+                r.screen_width * r.screen_height,
+                r.source.assert_map.clone(),
+                r.on_debug_print_cb.clone(),
+            ));
+            if let Some(buf) = r.finished_staging.take() {
+                wasm_bindgen_futures::spawn_local(WgpuToyRenderer::check_finished(
+                    buf,
+                    r.finished.clone(),
+                ));
+            }
+            if let Some(buf) = r.reduction_staging.take() {
+                wasm_bindgen_futures::spawn_local(WgpuToyRenderer::read_reductions(
+                    buf,
+                    r.reduction_results.clone(),
+                ));
+            }
+            if let Some((buf, textures)) = r.nan_check_staging.take() {
+                wasm_bindgen_futures::spawn_local(WgpuToyRenderer::read_nan_check(
+                    buf,
+                    textures,
+                    r.nan_reported.clone(),
+                    r.on_nan_cb.clone(),
+                ));
+            }
+            if let Some((buf, names)) = r.timestamp_staging.take() {
+                wasm_bindgen_futures::spawn_local(WgpuToyRenderer::read_timestamps(
+                    buf,
+                    names,
+                    r.wgpu.queue.get_timestamp_period(),
+                    r.pass_timings.clone(),
+                ));
+            }
+        }
+    }
+
+    /// Whether the device can be rendered with. Once it is lost, a new one is requested, and
+    /// when it is ready every resource is recreated on it.
+    fn device_ready(&mut self) -> bool {
+        if !self.renderer.device_lost.load(Ordering::SeqCst) {
+            return true;
+        }
+        let Some(request) = &self.device_request else {
+            log::warn!("Requesting a new device");
+            let request = DeviceRequest::default();
+            let result = request.clone();
+            let new_device = self.renderer.wgpu.request_new_device();
+            wasm_bindgen_futures::spawn_local(async move {
+                *result.borrow_mut() = Some(new_device.await);
+            });
+            self.device_request = Some(request);
+            return false;
+        };
+        let Some(result) = request.borrow_mut().take() else {
+            return false;
+        };
+        self.device_request = None;
+        match result {
+            Ok(device) => {
+                self.renderer.restore_device(device);
+                true
+            }
+            Err(e) => {
+                log::error!("Unable to replace the lost device: {e}");
+                false
+            }
+        }
+    }
+
+    /// Preprocess a shader, resolving to a `SourceMap` to be given to `compile`, or to
+    /// `undefined` if it has errors, which are reported to `wgsl_error_handler`.
+    pub fn preprocess(&self, shader: &str) -> js_sys::Promise {
+        let shader = shader.to_owned();
+        let defines = self.renderer.defines();
+        let resolver = self.renderer.include_resolver.clone();
+        promise(async move {
+            preprocessor::Preprocessor::new(defines)
+                .with_include_resolver(resolver)
+                .run(&shader)
+                .await
+                .map(JsSourceMap)
+        })
+    }
+
+    /// Preprocess, parse and validate a shader without compiling it, e.g. to lint it as it is
+    /// edited, resolving to an array of the `Diagnostic`s of the errors found. Shaders which
+    /// enable extensions are only preprocessed, as naga can't parse them yet.
+    pub fn check_shader(&self, shader: &str) -> js_sys::Promise {
+        let check = self.renderer.check(shader);
+        promise(async move {
+            Some(
+                check
+                    .await
+                    .into_iter()
+                    .map(|diagnostic| JsValue::from(JsDiagnostic(diagnostic)))
+                    .collect::<js_sys::Array>(),
+            )
+        })
+    }
+
+    pub fn compile(&mut self, source: JsSourceMap) {
+        self.renderer.compile(source.0)
+    }
+
+    /// Resolve `#include "name"` directives with a JS function returning the source, or a promise of it.
+    /// Includes it doesn't resolve are fetched from the default location.
+    pub fn set_include_resolver(&mut self, resolver: js_sys::Function) {
+        self.renderer.include_resolver = Some(IncludeResolver(Rc::new(move |name| {
+            let value = resolver.call1(&JsValue::NULL, &JsValue::from_str(name));
+            Box::pin(async move {
+                let mut value = value.ok()?;
+                if value.is_instance_of::<js_sys::Promise>() {
+                    value = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::from(value))
+                        .await
+                        .ok()?;
+                }
+                value.as_string()
+            })
+        })));
+    }
+
+    pub fn set_custom_floats(&mut self, names: Vec<js_sys::JsString>, values: Vec<f32>) {
+        let names = names.iter().map(From::from).collect();
+        self.renderer.set_custom_floats(names, values)
+    }
+
+    /// Read `len` bytes of a storage buffer, starting at `offset`, as a `Uint8Array`.
+    /// The offset and length must be multiples of 4.
+    pub fn read_storage_buffer(&self, name: &str, offset: u64, len: u64) -> js_sys::Promise {
+        let staging = self.renderer.copy_storage_buffer(name, offset, len);
+        promise(async move {
+            let bytes = match staging {
+                Ok(buf) => WgpuToyRenderer::read_staging_buffer(buf).await,
+                Err(e) => Err(e),
+            };
+            log_error("read_storage_buffer", bytes)
+                .map(|bytes| js_sys::Uint8Array::from(&bytes[..]))
+        })
+    }
+
+    /// Capture the screen as a PNG in a `Uint8Array`, at the internal resolution, tonemapped
+    /// and post-processed as on the canvas. Alpha is kept if the output is transparent.
+    pub fn screenshot(&self) -> js_sys::Promise {
+        let r = &self.renderer;
+        let readback = r.copy_screenshot(r.screen_width, r.screen_height);
+        let opaque = r.wgpu.surface_config.alpha_mode == wgpu::CompositeAlphaMode::Opaque;
+        promise(async move {
+            log_error("screenshot", readback.read_png(opaque).await)
+                .map(|png| js_sys::Uint8Array::from(&png[..]))
+        })
+    }
+
+    /// Read the screen as a `Float32Array` of RGBA values, row by row from the top, before
+    /// tonemapping and post-processing, so values above 1 are kept for external tonemapping.
+    pub fn screenshot_hdr(&self) -> js_sys::Promise {
+        let r = &self.renderer;
+        let readback = r.copy_texture(r.bindings.tex_screen.texture(), 0);
+        promise(async move {
+            log_error("screenshot_hdr", readback.read().await)
+                .map(|values| js_sys::Float32Array::from(&values[..]))
+        })
+    }
+
+    /// Capture the screen like `screenshot_hdr`, encoded losslessly as an OpenEXR `.exr` image
+    /// in a `Uint8Array`.
+    pub fn screenshot_exr(&self) -> js_sys::Promise {
+        let r = &self.renderer;
+        let readback = r.copy_texture(r.bindings.tex_screen.texture(), 0);
+        promise(async move {
+            log_error("screenshot_exr", readback.read_exr().await)
+                .map(|exr| js_sys::Uint8Array::from(&exr[..]))
+        })
+    }
+
+    /// Start recording the canvas as a video at the given frame rate, with a `MediaRecorder`
+    /// of the given MIME type, e.g. `video/webm;codecs=vp9`, or the browser's default if empty.
+    /// The bitrate is chosen by the browser if zero.
+    pub fn start_recording(
+        &mut self,
+        fps: f64,
+        mime_type: &str,
+        bits_per_second: u32,
+    ) -> Result<(), String> {
+        if self.recording.is_some() {
+            return Err("Already recording".to_string());
+        }
+        let canvas = self
+            .renderer
+            .wgpu
+            .canvas
+            .as_ref()
+            .ok_or("Only renderers with a canvas can be recorded")?;
+        self.recording = Some(record::Recording::start(
+            canvas,
+            fps,
+            mime_type,
+            bits_per_second,
+        )?);
+        Ok(())
+    }
+
+    /// Stop recording, resolving to a `Blob` of the video.
+    pub fn stop_recording(&mut self) -> Result<js_sys::Promise, String> {
+        self.recording.take().ok_or("Not recording")?.stop()
+    }
+
+    /// Render the next `frames` frames at the given frame rate, without presenting them, and
+    /// encode them as a looping GIF of the given size in a `Uint8Array`. The time continues
+    /// from the end of the recording afterwards.
+    pub fn record_gif(
+        &mut self,
+        frames: u32,
+        fps: f32,
+        width: u32,
+        height: u32,
+    ) -> js_sys::Promise {
+        let gif = self.renderer.capture_gif(frames, fps, width, height);
+        promise(async move {
+            let bytes = match gif {
+                Ok(gif) => gif.await,
+                Err(e) => Err(e),
+            };
+            log_error("record_gif", bytes).map(|bytes| js_sys::Uint8Array::from(&bytes[..]))
+        })
+    }
+
+    /// Render frames without presenting them, at the time `frame * dt` of each frame, for
+    /// frame-perfect animations independent of the real frame rate. Call `reset` first to
+    /// start from frame 0. Calls `on_frame(frame, pixels)` with each frame's sRGB RGBA8
+    /// `Uint8Array` at the internal resolution, as tonemapped and post-processed for display.
+    /// All frames are rendered before they're read back, so long sequences should be split
+    /// across calls. Resolves to the number of frames rendered.
+    pub fn render_offline(
+        &mut self,
+        frame_count: u32,
+        dt: f32,
+        on_frame: js_sys::Function,
+    ) -> js_sys::Promise {
+        let r = &mut self.renderer;
+        let first = r.bindings.time.host.frame;
+        r.bindings.time.host.elapsed = first as f32 * dt;
+        let readbacks = r.capture_frames(frame_count, dt, r.screen_width, r.screen_height);
+        promise(async move {
+            let result = async {
+                for (frame, readback) in (first..).zip(readbacks?) {
+                    let pixels = js_sys::Uint8Array::from(&readback.read_bytes().await?[..]);
+                    on_frame
+                        .call2(&JsValue::NULL, &frame.into(), &pixels)
+                        .map_err(|e| format!("{e:?}"))?;
+                }
+                Ok::<_, String>(frame_count)
+            };
+            log_error("render_offline", result.await)
+        })
+    }
+
+    /// Read the last rendered frame of a headless renderer as a `Uint8Array`, row by row from
+    /// the top, in the output format: sRGB `rgba8unorm`, or `rgba16float` after `set_output_hdr`.
+    pub fn read_output(&self) -> js_sys::Promise {
+        let readback = self.renderer.copy_output();
+        promise(async move {
+            let bytes = match readback {
+                Ok(readback) => readback.read_bytes().await,
+                Err(e) => Err(e),
+            };
+            log_error("read_output", bytes).map(|bytes| js_sys::Uint8Array::from(&bytes[..]))
+        })
+    }
+
+    /// Read the latest output of a pass layer as a `Float32Array`, row by row from the top,
+    /// with as many components per texel as the layer's format.
+    pub fn read_pass_texture(&self, layer: u32) -> js_sys::Promise {
+        let readback = self.renderer.copy_pass_texture(layer);
+        promise(async move {
+            let values = match readback {
+                Ok(readback) => readback.read().await,
+                Err(e) => Err(e),
+            };
+            log_error("read_pass_texture", values)
+                .map(|values| js_sys::Float32Array::from(&values[..]))
+        })
+    }
+
+    /// Read the value of the screen at `(x, y)`, in pixels from the top left, before
+    /// tonemapping, followed by the value of each pass layer if `include_passes`. Resolves to
+    /// an array of `Float32Array`s, with as many components as each texture's format.
+    pub fn inspect_pixel(&self, x: u32, y: u32, include_passes: bool) -> js_sys::Promise {
+        let readbacks = self.renderer.copy_pixel(x, y, include_passes);
+        promise(async move {
+            let values = async {
+                let values = js_sys::Array::new();
+                for readback in readbacks? {
+                    values.push(&js_sys::Float32Array::from(&readback.read().await?[..]));
+                }
+                Ok::<_, String>(values)
+            };
+            log_error("inspect_pixel", values.await)
+        })
+    }
+
+    /// Read back every pass layer, the screen and each storage buffer as of the last frame,
+    /// resolving to a `FrameDump`, to inspect the intermediate results of multi-pass shaders.
+    pub fn dump_frame_debug(&self) -> js_sys::Promise {
+        let readback = self.renderer.copy_frame_dump();
+        promise(async move {
+            let dump = match readback {
+                Ok(readback) => readback.read().await,
+                Err(e) => Err(e),
+            };
+            log_error("dump_frame_debug", dump).map(JsFrameDump)
+        })
+    }
+
+    /// Capture the storage buffers and pass layers, to be reloaded with `restore_state`.
+    pub fn snapshot_state(&self) -> js_sys::Promise {
+        let readback = self.renderer.copy_state();
+        promise(async move {
+            let state = match readback {
+                Ok(readback) => readback.read().await,
+                Err(e) => Err(e),
+            };
+            log_error("snapshot_state", state).map(JsStateBlob)
+        })
+    }
+
+    pub fn restore_state(&mut self, state: &JsStateBlob) -> Result<(), String> {
+        self.renderer.restore_state(&state.0)
+    }
+
+    /// Register a callback taking the entry points of each shader that compiles.
+    pub fn on_success(&mut self, callback: js_sys::Function) {
+        self.renderer.on_success(move |entry_points| {
+            let entry_points = entry_points
+                .into_iter()
+                .map(JsValue::from)
+                .collect::<js_sys::Array>();
+            call("success", callback.call1(&JsValue::NULL, &entry_points));
+        });
+    }
+
+    /// Register a callback taking a `Diagnostic` for non-fatal problems found when compiling.
+    pub fn on_warning(&mut self, callback: js_sys::Function) {
+        self.renderer.on_warning(move |diagnostic| {
+            let diagnostic = JsValue::from(JsDiagnostic(diagnostic));
+            call("warning", callback.call1(&JsValue::NULL, &diagnostic));
+        });
+    }
+
+    /// Register a callback `(tag, message)` for the values recorded by the shader with
+    /// `debug_print_f32`, `debug_print_u32`, `debug_print_i32` and `debug_print_vec4f`. These
+    /// are read back every 100 frames, up to 1024 at a time, so prints should be limited to a
+    /// few threads. Without a callback they are logged.
+    pub fn on_debug_print(&mut self, callback: js_sys::Function) {
+        self.renderer.on_debug_print(move |tag, message| {
+            let (tag, message) = (JsValue::from(tag), JsValue::from_str(message));
+            call(
+                "debug print",
+                callback.call2(&JsValue::NULL, &tag, &message),
+            );
+        });
+    }
+
+    /// Register a callback `(texture, x, y)` for the first non-finite value found in the screen
+    /// (`"screen"`) or a pass layer (`"pass 0"` onwards) when `set_nan_check` is enabled.
+    pub fn on_nan(&mut self, callback: js_sys::Function) {
+        self.renderer.on_nan(move |texture, x, y| {
+            let texture = JsValue::from_str(texture);
+            let (x, y) = (JsValue::from(x), JsValue::from(y));
+            call("NaN", callback.call3(&JsValue::NULL, &texture, &x, &y));
+        });
+    }
+
+    /// Register a callback for when the device has been replaced after being lost, e.g. by a
+    /// driver reset, and the shader recompiled on the new one. Pass textures and storage
+    /// buffers start from zero again, as on `reset`.
+    pub fn on_device_restored(&mut self, callback: js_sys::Function) {
+        self.renderer.on_device_restored(move || {
+            call("device restored", callback.call0(&JsValue::NULL));
+        });
+    }
+
+    /// Download an image and load it into a channel once the download completes.
+    /// URLs ending in `.hdr` are decoded as Radiance HDR images.
+    pub fn load_channel_url(&self, index: usize, url: String) -> js_sys::Promise {
+        let pending = self.pending_channels.clone();
+        promise(async move {
+            let bytes = log_error("load_channel_url", utils::fetch_bytes(url.clone()).await)?;
+            pending.borrow_mut().push((index, url, bytes));
+            Some(true)
+        })
+    }
+
+    pub fn get_frame_stats(&self) -> JsFrameStats {
+        JsFrameStats(self.renderer.get_frame_stats())
+    }
+
+    pub fn get_pass_timings(&self) -> JsPassTimings {
+        JsPassTimings(self.renderer.get_pass_timings())
+    }
+
+    pub fn set_profile_every_frame(&mut self, enabled: bool) {
+        self.renderer.set_profile_every_frame(enabled)
+    }
+
+    pub fn get_compile_stats(&self) -> JsCompileStats {
+        JsCompileStats(self.renderer.get_compile_stats())
+    }
+
+    pub fn get_memory_usage(&self) -> JsMemoryUsage {
+        JsMemoryUsage(self.renderer.get_memory_usage())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.renderer.is_finished()
+    }
+
+    pub fn request_reduction(
+        &mut self,
+        op: JsReduceOp,
+        pass_index: Option<u32>,
+    ) -> Result<usize, String> {
+        self.renderer.request_reduction(op.into(), pass_index)
+    }
+
+    pub fn reduction_result(&self, id: usize) -> Option<Vec<f32>> {
+        self.renderer.reduction_result(id)
+    }
+
+    pub fn clear_reductions(&mut self) {
+        self.renderer.clear_reductions()
+    }
+
+    pub fn set_nan_check(&mut self, enabled: bool) {
+        self.renderer.set_nan_check(enabled)
+    }
+
+    pub fn set_time_elapsed(&mut self, t: f32) {
+        self.renderer.set_time_elapsed(t)
+    }
+
+    pub fn set_time_delta(&mut self, t: f32) {
+        self.renderer.set_time_delta(t)
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.renderer.frame()
+    }
+
+    pub fn set_frame(&mut self, frame: u32) {
+        self.renderer.set_frame(frame)
+    }
+
+    pub fn seek(&mut self, frame: u32, dt: f32) -> Result<(), String> {
+        self.renderer.seek(frame, dt)
+    }
+
+    pub fn set_mouse_pos(&mut self, x: f32, y: f32) {
+        self.renderer.set_mouse_pos(x, y)
+    }
+
+    pub fn set_mouse_pos_logical(&mut self, x: f32, y: f32) {
+        self.renderer.set_mouse_pos_logical(x, y)
+    }
+
+    pub fn set_mouse_click(&mut self, click: bool) {
+        self.renderer.set_mouse_click(click)
+    }
+
+    pub fn set_keydown(&mut self, keycode: usize, keydown: bool) {
+        self.renderer.set_keydown(keycode, keydown)
+    }
+
+    pub fn pause(&mut self) {
+        self.renderer.pause()
+    }
+
+    pub fn resume(&mut self) {
+        self.renderer.resume()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.renderer.is_paused()
+    }
+
+    pub fn step_frame(&mut self) {
+        self.renderer.step_frame()
+    }
+
+    pub fn reset(&mut self) {
+        self.renderer.reset()
+    }
+
+    pub fn clear_state(&mut self) {
+        self.renderer.clear_state()
+    }
+
+    pub fn reflection_json(&self) -> String {
+        self.renderer.reflection_json()
+    }
+
+    pub fn prelude_json(&self) -> String {
+        self.renderer.prelude_json()
+    }
+
+    pub fn prelude(&self) -> String {
+        self.renderer.prelude()
+    }
+
+    pub fn supports_f16(&self) -> bool {
+        self.renderer.supports_f16()
+    }
+
+    pub fn get_device_info(&self) -> String {
+        self.renderer.get_device_info()
+    }
+
+    pub fn set_define(&mut self, name: &str, value: &str) {
+        self.renderer.set_define(name, value)
+    }
+
+    pub fn remove_define(&mut self, name: &str) {
+        self.renderer.remove_define(name)
+    }
+
+    #[cfg(feature = "spirv")]
+    pub fn set_shader_spirv(&mut self, spirv: &[u8]) {
+        self.renderer.set_shader_spirv(spirv)
+    }
+
+    pub fn get_composed_wgsl(&self) -> String {
+        self.renderer.get_composed_wgsl()
+    }
+
+    #[cfg(feature = "export")]
+    pub fn export_shader(&self, target: &str) -> Result<Vec<u8>, String> {
+        self.renderer.export_shader(target)
+    }
+
+    pub fn pass_name(&self, entry_point: &str) -> Option<String> {
+        self.renderer.pass_name(entry_point)
+    }
+
+    pub fn set_entry_point_enabled(&mut self, entry_point: &str, enabled: bool) {
+        self.renderer.set_entry_point_enabled(entry_point, enabled)
+    }
+
+    pub fn set_dispatch_size(
+        &mut self,
+        entry_point: &str,
+        x: u32,
+        y: u32,
+        z: u32,
+    ) -> Result<(), String> {
+        self.renderer.set_dispatch_size(entry_point, x, y, z)
+    }
+
+    pub fn set_override(&mut self, name: &str, value: f64) -> Result<(), String> {
+        self.renderer.set_override(name, value)
+    }
+
+    pub fn remove_override(&mut self, name: &str) {
+        self.renderer.remove_override(name)
+    }
+
+    pub fn set_pass_count(&mut self, pass_count: u32) -> Result<(), String> {
+        self.renderer.set_pass_count(pass_count)
+    }
+
+    pub fn add_storage_buffer(&mut self, name: &str, bytes: u64) -> Result<(), String> {
+        self.renderer.add_storage_buffer(name, bytes)
+    }
+
+    pub fn remove_storage_buffer(&mut self, name: &str) {
+        self.renderer.remove_storage_buffer(name)
+    }
+
+    pub fn set_storage_buffer_size(&mut self, bytes: u64) -> Result<(), String> {
+        self.renderer.set_storage_buffer_size(bytes)
+    }
+
+    pub fn set_pass_f32(&mut self, pass_f32: bool) {
+        self.renderer.set_pass_f32(pass_f32)
+    }
+
+    pub fn write_storage_buffer(&self, name: &str, offset: u64, data: &[u8]) -> Result<(), String> {
+        self.renderer.write_storage_buffer(name, offset, data)
+    }
+
+    pub fn set_post_process(&mut self, snippet: &str) -> Result<(), String> {
+        self.renderer.set_post_process(snippet)
+    }
+
+    pub fn clear_post_process(&mut self) {
+        self.renderer.clear_post_process()
+    }
+
+    pub fn set_output_gamut(&mut self, gamut: JsGamut) {
+        self.renderer.set_output_gamut(gamut.into())
+    }
+
+    pub fn set_tonemap(&mut self, mode: JsTonemap, exposure: f32) {
+        self.renderer.set_tonemap(mode.into(), exposure)
+    }
+
+    pub fn set_dither(&mut self, dither: bool) {
+        self.renderer.set_dither(dither)
+    }
+
+    pub fn set_output_hdr(&mut self, hdr: bool) -> Result<(), String> {
+        self.renderer.set_output_hdr(hdr)
+    }
+
+    pub fn set_present_mode(&mut self, mode: JsPresentMode) -> Result<(), String> {
+        self.renderer.set_present_mode(mode.into())
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32, scale: f32) {
+        self.renderer.resize(width, height, scale)
+    }
+
+    pub fn resize_physical(&mut self, width: u32, height: u32, scale: f32) {
+        self.renderer.resize_physical(width, height, scale)
+    }
+
+    pub fn pixel_ratio(&self) -> f32 {
+        self.renderer.pixel_ratio()
+    }
+
+    pub fn set_max_resolution(&mut self, width: u32, height: u32) {
+        self.renderer.set_max_resolution(width, height)
+    }
+
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<(), String> {
+        self.renderer.set_render_scale(scale)
+    }
+
+    pub fn set_supersample(&mut self, supersample: bool) {
+        self.renderer.set_supersample(supersample)
+    }
+
+    pub fn set_transparent(&mut self, transparent: bool) -> Result<(), String> {
+        self.renderer.set_transparent(transparent)
+    }
+
+    pub fn set_background_color(&mut self, r: f64, g: f64, b: f64, a: f64) {
+        self.renderer.set_background_color(r, g, b, a)
+    }
+
+    pub fn set_aspect_ratio(&mut self, ratio: f32) -> Result<(), String> {
+        self.renderer.set_aspect_ratio(ratio)
+    }
+
+    pub fn destroy(&mut self) {
+        self.renderer.destroy()
+    }
+
+    pub fn channel_info(&self, index: usize) -> Option<JsChannelInfo> {
+        self.renderer.channel_info(index).map(JsChannelInfo)
+    }
+
+    pub fn load_channel(&mut self, index: usize, bytes: &[u8]) {
+        self.renderer.load_channel(index, bytes)
+    }
+
+    pub fn load_channel_hdr(&mut self, index: usize, bytes: &[u8]) -> Result<(), String> {
+        self.renderer.load_channel_hdr(index, bytes)
+    }
+
+    pub fn set_channel_audio(
+        &mut self,
+        index: usize,
+        spectrum: &[f32],
+        waveform: &[f32],
+    ) -> Result<(), String> {
+        self.renderer.set_channel_audio(index, spectrum, waveform)
+    }
+
+    pub fn clear_channel(&mut self, index: usize) -> Result<(), String> {
+        self.renderer.clear_channel(index)
+    }
+
+    pub fn create_channel_storage(
+        &mut self,
+        index: usize,
+        width: u32,
+        height: u32,
+        format: &str,
+    ) -> Result<(), String> {
+        self.renderer
+            .create_channel_storage(index, width, height, format)
+    }
+}
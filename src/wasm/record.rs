@@ -0,0 +1,86 @@
+//! Recording the canvas as a WebM (or other browser supported) video with a `MediaRecorder`.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+pub struct Recording {
+    recorder: web_sys::MediaRecorder,
+    chunks: js_sys::Array,
+    mime_type: String,
+    ondataavailable: Closure<dyn FnMut(web_sys::BlobEvent)>,
+}
+
+impl Recording {
+    /// Start recording the canvas with a `MediaRecorder` of the given MIME type, e.g.
+    /// `video/webm;codecs=vp9`, or the browser's default if empty.
+    pub fn start(
+        canvas: &web_sys::HtmlCanvasElement,
+        fps: f64,
+        mime_type: &str,
+        bits_per_second: u32,
+    ) -> Result<Self, String> {
+        let error = |e: JsValue| format!("{e:?}");
+        let stream = canvas
+            .capture_stream_with_frame_request_rate(fps)
+            .map_err(error)?;
+        let mut options = web_sys::MediaRecorderOptions::new();
+        if !mime_type.is_empty() {
+            options.mime_type(mime_type);
+        }
+        if bits_per_second > 0 {
+            options.video_bits_per_second(bits_per_second);
+        }
+        let recorder = web_sys::MediaRecorder::new_with_media_stream_and_media_recorder_options(
+            &stream, &options,
+        )
+        .map_err(error)?;
+        let chunks = js_sys::Array::new();
+        let ondataavailable = {
+            let chunks = chunks.clone();
+            Closure::<dyn FnMut(web_sys::BlobEvent)>::new(move |event: web_sys::BlobEvent| {
+                if let Some(blob) = event.data() {
+                    chunks.push(&blob);
+                }
+            })
+        };
+        recorder.set_ondataavailable(Some(ondataavailable.as_ref().unchecked_ref()));
+        recorder.start().map_err(error)?;
+        Ok(Recording {
+            mime_type: recorder.mime_type(),
+            recorder,
+            chunks,
+            ondataavailable,
+        })
+    }
+
+    /// Stop recording, resolving to a `Blob` of the video once the recorder has flushed it.
+    pub fn stop(self) -> Result<js_sys::Promise, String> {
+        let Recording {
+            recorder,
+            chunks,
+            mime_type,
+            ondataavailable,
+        } = self;
+        let mut ondataavailable = Some(ondataavailable);
+        let promise = js_sys::Promise::new(&mut |resolve, reject| {
+            let chunks = chunks.clone();
+            let mime_type = mime_type.clone();
+            let ondataavailable = ondataavailable.take();
+            let onstop = Closure::once_into_js(move || {
+                // the last chunk is delivered just before stopping, so keep its handler until now
+                drop(ondataavailable);
+                let mut properties = web_sys::BlobPropertyBag::new();
+                properties.type_(&mime_type);
+                let result =
+                    web_sys::Blob::new_with_blob_sequence_and_options(&chunks, &properties)
+                        .map(|blob| resolve.call1(&JsValue::NULL, &blob));
+                if let Err(e) = result {
+                    let _ = reject.call1(&JsValue::NULL, &e);
+                }
+            });
+            recorder.set_onstop(Some(onstop.unchecked_ref()));
+        });
+        recorder.stop().map_err(|e| format!("{e:?}"))?;
+        Ok(promise)
+    }
+}
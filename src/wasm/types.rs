@@ -0,0 +1,461 @@
+//! The types the renderer takes and returns, as seen from JS. Each wraps or mirrors the Rust
+//! type of the same name.
+
+use crate::{
+    ChannelInfo, CompileStats, DeviceProfile, Diagnostic, FrameDump, FrameStats, Gamut,
+    MemoryUsage, PassGraph, PassLocation, PassTimings, PresentMode, ReduceOp, Severity, SourceMap,
+    StateBlob, Tonemap,
+};
+use wasm_bindgen::prelude::*;
+
+/// Defines a JS enum with the same variants as a Rust one, converting into it.
+macro_rules! js_enum {
+    ($(#[$attr:meta])* $js:ident => $rust:ident { $($(#[$variant_attr:meta])* $variant:ident,)* }) => {
+        $(#[$attr])*
+        #[wasm_bindgen(js_name = $rust)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum $js {
+            $($(#[$variant_attr])* $variant,)*
+        }
+
+        impl From<$js> for $rust {
+            fn from(value: $js) -> Self {
+                match value {
+                    $($js::$variant => $rust::$variant,)*
+                }
+            }
+        }
+
+        impl From<$rust> for $js {
+            fn from(value: $rust) -> Self {
+                match value {
+                    $($rust::$variant => $js::$variant,)*
+                }
+            }
+        }
+    };
+}
+
+js_enum! {
+    /// How the final blit maps the screen's linear colours to the displayable range.
+    JsTonemap => Tonemap {
+        /// Clip values above 1
+        None,
+        Reinhard,
+        Aces,
+        AgX,
+    }
+}
+
+js_enum! {
+    /// The primaries of the colours the surface is displayed with. Shaders work in linear sRGB.
+    JsGamut => Gamut {
+        Srgb,
+        DisplayP3,
+    }
+}
+
+js_enum! {
+    /// How frames are presented.
+    JsPresentMode => PresentMode {
+        /// Wait for vertical blank, the default
+        Fifo,
+        /// Replace the queued frame without tearing, dropping frames if rendering is faster
+        Mailbox,
+        /// Present immediately, which may tear
+        Immediate,
+    }
+}
+
+js_enum! {
+    /// The features and limits requested of the adapter, to check that shaders work on weaker
+    /// devices than the one at hand.
+    JsDeviceProfile => DeviceProfile {
+        /// Every feature the adapter supports, with the limits every WebGPU implementation
+        /// supports, the default
+        Full,
+        /// No optional features, e.g. `f16` or filtering float32 textures
+        Baseline,
+        /// No optional features, and the lower limits of downlevel devices such as those
+        /// only supporting GLES 3.1 or D3D11, e.g. 2048x2048 textures
+        Downlevel,
+    }
+}
+
+js_enum! {
+    JsReduceOp => ReduceOp {
+        Min,
+        Max,
+        Sum,
+        Average,
+    }
+}
+
+js_enum! {
+    JsSeverity => Severity {
+        /// The shader can't run
+        Error,
+        /// The shader runs, but probably not as intended
+        Warning,
+    }
+}
+
+/// A problem with a shader, located in the source the user wrote. Lines and columns are
+/// 1-based, where 0 means the location isn't known. Problems in an include are located at the
+/// `#include` directive, with `file`, `file_line` and `file_column` giving where in the include.
+#[wasm_bindgen(js_name = Diagnostic)]
+pub struct JsDiagnostic(pub(crate) Diagnostic);
+
+#[wasm_bindgen(js_class = Diagnostic)]
+impl JsDiagnostic {
+    #[wasm_bindgen(getter)]
+    pub fn severity(&self) -> JsSeverity {
+        self.0.severity().into()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.0.message()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.0.line()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn column(&self) -> usize {
+        self.0.column()
+    }
+
+    /// The line the problem ends on, the same as `line` if only its start is known.
+    #[wasm_bindgen(getter)]
+    pub fn end_line(&self) -> usize {
+        self.0.end_line()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn end_column(&self) -> usize {
+        self.0.end_column()
+    }
+
+    /// The include the problem is in, `"prelude"` if it is in the prelude, or `undefined` if
+    /// it is in the shader itself.
+    #[wasm_bindgen(getter)]
+    pub fn file(&self) -> Option<String> {
+        self.0.file()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn file_line(&self) -> usize {
+        self.0.file_line()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn file_column(&self) -> usize {
+        self.0.file_column()
+    }
+
+    /// How the problem might be fixed, if known.
+    #[wasm_bindgen(getter)]
+    pub fn fix(&self) -> Option<String> {
+        self.0.fix()
+    }
+
+    /// The message along with where the problem is in an include, if it is in one.
+    pub fn summary(&self) -> String {
+        self.0.summary()
+    }
+}
+
+/// A preprocessed shader, from `preprocess`, to be given to `compile`.
+#[wasm_bindgen(js_name = SourceMap)]
+pub struct JsSourceMap(pub(crate) SourceMap);
+
+#[wasm_bindgen(js_name = ChannelInfo)]
+pub struct JsChannelInfo(pub(crate) ChannelInfo);
+
+#[wasm_bindgen(js_class = ChannelInfo)]
+impl JsChannelInfo {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.0.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.0.height
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> String {
+        self.0.format.clone()
+    }
+}
+
+/// Timings of the recent frames rendered, averaged over the last 60, in milliseconds.
+#[wasm_bindgen(js_name = FrameStats)]
+pub struct JsFrameStats(pub(crate) FrameStats);
+
+#[wasm_bindgen(js_class = FrameStats)]
+impl JsFrameStats {
+    /// Time spent on the CPU recording each frame's commands
+    #[wasm_bindgen(getter)]
+    pub fn encode_ms(&self) -> f32 {
+        self.0.encode_ms
+    }
+
+    /// Time spent submitting each frame's commands to the queue
+    #[wasm_bindgen(getter)]
+    pub fn submit_ms(&self) -> f32 {
+        self.0.submit_ms
+    }
+
+    /// Time between the starts of consecutive frames
+    #[wasm_bindgen(getter)]
+    pub fn interval_ms(&self) -> f32 {
+        self.0.interval_ms
+    }
+
+    /// The longest time between consecutive frames
+    #[wasm_bindgen(getter)]
+    pub fn max_interval_ms(&self) -> f32 {
+        self.0.max_interval_ms
+    }
+
+    /// Frames per second, from the mean interval
+    #[wasm_bindgen(getter)]
+    pub fn fps(&self) -> f32 {
+        self.0.fps
+    }
+}
+
+/// Where the time went in the last shader compilation, in milliseconds.
+#[wasm_bindgen(js_name = CompileStats)]
+pub struct JsCompileStats(pub(crate) CompileStats);
+
+#[wasm_bindgen(js_class = CompileStats)]
+impl JsCompileStats {
+    /// Preprocessing, including fetching includes and textures
+    #[wasm_bindgen(getter)]
+    pub fn preprocess_ms(&self) -> f32 {
+        self.0.preprocess_ms
+    }
+
+    /// Parsing and validating the shader with naga, skipped for shaders using extensions
+    #[wasm_bindgen(getter)]
+    pub fn parse_ms(&self) -> f32 {
+        self.0.parse_ms
+    }
+
+    /// Creating the shader module and the pipeline of each entry point, which browsers may
+    /// partly defer until the first dispatch
+    #[wasm_bindgen(getter)]
+    pub fn pipeline_ms(&self) -> f32 {
+        self.0.pipeline_ms
+    }
+
+    /// The sum of the above along with everything else done by `compile`
+    #[wasm_bindgen(getter)]
+    pub fn total_ms(&self) -> f32 {
+        self.0.total_ms
+    }
+}
+
+/// The GPU memory allocated by the renderer, in bytes. Drivers may pad or compress
+/// allocations, so the memory actually used may differ.
+#[wasm_bindgen(js_name = MemoryUsage)]
+pub struct JsMemoryUsage(pub(crate) MemoryUsage);
+
+#[wasm_bindgen(js_class = MemoryUsage)]
+impl JsMemoryUsage {
+    /// The `pass_in`/`pass_out` textures and the layers declared with `#pass_format`
+    #[wasm_bindgen(getter)]
+    pub fn passes(&self) -> f64 {
+        self.0.passes
+    }
+
+    /// The screen, its mipmaps and the output texture when headless
+    #[wasm_bindgen(getter)]
+    pub fn screen(&self) -> f64 {
+        self.0.screen
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn channels(&self) -> f64 {
+        self.0.channels
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn storage(&self) -> f64 {
+        self.0.storage
+    }
+
+    /// Uniforms, debug and indirect dispatch buffers, and the buffers of reductions
+    #[wasm_bindgen(getter)]
+    pub fn other(&self) -> f64 {
+        self.0.other
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn total(&self) -> f64 {
+        self.0.total
+    }
+}
+
+/// The GPU time taken by each entry point in a recent frame, in milliseconds, measured with
+/// timestamp queries. Empty if the device doesn't support them.
+#[wasm_bindgen(js_name = PassTimings)]
+pub struct JsPassTimings(pub(crate) PassTimings);
+
+#[wasm_bindgen(js_class = PassTimings)]
+impl JsPassTimings {
+    /// The `#pass_name` of each entry point dispatched, or its name, in the order dispatched.
+    #[wasm_bindgen(getter)]
+    pub fn names(&self) -> Vec<String> {
+        self.0.names()
+    }
+
+    /// The time from the start of each entry point's first dispatch to the end of its last.
+    #[wasm_bindgen(getter)]
+    pub fn ms(&self) -> Vec<f32> {
+        self.0.ms()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn total_ms(&self) -> f32 {
+        self.0.total_ms()
+    }
+}
+
+/// The contents of a shader's storage buffers and pass layers at a given frame, captured by
+/// `snapshot_state` and reloaded by `restore_state` to resume a simulation.
+#[wasm_bindgen(js_name = StateBlob)]
+pub struct JsStateBlob(pub(crate) StateBlob);
+
+#[wasm_bindgen(js_class = StateBlob)]
+impl JsStateBlob {
+    /// The frame the state was captured after.
+    #[wasm_bindgen(getter)]
+    pub fn frame(&self) -> u32 {
+        self.0.frame()
+    }
+
+    /// Serialise the state, e.g. to save it to a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    /// Deserialise a state written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<JsStateBlob, String> {
+        StateBlob::from_bytes(bytes).map(JsStateBlob)
+    }
+}
+
+/// Every pass layer, the screen and each storage buffer as of one frame, from
+/// `dump_frame_debug`. Textures are RGBA values, row by row from the top.
+#[wasm_bindgen(js_name = FrameDump)]
+pub struct JsFrameDump(pub(crate) FrameDump);
+
+#[wasm_bindgen(js_class = FrameDump)]
+impl JsFrameDump {
+    /// The number of frames rendered before the dump.
+    #[wasm_bindgen(getter)]
+    pub fn frame(&self) -> u32 {
+        self.0.frame()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.0.height()
+    }
+
+    /// The screen before tonemapping and post-processing.
+    pub fn screen(&self) -> Vec<f32> {
+        self.0.screen()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pass_count(&self) -> u32 {
+        self.0.pass_count()
+    }
+
+    pub fn pass(&self, layer: u32) -> Option<Vec<f32>> {
+        self.0.pass(layer)
+    }
+
+    pub fn storage_names(&self) -> Vec<String> {
+        self.0.storage_names()
+    }
+
+    pub fn storage(&self, name: &str) -> Option<Vec<u8>> {
+        self.0.storage(name)
+    }
+}
+
+/// A project made of several named passes, in the style of Shadertoy's Buffer A–D tabs. See
+/// `PassGraph` in the Rust API.
+#[wasm_bindgen(js_name = PassGraph)]
+#[derive(Default)]
+pub struct JsPassGraph(PassGraph);
+
+#[wasm_bindgen(js_class = PassGraph)]
+impl JsPassGraph {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Code shared by all passes, like Shadertoy's Common tab.
+    pub fn set_common(&mut self, source: &str) {
+        self.0.set_common(source)
+    }
+
+    /// Add a pass, or replace the source of an existing one.
+    pub fn add_pass(&mut self, name: &str, source: &str) -> Result<(), String> {
+        self.0.add_pass(name, source)
+    }
+
+    pub fn remove_pass(&mut self, name: &str) {
+        self.0.remove_pass(name)
+    }
+
+    /// The `pass_in`/`pass_out` layer written by a pass.
+    pub fn pass_index(&self, name: &str) -> Option<u32> {
+        self.0.pass_index(name)
+    }
+
+    /// Combine the passes into a single shader, to be given to `preprocess`, prefixing the
+    /// declarations of each pass with its name.
+    pub fn compose(&self) -> String {
+        self.0.compose()
+    }
+
+    /// The pass (or `common`) and line within it that a line of the composed shader came from.
+    pub fn locate(&self, line: usize) -> Option<JsPassLocation> {
+        self.0.locate(line).map(JsPassLocation)
+    }
+}
+
+/// Where a line of a composed `PassGraph` was written.
+#[wasm_bindgen(js_name = PassLocation)]
+pub struct JsPassLocation(PassLocation);
+
+#[wasm_bindgen(js_class = PassLocation)]
+impl JsPassLocation {
+    /// The name of the pass, or `common`
+    #[wasm_bindgen(getter)]
+    pub fn pass(&self) -> String {
+        self.0.pass.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.0.line
+    }
+}